@@ -41,7 +41,7 @@ pub fn ExpLogin(cx: Scope) -> impl IntoView {
 
                 let (k, r1, r2) = protocol.commit();
                 let request = Request::new(pb2::AuthenticationChallengeRequest {
-                    user: username,
+                    user: username.clone(),
                     r1: r1.to_bytes_be(),
                     r2: r2.to_bytes_be(),
                 });
@@ -50,9 +50,10 @@ pub fn ExpLogin(cx: Scope) -> impl IntoView {
                         let response = response.into_inner();
                         log::info!("RESPONSE={:?}", response);
                         let auth_id = response.auth_id;
+                        let realm = response.realm;
                         let c = BigUint::from_bytes_be(&response.c);
 
-                        let s = protocol.solve(&password, &k, &c);
+                        let s = protocol.solve(&realm, &username, &password, &k, &c);
                         let request = Request::new(pb2::AuthenticationAnswerRequest {
                             auth_id,
                             s: s.to_bytes_be(),
@@ -76,7 +77,20 @@ pub fn ExpLogin(cx: Scope) -> impl IntoView {
         }
     });
 
-    let logout = move || session.set(None);
+    let logout = create_action(cx, {
+        let client = client.clone();
+        move |_| {
+            let mut client = client.clone();
+            async move {
+                if let Some(session_id) = session.get_untracked() {
+                    if let Err(status) = client.logout(Request::new(pb2::LogoutRequest { session_id })).await {
+                        message.set(Some(format!("Error: {:?}", status.message())));
+                    }
+                }
+                session.set(None);
+            }
+        }
+    });
 
     let register = create_action(cx, {
         move |_| {
@@ -85,11 +99,23 @@ pub fn ExpLogin(cx: Scope) -> impl IntoView {
             async move {
                 let username = username.get_untracked();
                 let password = password.get_untracked();
-                let (y1, y2) = protocol.register(&password);
+                let realm = match client.get_realm(Request::new(pb2::RealmRequest {})).await {
+                    Ok(response) => response.into_inner().realm,
+                    Err(status) => {
+                        message.set(Some(format!("Error: {:?}", status.message())));
+                        return;
+                    }
+                };
+                let (y1, y2) = protocol.register(&realm, &username, &password);
+                let (r1, r2, s) = protocol.prove_registration(&realm, &username, &password);
                 let request = Request::new(pb2::RegisterRequest {
                     user: username,
                     y1: y1.to_bytes_be(),
                     y2: y2.to_bytes_be(),
+                    factors: Vec::new(),
+                    r1: r1.to_bytes_be(),
+                    r2: r2.to_bytes_be(),
+                    s: s.to_bytes_be(),
                 });
                 match client.register(request).await {
                     Ok(response) => {
@@ -112,7 +138,7 @@ pub fn ExpLogin(cx: Scope) -> impl IntoView {
             <Show
                 when=move || session.get().is_none()
                 fallback=move |cx| view! { cx,
-                    <Button on_click=move |_| logout()>"Logout"</Button>
+                    <Button on_click=move |_| logout.dispatch(())>"Logout"</Button>
                 }
             >
                 <Stack orientation=StackOrientation::Horizontal spacing=Size::Em(0.6)>
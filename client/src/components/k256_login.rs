@@ -13,6 +13,39 @@ mod pb2 {
 }
 
 
+/// Local storage key of the device secret enrolled for `username` in `realm`.
+fn device_key_name(realm: &str, username: &str) -> String {
+    format!("chaum-pedersen-auth/device-key/{}/{}", realm, username)
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+fn load_device_key(realm: &str, username: &str) -> Option<Scalar> {
+    let hex = storage()?.get_item(&device_key_name(realm, username)).ok()??;
+    let bytes = (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let bytes: [u8; 32] = bytes.try_into().ok()?;
+    Scalar::from_repr(bytes.into()).into()
+}
+
+fn has_device_key(realm: &str, username: &str) -> bool {
+    storage().and_then(|storage| storage.get_item(&device_key_name(realm, username)).ok()).flatten().is_some()
+}
+
+/// Stores the device key of a confirmed registration, refusing to overwrite a stored key, which
+/// registering a taken name would otherwise destroy.
+fn store_device_key(realm: &str, username: &str, device_key: &Scalar) -> Option<()> {
+    if has_device_key(realm, username) {
+        return None;
+    }
+    let hex = device_key.to_repr().iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    storage()?.set_item(&device_key_name(realm, username), &hex).ok()
+}
+
+
 #[component]
 pub fn K256Login(cx: Scope) -> impl IntoView {
     let client = pb2::auth_client::AuthClient::new(Client::new(format!(
@@ -41,7 +74,7 @@ pub fn K256Login(cx: Scope) -> impl IntoView {
 
                 let (k, r1, r2) = protocol.commit();
                 let request = Request::new(pb2::K256AuthenticationChallengeRequest {
-                    user: username,
+                    user: username.clone(),
                     r1: Some(pb2::Point { x: r1.x().as_slice().into(), is_y_odd: r1.y_is_odd().into() }),
                     r2: Some(pb2::Point { x: r2.x().as_slice().into(), is_y_odd: r2.y_is_odd().into() }),
                 });
@@ -50,8 +83,19 @@ pub fn K256Login(cx: Scope) -> impl IntoView {
                         let response = response.into_inner();
                         log::info!("RESPONSE={:?}", response);
                         let auth_id = response.auth_id;
+                        let realm = response.realm;
                         let c = Scalar::from_repr(GenericArray::clone_from_slice(response.c.as_slice())).unwrap();
-                        let s = protocol.solve(&password, &k, &c);
+                        let s = if response.factors.contains(&(pb2::Factor::Device as i32)) {
+                            match load_device_key(&realm, &username) {
+                                Some(device_key) => protocol.solve_with_device(&realm, &username, &password, &device_key, &k, &c),
+                                None => {
+                                    message.set(Some("No device key for this user in this browser".into()));
+                                    return;
+                                }
+                            }
+                        } else {
+                            protocol.solve(&realm, &username, &password, &k, &c)
+                        };
                         let request = Request::new(pb2::K256AuthenticationAnswerRequest {
                             auth_id,
                             s: s.to_repr().to_vec(),
@@ -75,7 +119,20 @@ pub fn K256Login(cx: Scope) -> impl IntoView {
         }
     });
 
-    let logout = move || session.set(None);
+    let logout = create_action(cx, {
+        let client = client.clone();
+        move |_| {
+            let mut client = client.clone();
+            async move {
+                if let Some(session_id) = session.get_untracked() {
+                    if let Err(status) = client.logout(Request::new(pb2::LogoutRequest { session_id })).await {
+                        message.set(Some(format!("Error: {:?}", status.message())));
+                    }
+                }
+                session.set(None);
+            }
+        }
+    });
 
     let register = create_action(cx, {
         move |_| {
@@ -84,16 +141,37 @@ pub fn K256Login(cx: Scope) -> impl IntoView {
             async move {
                 let username = username.get_untracked();
                 let password = password.get_untracked();
-                let (y1, y2) = protocol.register(&password);
+                let realm = match client.get_realm(Request::new(pb2::RealmRequest {})).await {
+                    Ok(response) => response.into_inner().realm,
+                    Err(status) => {
+                        message.set(Some(format!("Error: {:?}", status.message())));
+                        return;
+                    }
+                };
+                if has_device_key(&realm, &username) {
+                    message.set(Some("Error: this browser already has a device key for this user".into()));
+                    return;
+                }
+                let device_key = protocol.device_key();
+                let (y1, y2) = protocol.register_with_device(&realm, &username, &password, &device_key);
+                let (r1, r2, s) = protocol.prove_registration_with_device(&realm, &username, &password, &device_key);
                 let request = Request::new(pb2::K256RegisterRequest {
-                    user: username,
+                    user: username.clone(),
                     y1: Some(pb2::Point { x: y1.x().as_slice().into(), is_y_odd: y1.y_is_odd().into() }),
                     y2: Some(pb2::Point { x: y2.x().as_slice().into(), is_y_odd: y2.y_is_odd().into() }),
+                    factors: vec![pb2::Factor::Device as i32],
+                    r1: Some(pb2::Point { x: r1.x().as_slice().into(), is_y_odd: r1.y_is_odd().into() }),
+                    r2: Some(pb2::Point { x: r2.x().as_slice().into(), is_y_odd: r2.y_is_odd().into() }),
+                    s: s.to_repr().to_vec(),
                 });
                 match client.k256_register(request).await {
                     Ok(response) => {
                         let response = response.into_inner();
                         log::info!("RESPONSE={:?}", response);
+                        if store_device_key(&realm, &username, &device_key).is_none() {
+                            message.set(Some("Error: cannot store the device key in this browser".into()));
+                            return;
+                        }
                         message.set(Some("Registration successful".into()));
                         login.dispatch(());
                     }
@@ -111,7 +189,7 @@ pub fn K256Login(cx: Scope) -> impl IntoView {
             <Show
                 when=move || session.get().is_none()
                 fallback=move |cx| view! { cx,
-                    <Button on_click=move |_| logout()>"Logout"</Button>
+                    <Button on_click=move |_| logout.dispatch(())>"Logout"</Button>
                 }
             >
                 <Stack orientation=StackOrientation::Horizontal spacing=Size::Em(0.6)>
@@ -0,0 +1,211 @@
+#ifndef CHAUM_PEDERSEN_H
+#define CHAUM_PEDERSEN_H
+
+/* Generated with cbindgen from ffi/src/lib.rs, do not edit by hand. Run `make header` to regenerate. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CP_K256_POINT_LEN 33
+
+#define CP_K256_SCALAR_LEN 32
+
+typedef enum CpStatus {
+  CP_STATUS_OK = 0,
+  CP_STATUS_NULL_POINTER = 1,
+  CP_STATUS_INVALID_UTF8 = 2,
+  CP_STATUS_INVALID_LENGTH = 3,
+  CP_STATUS_INVALID_ENCODING = 4,
+  CP_STATUS_BUFFER_TOO_SMALL = 5,
+  CP_STATUS_PANIC = 6,
+} CpStatus;
+
+/**
+ * Opaque handle to the exponentiation variant.
+ */
+typedef struct CpExp CpExp;
+
+/**
+ * Opaque handle to the k256 elliptic curve variant.
+ */
+typedef struct CpK256 CpK256;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an exponentiation handle from big endian `p`, `q`, `g` and `h`. Release it with
+ * `cp_exp_free`.
+ */
+enum CpStatus cp_exp_new(const uint8_t *p,
+                         size_t p_len,
+                         const uint8_t *q,
+                         size_t q_len,
+                         const uint8_t *g,
+                         size_t g_len,
+                         const uint8_t *h,
+                         size_t h_len,
+                         struct CpExp **out);
+
+void cp_exp_free(struct CpExp *protocol);
+
+enum CpStatus cp_exp_register(const struct CpExp *protocol,
+                              const uint8_t *realm,
+                              size_t realm_len,
+                              const uint8_t *user,
+                              size_t user_len,
+                              const uint8_t *password,
+                              size_t password_len,
+                              uint8_t *y1,
+                              size_t *y1_len,
+                              uint8_t *y2,
+                              size_t *y2_len);
+
+/**
+ * Writes the proof of possession of the credentials of `cp_exp_register` the service expects
+ * along them.
+ */
+enum CpStatus cp_exp_prove_registration(const struct CpExp *protocol,
+                                        const uint8_t *realm,
+                                        size_t realm_len,
+                                        const uint8_t *user,
+                                        size_t user_len,
+                                        const uint8_t *password,
+                                        size_t password_len,
+                                        uint8_t *r1,
+                                        size_t *r1_len,
+                                        uint8_t *r2,
+                                        size_t *r2_len,
+                                        uint8_t *s,
+                                        size_t *s_len);
+
+enum CpStatus cp_exp_commit(const struct CpExp *protocol,
+                            uint8_t *k,
+                            size_t *k_len,
+                            uint8_t *r1,
+                            size_t *r1_len,
+                            uint8_t *r2,
+                            size_t *r2_len);
+
+enum CpStatus cp_exp_challenge(const struct CpExp *protocol, uint8_t *c, size_t *c_len);
+
+enum CpStatus cp_exp_solve(const struct CpExp *protocol,
+                           const uint8_t *realm,
+                           size_t realm_len,
+                           const uint8_t *user,
+                           size_t user_len,
+                           const uint8_t *password,
+                           size_t password_len,
+                           const uint8_t *k,
+                           size_t k_len,
+                           const uint8_t *c,
+                           size_t c_len,
+                           uint8_t *s,
+                           size_t *s_len);
+
+/**
+ * Writes whether the proof is valid into `valid`.
+ */
+enum CpStatus cp_exp_verify(const struct CpExp *protocol,
+                            const uint8_t *y1,
+                            size_t y1_len,
+                            const uint8_t *y2,
+                            size_t y2_len,
+                            const uint8_t *r1,
+                            size_t r1_len,
+                            const uint8_t *r2,
+                            size_t r2_len,
+                            const uint8_t *c,
+                            size_t c_len,
+                            const uint8_t *s,
+                            size_t s_len,
+                            bool *valid);
+
+/**
+ * Creates a k256 handle. Release it with `cp_k256_free`.
+ */
+enum CpStatus cp_k256_new(uint64_t h_offset, struct CpK256 **out);
+
+void cp_k256_free(struct CpK256 *protocol);
+
+enum CpStatus cp_k256_register(const struct CpK256 *protocol,
+                               const uint8_t *realm,
+                               size_t realm_len,
+                               const uint8_t *user,
+                               size_t user_len,
+                               const uint8_t *password,
+                               size_t password_len,
+                               uint8_t *y1,
+                               size_t *y1_len,
+                               uint8_t *y2,
+                               size_t *y2_len);
+
+/**
+ * Writes the proof of possession of the credentials of `cp_k256_register` the service expects
+ * along them.
+ */
+enum CpStatus cp_k256_prove_registration(const struct CpK256 *protocol,
+                                         const uint8_t *realm,
+                                         size_t realm_len,
+                                         const uint8_t *user,
+                                         size_t user_len,
+                                         const uint8_t *password,
+                                         size_t password_len,
+                                         uint8_t *r1,
+                                         size_t *r1_len,
+                                         uint8_t *r2,
+                                         size_t *r2_len,
+                                         uint8_t *s,
+                                         size_t *s_len);
+
+enum CpStatus cp_k256_commit(const struct CpK256 *protocol,
+                             uint8_t *k,
+                             size_t *k_len,
+                             uint8_t *r1,
+                             size_t *r1_len,
+                             uint8_t *r2,
+                             size_t *r2_len);
+
+enum CpStatus cp_k256_challenge(const struct CpK256 *protocol, uint8_t *c, size_t *c_len);
+
+enum CpStatus cp_k256_solve(const struct CpK256 *protocol,
+                            const uint8_t *realm,
+                            size_t realm_len,
+                            const uint8_t *user,
+                            size_t user_len,
+                            const uint8_t *password,
+                            size_t password_len,
+                            const uint8_t *k,
+                            size_t k_len,
+                            const uint8_t *c,
+                            size_t c_len,
+                            uint8_t *s,
+                            size_t *s_len);
+
+/**
+ * Writes whether the proof is valid into `valid`.
+ */
+enum CpStatus cp_k256_verify(const struct CpK256 *protocol,
+                             const uint8_t *y1,
+                             size_t y1_len,
+                             const uint8_t *y2,
+                             size_t y2_len,
+                             const uint8_t *r1,
+                             size_t r1_len,
+                             const uint8_t *r2,
+                             size_t r2_len,
+                             const uint8_t *c,
+                             size_t c_len,
+                             const uint8_t *s,
+                             size_t s_len,
+                             bool *valid);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CHAUM_PEDERSEN_H */
@@ -0,0 +1,348 @@
+//! C ABI over the `protocol` crate.
+//!
+//! Every function returns a [`CpStatus`] and never unwinds into the caller. Inputs are passed as
+//! pointer and length pairs. Outputs are written into caller owned buffers whose capacity is
+//! passed in through `*_len` and replaced with the number of bytes written. When any output
+//! buffer is too small nothing is written, every `*_len` is set to the size needed and
+//! `CP_STATUS_BUFFER_TOO_SMALL` is returned.
+//!
+//! Exponentiation values use the unsigned big endian encoding of the gRPC api, k256 scalars are
+//! 32 byte big endian and k256 points are 33 byte SEC1 compressed (`0x02`/`0x03` prefix followed
+//! by the `x` coordinate of the gRPC `Point` message).
+#![allow(clippy::missing_safety_doc)]
+
+use std::panic::{self, AssertUnwindSafe};
+
+use k256::{
+    AffinePoint,
+    Scalar,
+    elliptic_curve::{group::GroupEncoding, PrimeField},
+};
+use num_bigint::BigUint;
+use protocol::{ChaumPedersen, ChaumPedersenK256};
+
+
+pub const CP_K256_POINT_LEN: usize = 33;
+pub const CP_K256_SCALAR_LEN: usize = 32;
+
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    InvalidLength = 3,
+    InvalidEncoding = 4,
+    BufferTooSmall = 5,
+    Panic = 6,
+}
+
+
+/// Opaque handle to the exponentiation variant.
+pub struct CpExp(ChaumPedersen);
+
+/// Opaque handle to the k256 elliptic curve variant.
+pub struct CpK256(ChaumPedersenK256);
+
+
+fn guard(f: impl FnOnce() -> Result<(), CpStatus>) -> CpStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => CpStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => CpStatus::Panic,
+    }
+}
+
+unsafe fn handle<'a, T>(ptr: *const T) -> Result<&'a T, CpStatus> {
+    ptr.as_ref().ok_or(CpStatus::NullPointer)
+}
+
+unsafe fn input<'a>(ptr: *const u8, len: usize) -> Result<&'a [u8], CpStatus> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(CpStatus::NullPointer)
+    } else {
+        Ok(std::slice::from_raw_parts(ptr, len))
+    }
+}
+
+unsafe fn utf8<'a>(ptr: *const u8, len: usize) -> Result<&'a str, CpStatus> {
+    std::str::from_utf8(input(ptr, len)?).map_err(|_| CpStatus::InvalidUtf8)
+}
+
+unsafe fn output(values: &[(&[u8], *mut u8, *mut usize)]) -> Result<(), CpStatus> {
+    if values.iter().any(|(_, ptr, len)| ptr.is_null() || len.is_null()) {
+        return Err(CpStatus::NullPointer);
+    }
+    if values.iter().any(|(value, _, len)| **len < value.len()) {
+        for (value, _, len) in values {
+            **len = value.len();
+        }
+        return Err(CpStatus::BufferTooSmall);
+    }
+    for (value, ptr, len) in values {
+        std::ptr::copy_nonoverlapping(value.as_ptr(), *ptr, value.len());
+        **len = value.len();
+    }
+    Ok(())
+}
+
+unsafe fn biguint(ptr: *const u8, len: usize) -> Result<BigUint, CpStatus> {
+    Ok(BigUint::from_bytes_be(input(ptr, len)?))
+}
+
+unsafe fn scalar(ptr: *const u8, len: usize) -> Result<Scalar, CpStatus> {
+    let bytes: [u8; CP_K256_SCALAR_LEN] = input(ptr, len)?.try_into().map_err(|_| CpStatus::InvalidLength)?;
+    Option::from(Scalar::from_repr(bytes.into())).ok_or(CpStatus::InvalidEncoding)
+}
+
+unsafe fn point(ptr: *const u8, len: usize) -> Result<AffinePoint, CpStatus> {
+    let bytes: [u8; CP_K256_POINT_LEN] = input(ptr, len)?.try_into().map_err(|_| CpStatus::InvalidLength)?;
+    Option::from(AffinePoint::from_bytes(&bytes.into())).ok_or(CpStatus::InvalidEncoding)
+}
+
+
+/// Creates an exponentiation handle from big endian `p`, `q`, `g` and `h`. Release it with
+/// `cp_exp_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_new(
+    p: *const u8, p_len: usize,
+    q: *const u8, q_len: usize,
+    g: *const u8, g_len: usize,
+    h: *const u8, h_len: usize,
+    out: *mut *mut CpExp,
+) -> CpStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(CpStatus::NullPointer);
+        }
+        let protocol = ChaumPedersen::new(biguint(p, p_len)?, biguint(q, q_len)?, biguint(g, g_len)?, biguint(h, h_len)?);
+        *out = Box::into_raw(Box::new(CpExp(protocol)));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_free(protocol: *mut CpExp) {
+    if !protocol.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(protocol))));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_register(
+    protocol: *const CpExp,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    y1: *mut u8, y1_len: *mut usize,
+    y2: *mut u8, y2_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (v1, v2) = handle(protocol)?.0.register(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?);
+        output(&[(&v1.to_bytes_be(), y1, y1_len), (&v2.to_bytes_be(), y2, y2_len)])
+    })
+}
+
+/// Writes the proof of possession of the credentials of `cp_exp_register` the service expects
+/// along them.
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_prove_registration(
+    protocol: *const CpExp,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    r1: *mut u8, r1_len: *mut usize,
+    r2: *mut u8, r2_len: *mut usize,
+    s: *mut u8, s_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (v1, v2, vs) = handle(protocol)?.0.prove_registration(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?);
+        output(&[(&v1.to_bytes_be(), r1, r1_len), (&v2.to_bytes_be(), r2, r2_len), (&vs.to_bytes_be(), s, s_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_commit(
+    protocol: *const CpExp,
+    k: *mut u8, k_len: *mut usize,
+    r1: *mut u8, r1_len: *mut usize,
+    r2: *mut u8, r2_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (vk, v1, v2) = handle(protocol)?.0.commit();
+        output(&[(&vk.to_bytes_be(), k, k_len), (&v1.to_bytes_be(), r1, r1_len), (&v2.to_bytes_be(), r2, r2_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_challenge(protocol: *const CpExp, c: *mut u8, c_len: *mut usize) -> CpStatus {
+    guard(|| {
+        let vc = handle(protocol)?.0.challenge();
+        output(&[(&vc.to_bytes_be(), c, c_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_solve(
+    protocol: *const CpExp,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    k: *const u8, k_len: usize,
+    c: *const u8, c_len: usize,
+    s: *mut u8, s_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let vs = handle(protocol)?.0.solve(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?, &biguint(k, k_len)?, &biguint(c, c_len)?);
+        output(&[(&vs.to_bytes_be(), s, s_len)])
+    })
+}
+
+/// Writes whether the proof is valid into `valid`.
+#[no_mangle]
+pub unsafe extern "C" fn cp_exp_verify(
+    protocol: *const CpExp,
+    y1: *const u8, y1_len: usize,
+    y2: *const u8, y2_len: usize,
+    r1: *const u8, r1_len: usize,
+    r2: *const u8, r2_len: usize,
+    c: *const u8, c_len: usize,
+    s: *const u8, s_len: usize,
+    valid: *mut bool,
+) -> CpStatus {
+    guard(|| {
+        if valid.is_null() {
+            return Err(CpStatus::NullPointer);
+        }
+        *valid = handle(protocol)?.0.verify(
+            &biguint(y1, y1_len)?,
+            &biguint(y2, y2_len)?,
+            &biguint(r1, r1_len)?,
+            &biguint(r2, r2_len)?,
+            &biguint(c, c_len)?,
+            &biguint(s, s_len)?,
+        );
+        Ok(())
+    })
+}
+
+
+/// Creates a k256 handle. Release it with `cp_k256_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_new(h_offset: u64, out: *mut *mut CpK256) -> CpStatus {
+    guard(|| {
+        if out.is_null() {
+            return Err(CpStatus::NullPointer);
+        }
+        *out = Box::into_raw(Box::new(CpK256(ChaumPedersenK256::new(h_offset))));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_free(protocol: *mut CpK256) {
+    if !protocol.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(protocol))));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_register(
+    protocol: *const CpK256,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    y1: *mut u8, y1_len: *mut usize,
+    y2: *mut u8, y2_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (v1, v2) = handle(protocol)?.0.register(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?);
+        output(&[(&v1.to_bytes(), y1, y1_len), (&v2.to_bytes(), y2, y2_len)])
+    })
+}
+
+/// Writes the proof of possession of the credentials of `cp_k256_register` the service expects
+/// along them.
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_prove_registration(
+    protocol: *const CpK256,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    r1: *mut u8, r1_len: *mut usize,
+    r2: *mut u8, r2_len: *mut usize,
+    s: *mut u8, s_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (v1, v2, vs) = handle(protocol)?.0.prove_registration(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?);
+        output(&[(&v1.to_bytes(), r1, r1_len), (&v2.to_bytes(), r2, r2_len), (&vs.to_repr(), s, s_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_commit(
+    protocol: *const CpK256,
+    k: *mut u8, k_len: *mut usize,
+    r1: *mut u8, r1_len: *mut usize,
+    r2: *mut u8, r2_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let (vk, v1, v2) = handle(protocol)?.0.commit();
+        output(&[(&vk.to_repr(), k, k_len), (&v1.to_bytes(), r1, r1_len), (&v2.to_bytes(), r2, r2_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_challenge(protocol: *const CpK256, c: *mut u8, c_len: *mut usize) -> CpStatus {
+    guard(|| {
+        let vc = handle(protocol)?.0.challenge();
+        output(&[(&vc.to_repr(), c, c_len)])
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_solve(
+    protocol: *const CpK256,
+    realm: *const u8, realm_len: usize,
+    user: *const u8, user_len: usize,
+    password: *const u8, password_len: usize,
+    k: *const u8, k_len: usize,
+    c: *const u8, c_len: usize,
+    s: *mut u8, s_len: *mut usize,
+) -> CpStatus {
+    guard(|| {
+        let vs = handle(protocol)?.0.solve(utf8(realm, realm_len)?, utf8(user, user_len)?, utf8(password, password_len)?, &scalar(k, k_len)?, &scalar(c, c_len)?);
+        output(&[(&vs.to_repr(), s, s_len)])
+    })
+}
+
+/// Writes whether the proof is valid into `valid`.
+#[no_mangle]
+pub unsafe extern "C" fn cp_k256_verify(
+    protocol: *const CpK256,
+    y1: *const u8, y1_len: usize,
+    y2: *const u8, y2_len: usize,
+    r1: *const u8, r1_len: usize,
+    r2: *const u8, r2_len: usize,
+    c: *const u8, c_len: usize,
+    s: *const u8, s_len: usize,
+    valid: *mut bool,
+) -> CpStatus {
+    guard(|| {
+        if valid.is_null() {
+            return Err(CpStatus::NullPointer);
+        }
+        *valid = handle(protocol)?.0.verify(
+            &point(y1, y1_len)?,
+            &point(y2, y2_len)?,
+            &point(r1, r1_len)?,
+            &point(r2, r2_len)?,
+            &scalar(c, c_len)?,
+            &scalar(s, s_len)?,
+        );
+        Ok(())
+    })
+}
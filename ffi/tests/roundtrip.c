@@ -0,0 +1,120 @@
+#include <stdio.h>
+#include <string.h>
+
+#include "chaum_pedersen.h"
+
+#define CHECK(expr)                                                        \
+    do {                                                                   \
+        if (!(expr)) {                                                     \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #expr); \
+            return 1;                                                      \
+        }                                                                  \
+    } while (0)
+
+#define ELEMENT_LEN 64
+
+#define BYTES(str) (const uint8_t *)(str), strlen(str)
+#define IDENTITY(password) BYTES(REALM), BYTES(USER), BYTES(password)
+
+static const char REALM[] = "chaum-pedersen-auth";
+static const char USER[] = "testuser";
+static const char PASSWORD[] = "password";
+static const char WRONG_PASSWORD[] = "passw0rd";
+
+
+static size_t be_bytes(uint64_t value, uint8_t *out) {
+    for (int i = 0; i < 8; i++) {
+        out[i] = (uint8_t)(value >> (56 - 8 * i));
+    }
+    return 8;
+}
+
+
+static int test_exp(void) {
+    uint8_t p[8], q[8], g[8], h[8];
+    size_t p_len = be_bytes(363967321904221003ULL, p);
+    size_t q_len = be_bytes(7696033ULL, q);
+    size_t g_len = be_bytes(165950041202038920ULL, g);
+    size_t h_len = be_bytes(96429580695728554ULL, h);
+
+    CpExp *protocol = NULL;
+    CHECK(cp_exp_new(p, p_len, q, q_len, g, g_len, h, h_len, &protocol) == CP_STATUS_OK);
+
+    uint8_t y1[ELEMENT_LEN], y2[ELEMENT_LEN];
+    size_t y1_len = 1, y2_len = sizeof(y2);
+    CHECK(cp_exp_register(protocol, IDENTITY(PASSWORD), y1, &y1_len, y2, &y2_len) == CP_STATUS_BUFFER_TOO_SMALL);
+    CHECK(y1_len == 8);
+    y1_len = sizeof(y1);
+    y2_len = sizeof(y2);
+    CHECK(cp_exp_register(protocol, IDENTITY(PASSWORD), y1, &y1_len, y2, &y2_len) == CP_STATUS_OK);
+
+    uint8_t proof_r1[ELEMENT_LEN], proof_r2[ELEMENT_LEN], proof_s[ELEMENT_LEN];
+    size_t proof_r1_len = sizeof(proof_r1), proof_r2_len = sizeof(proof_r2), proof_s_len = sizeof(proof_s);
+    CHECK(cp_exp_prove_registration(protocol, IDENTITY(PASSWORD), proof_r1, &proof_r1_len, proof_r2, &proof_r2_len, proof_s, &proof_s_len) == CP_STATUS_OK);
+
+    uint8_t k[ELEMENT_LEN], r1[ELEMENT_LEN], r2[ELEMENT_LEN], c[ELEMENT_LEN], s[ELEMENT_LEN];
+    size_t k_len = sizeof(k), r1_len = sizeof(r1), r2_len = sizeof(r2), c_len = sizeof(c), s_len = sizeof(s);
+    CHECK(cp_exp_commit(protocol, k, &k_len, r1, &r1_len, r2, &r2_len) == CP_STATUS_OK);
+    CHECK(cp_exp_challenge(protocol, c, &c_len) == CP_STATUS_OK);
+    CHECK(cp_exp_solve(protocol, IDENTITY(PASSWORD), k, k_len, c, c_len, s, &s_len) == CP_STATUS_OK);
+
+    bool valid = false;
+    CHECK(cp_exp_verify(protocol, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_OK);
+    CHECK(valid);
+
+    s_len = sizeof(s);
+    CHECK(cp_exp_solve(protocol, IDENTITY(WRONG_PASSWORD), k, k_len, c, c_len, s, &s_len) == CP_STATUS_OK);
+    CHECK(cp_exp_verify(protocol, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_OK);
+    CHECK(!valid);
+
+    CHECK(cp_exp_verify(NULL, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_NULL_POINTER);
+
+    cp_exp_free(protocol);
+    return 0;
+}
+
+
+static int test_k256(void) {
+    CpK256 *protocol = NULL;
+    CHECK(cp_k256_new(107211496160805127ULL, &protocol) == CP_STATUS_OK);
+
+    uint8_t y1[CP_K256_POINT_LEN], y2[CP_K256_POINT_LEN];
+    size_t y1_len = sizeof(y1), y2_len = sizeof(y2);
+    CHECK(cp_k256_register(protocol, IDENTITY(PASSWORD), y1, &y1_len, y2, &y2_len) == CP_STATUS_OK);
+
+    uint8_t proof_r1[CP_K256_POINT_LEN], proof_r2[CP_K256_POINT_LEN], proof_s[CP_K256_SCALAR_LEN];
+    size_t proof_r1_len = sizeof(proof_r1), proof_r2_len = sizeof(proof_r2), proof_s_len = sizeof(proof_s);
+    CHECK(cp_k256_prove_registration(protocol, IDENTITY(PASSWORD), proof_r1, &proof_r1_len, proof_r2, &proof_r2_len, proof_s, &proof_s_len) == CP_STATUS_OK);
+    CHECK(proof_r1_len == CP_K256_POINT_LEN && proof_s_len == CP_K256_SCALAR_LEN);
+
+    uint8_t k[CP_K256_SCALAR_LEN], r1[CP_K256_POINT_LEN], r2[CP_K256_POINT_LEN], c[CP_K256_SCALAR_LEN], s[CP_K256_SCALAR_LEN];
+    size_t k_len = sizeof(k), r1_len = sizeof(r1), r2_len = sizeof(r2), c_len = sizeof(c), s_len = sizeof(s);
+    CHECK(cp_k256_commit(protocol, k, &k_len, r1, &r1_len, r2, &r2_len) == CP_STATUS_OK);
+    CHECK(cp_k256_challenge(protocol, c, &c_len) == CP_STATUS_OK);
+    CHECK(cp_k256_solve(protocol, IDENTITY(PASSWORD), k, k_len, c, c_len, s, &s_len) == CP_STATUS_OK);
+
+    bool valid = false;
+    CHECK(cp_k256_verify(protocol, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_OK);
+    CHECK(valid);
+
+    s_len = sizeof(s);
+    CHECK(cp_k256_solve(protocol, IDENTITY(WRONG_PASSWORD), k, k_len, c, c_len, s, &s_len) == CP_STATUS_OK);
+    CHECK(cp_k256_verify(protocol, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_OK);
+    CHECK(!valid);
+
+    CHECK(cp_k256_verify(protocol, y1, y1_len - 1, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_INVALID_LENGTH);
+    memset(y1 + 1, 0xff, CP_K256_POINT_LEN - 1);
+    CHECK(cp_k256_verify(protocol, y1, y1_len, y2, y2_len, r1, r1_len, r2, r2_len, c, c_len, s, s_len, &valid) == CP_STATUS_INVALID_ENCODING);
+
+    cp_k256_free(protocol);
+    return 0;
+}
+
+
+int main(void) {
+    if (test_exp() != 0 || test_k256() != 0) {
+        return 1;
+    }
+    printf("roundtrip ok\n");
+    return 0;
+}
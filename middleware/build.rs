@@ -0,0 +1,11 @@
+fn main() {
+    tonic_build::configure()
+        .build_server(false)
+        .compile(
+            &[
+                "../zkp_auth.proto",
+            ],
+            &[".."],
+        )
+        .unwrap();
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use tonic::{service::Interceptor, Request, Status};
+
+use crate::{bearer, verify, VerifyingKeys, AUTHORIZATION};
+
+
+/// Interceptor validating session tokens against the auth service's keys, for use with
+/// `with_interceptor` on generated servers.
+#[derive(Clone)]
+pub struct SessionInterceptor {
+    keys: Arc<VerifyingKeys>,
+}
+
+impl SessionInterceptor {
+    pub fn new(keys: Arc<VerifyingKeys>) -> Self {
+        Self { keys }
+    }
+}
+
+impl Interceptor for SessionInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let token = request.metadata().get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(bearer);
+        let user = verify(&self.keys, token)?;
+        request.extensions_mut().insert(user);
+        Ok(request)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tonic::Code;
+
+    use super::*;
+    use crate::{tests::setup_token, AuthenticatedUser};
+
+    #[test]
+    fn test_intercept() {
+        let (keys, token) = setup_token("2023-10", 60);
+        let mut interceptor = SessionInterceptor::new(Arc::new(keys));
+
+        let mut request = Request::new(());
+        request.metadata_mut().insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        let request = interceptor.call(request).unwrap();
+        assert_eq!(request.extensions().get::<AuthenticatedUser>().unwrap().user, "alice");
+
+        assert_eq!(interceptor.call(Request::new(())).unwrap_err().code(), Code::Unauthenticated);
+        let mut request = Request::new(());
+        request.metadata_mut().insert(AUTHORIZATION, "Bearer invalid".parse().unwrap());
+        assert_eq!(interceptor.call(request).unwrap_err().code(), Code::Unauthenticated);
+    }
+}
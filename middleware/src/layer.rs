@@ -0,0 +1,99 @@
+use std::{future::Future, pin::Pin, task::{Context, Poll}};
+
+use tonic::{body::BoxBody, server::NamedService, Status};
+use tower::{Layer, Service};
+
+use crate::{bearer, Validator, AUTHORIZATION};
+
+
+/// Layer validating session tokens before calls reach the wrapped service.
+#[derive(Clone)]
+pub struct SessionLayer {
+    validator: Validator,
+}
+
+impl SessionLayer {
+    pub fn new(validator: Validator) -> Self {
+        Self { validator }
+    }
+}
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionService { inner, validator: self.validator.clone() }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct SessionService<S> {
+    inner: S,
+    validator: Validator,
+}
+
+impl<S, B> Service<http::Request<B>> for SessionService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        // The clone might not be ready, so call the service that was polled and keep the clone.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let validator = self.validator.clone();
+        Box::pin(async move {
+            let token = request.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(bearer);
+            match validator.validate(token).await {
+                Ok(user) => {
+                    request.extensions_mut().insert(user);
+                    inner.call(request).await
+                }
+                Err(error) => Ok(Status::from(error).to_http()),
+            }
+        })
+    }
+}
+
+impl<S: NamedService> NamedService for SessionService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, sync::Arc};
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{tests::setup_token, AuthenticatedUser};
+
+    async fn echo(request: http::Request<()>) -> Result<http::Response<BoxBody>, Infallible> {
+        let user = request.extensions().get::<AuthenticatedUser>().unwrap().user.clone();
+        Ok(http::Response::builder().header("user", user).body(tonic::body::empty_body()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        let (keys, token) = setup_token("2023-10", 60);
+        let service = SessionLayer::new(Validator::Local(Arc::new(keys))).layer(service_fn(echo));
+
+        let request = http::Request::builder().header(AUTHORIZATION, format!("Bearer {}", token)).body(()).unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.headers()["user"], "alice");
+
+        let response = service.oneshot(http::Request::new(())).await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], (tonic::Code::Unauthenticated as i32).to_string().as_str());
+    }
+}
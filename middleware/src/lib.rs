@@ -0,0 +1,166 @@
+//! Enforces logins with the Chaum Pedersen auth service in other tonic services.
+//!
+//! Clients send the session token they got from the auth service as `authorization: Bearer
+//! <token>`. [`SessionLayer`] validates it either locally against the service's signing keys or
+//! by calling `ValidateSession`, which also sees logouts and idle sessions, while
+//! [`SessionInterceptor`] can only validate locally as interceptors are synchronous. Both insert an
+//! [`AuthenticatedUser`] into the request extensions and reject the call with
+//! `Status::unauthenticated` otherwise.
+
+use std::{fmt, sync::Arc};
+
+use tonic::{transport::Channel, Code, Status};
+
+mod interceptor;
+mod layer;
+mod token;
+
+pub use interceptor::SessionInterceptor;
+pub use layer::{SessionLayer, SessionService};
+pub use token::{Claims, VerifyingKeys};
+
+mod pb2 {
+    tonic::include_proto!("zkp_auth");
+}
+
+pub use pb2::auth_client::AuthClient;
+
+
+/// Metadata key of the session token.
+pub const AUTHORIZATION: &str = "authorization";
+
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuthenticatedUser {
+    pub user: String,
+    /// Seconds since the Unix epoch the session expires at.
+    pub expires_at: u64,
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    MissingToken,
+    InvalidToken,
+    /// The auth service could not be asked to validate the token.
+    Unavailable,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingToken => write!(f, "missing session token"),
+            Error::InvalidToken => write!(f, "invalid session"),
+            Error::Unavailable => write!(f, "auth service unavailable"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::MissingToken | Error::InvalidToken => Status::unauthenticated(error.to_string()),
+            Error::Unavailable => Status::unavailable(error.to_string()),
+        }
+    }
+}
+
+
+/// Token of an `authorization` value of the form `Bearer <token>`.
+fn bearer(value: &str) -> Option<&str> {
+    value.strip_prefix("Bearer ").map(str::trim).filter(|token| !token.is_empty())
+}
+
+
+fn verify(keys: &VerifyingKeys, token: Option<&str>) -> Result<AuthenticatedUser, Error> {
+    let claims = keys.verify(token.ok_or(Error::MissingToken)?).ok_or(Error::InvalidToken)?;
+    Ok(AuthenticatedUser { user: claims.sub, expires_at: claims.exp })
+}
+
+
+#[derive(Clone)]
+pub enum Validator {
+    /// Checks signature and expiry against the auth service's keys, without a round trip but also
+    /// without noticing logouts.
+    Local(Arc<VerifyingKeys>),
+    /// Asks the auth service's `ValidateSession`.
+    Remote(AuthClient<Channel>),
+}
+
+impl Validator {
+    pub async fn validate(&self, token: Option<&str>) -> Result<AuthenticatedUser, Error> {
+        match self {
+            Validator::Local(keys) => verify(keys, token),
+            Validator::Remote(client) => {
+                let request = pb2::ValidateSessionRequest { session_id: token.ok_or(Error::MissingToken)?.to_string() };
+                match client.clone().validate_session(request).await {
+                    Ok(response) => {
+                        let response = response.into_inner();
+                        Ok(AuthenticatedUser { user: response.user, expires_at: response.expires_at })
+                    }
+                    Err(status) if status.code() == Code::Unauthenticated => Err(Error::InvalidToken),
+                    Err(_) => Err(Error::Unavailable),
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use ed25519_dalek::{pkcs8::EncodePrivateKey, SigningKey};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Keys with a single key `kid` and a token for `alice` signed with it.
+    pub fn setup_token(kid: &str, exp: u64) -> (VerifyingKeys, String) {
+        let key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let mut keys = VerifyingKeys::new();
+        keys.insert(kid, key.verifying_key().to_bytes());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let claims = Claims {
+            iss: "chaum-pedersen-auth".to_string(),
+            sub: "alice".to_string(),
+            ciphersuite: "CP-K256-SHA256-SHA256".to_string(),
+            iat: now,
+            exp: now + exp,
+            jti: Uuid::new_v4(),
+        };
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.to_string());
+        let encoding = EncodingKey::from_ed_der(key.to_pkcs8_der().unwrap().as_bytes());
+        (keys, jsonwebtoken::encode(&header, &claims, &encoding).unwrap())
+    }
+
+    #[test]
+    fn test_bearer() {
+        assert_eq!(bearer("Bearer abc"), Some("abc"));
+        assert_eq!(bearer("Bearer "), None);
+        assert_eq!(bearer("Basic abc"), None);
+    }
+
+    #[tokio::test]
+    async fn test_validate_local() {
+        let (keys, token) = setup_token("2023-10", 60);
+        let validator = Validator::Local(Arc::new(keys));
+        assert_eq!(validator.validate(Some(&token)).await.unwrap().user, "alice");
+        assert_eq!(validator.validate(None).await, Err(Error::MissingToken));
+        assert_eq!(validator.validate(Some("invalid")).await, Err(Error::InvalidToken));
+        let (_, other) = setup_token("2023-10", 60);
+        assert_eq!(validator.validate(Some(&other)).await, Err(Error::InvalidToken));
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(Status::from(Error::InvalidToken).code(), Code::Unauthenticated);
+        assert_eq!(Status::from(Error::MissingToken).code(), Code::Unauthenticated);
+        assert_eq!(Status::from(Error::Unavailable).code(), Code::Unavailable);
+    }
+}
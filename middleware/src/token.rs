@@ -0,0 +1,65 @@
+use std::collections::BTreeMap;
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{pb2, AuthClient, Error};
+
+
+/// Claims of the session tokens the auth service issues.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// Realm of the service that issued the token.
+    pub iss: String,
+    pub sub: String,
+    pub ciphersuite: String,
+    pub iat: u64,
+    pub exp: u64,
+    /// Id of the session, which can be revoked before the token expires.
+    pub jti: Uuid,
+}
+
+
+/// Ed25519 public keys by key id, checking tokens signed with `alg` `EdDSA` and a `kid` header.
+#[derive(Clone, Default)]
+pub struct VerifyingKeys {
+    keys: BTreeMap<String, (DecodingKey, [u8; 32])>,
+}
+
+impl VerifyingKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the raw public key `kid`, returning false if a key with that id was already added.
+    pub fn insert(&mut self, kid: impl Into<String>, public_key: [u8; 32]) -> bool {
+        self.keys.insert(kid.into(), (DecodingKey::from_ed_der(&public_key), public_key)).is_none()
+    }
+
+    /// Keys published by the auth service's `GetSigningKeys`, skipping keys of other algorithms.
+    pub async fn fetch(client: &mut AuthClient<tonic::transport::Channel>) -> Result<Self, Error> {
+        let response = client.get_signing_keys(pb2::SigningKeysRequest {}).await.map_err(|_| Error::Unavailable)?;
+        let mut keys = Self::new();
+        for key in response.into_inner().keys {
+            if let (Ok(public_key), "EdDSA") = (key.public_key.as_slice().try_into(), key.algorithm.as_str()) {
+                keys.insert(key.kid, public_key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Claims of `token` if it is signed by one of the keys and has not expired.
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        let kid = jsonwebtoken::decode_header(token).ok()?.kid?;
+        let (key, _) = self.keys.get(&kid)?;
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.leeway = 0;
+        jsonwebtoken::decode(token, key, &validation).ok().map(|data| data.claims)
+    }
+
+    /// Ids and raw public keys in order of their ids.
+    pub fn public_keys(&self) -> impl Iterator<Item = (&str, &[u8; 32])> {
+        self.keys.iter().map(|(kid, (_, public_key))| (kid.as_str(), public_key))
+    }
+}
@@ -0,0 +1,318 @@
+//! Ciphersuites identified by stable ids of the form `CP-<group>-<kdf>-<hash>`, naming the group
+//! together with the encoding of its scalars and elements, the secret derivation and the
+//! transcript hash, and a registry mapping ids to implementations.
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::fmt;
+
+use rand_core::CryptoRngCore;
+
+use crate::{group::Group, multi::MultiChaumPedersen, transcript::Transcript};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    UnknownCiphersuite,
+    InvalidLength,
+    InvalidEncoding,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnknownCiphersuite => write!(f, "unknown ciphersuite"),
+            Error::InvalidLength => write!(f, "wrong number of values"),
+            Error::InvalidEncoding => write!(f, "value is not canonically encoded"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+
+/// Byte level interface of a ciphersuite, so that callers can handle every suite alike. Scalars
+/// and elements are in the fixed width encodings of the suite's group.
+pub trait Ciphersuite: Send + Sync {
+    fn id(&self) -> &'static str;
+
+    /// Digest of the group parameters and the generators, which the id does not pin down, so that
+    /// credentials can be told apart from ones made under other parameters of the same suite.
+    fn fingerprint(&self) -> [u8; 32];
+
+    /// Checks that `elements` are the canonical encodings of as many elements as the suite has
+    /// bases, as public values or commitments have to be.
+    fn check(&self, elements: &[Vec<u8>]) -> Result<(), Error>;
+
+    fn register(&self, realm: &str, user: &str, password: &str) -> Vec<Vec<u8>>;
+    fn commit(&self, rng: &mut dyn CryptoRngCore) -> (Vec<u8>, Vec<Vec<u8>>);
+    fn challenge(&self, rng: &mut dyn CryptoRngCore) -> Vec<u8>;
+    fn solve(&self, realm: &str, user: &str, password: &str, k: &[u8], c: &[u8]) -> Result<Vec<u8>, Error>;
+    fn verify(&self, ys: &[Vec<u8>], rs: &[Vec<u8>], c: &[u8], s: &[u8]) -> Result<bool, Error>;
+
+    /// Proof of possession `(rs, s)` of the credentials of `register`, to send along them.
+    fn prove_registration(&self, realm: &str, user: &str, password: &str, rng: &mut dyn CryptoRngCore) -> (Vec<Vec<u8>>, Vec<u8>);
+    fn verify_registration(&self, realm: &str, user: &str, ys: &[Vec<u8>], rs: &[Vec<u8>], s: &[u8]) -> Result<bool, Error>;
+
+    /// Non-interactive proof `(rs, s)` of knowledge of the secret of `password` under `context`,
+    /// such as a `session_context`.
+    fn prove(&self, realm: &str, user: &str, password: &str, context: &[u8], rng: &mut dyn CryptoRngCore) -> (Vec<Vec<u8>>, Vec<u8>);
+    fn verify_proof(&self, ys: &[Vec<u8>], rs: &[Vec<u8>], s: &[u8], context: &[u8]) -> Result<bool, Error>;
+}
+
+
+impl<G> MultiChaumPedersen<G> where G: Group + Send + Sync, G::Element: Send + Sync {
+    fn encode_scalar(&self, scalar: &G::Scalar) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.group().encode_scalar(scalar, &mut out);
+        out
+    }
+
+    fn encode_elements(&self, elements: &[G::Element]) -> Vec<Vec<u8>> {
+        elements.iter().map(|element| {
+            let mut out = Vec::new();
+            self.group().encode_element(element, &mut out);
+            out
+        }).collect()
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Result<G::Scalar, Error> {
+        self.group().decode_scalar(bytes).ok_or(Error::InvalidEncoding)
+    }
+
+    fn decode_elements(&self, elements: &[Vec<u8>]) -> Result<Vec<G::Element>, Error> {
+        if elements.len() != self.generators().len() {
+            return Err(Error::InvalidLength);
+        }
+        elements.iter()
+            .map(|bytes| self.group().decode_element(bytes).ok_or(Error::InvalidEncoding))
+            .collect()
+    }
+}
+
+impl<G> Ciphersuite for MultiChaumPedersen<G> where G: Group + Send + Sync, G::Element: Send + Sync {
+    fn id(&self) -> &'static str {
+        G::CIPHERSUITE
+    }
+
+    fn fingerprint(&self) -> [u8; 32] {
+        let mut transcript = Transcript::new(b"chaum-pedersen-parameters");
+        self.bind(&mut transcript, &[], &[]);
+        transcript.fingerprint()
+    }
+
+    fn check(&self, elements: &[Vec<u8>]) -> Result<(), Error> {
+        self.decode_elements(elements).map(|_| ())
+    }
+
+    fn register(&self, realm: &str, user: &str, password: &str) -> Vec<Vec<u8>> {
+        self.encode_elements(&MultiChaumPedersen::register(self, realm, user, password))
+    }
+
+    fn commit(&self, mut rng: &mut dyn CryptoRngCore) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let (k, rs) = self.commit_with_rng(&mut rng);
+        (self.encode_scalar(&k), self.encode_elements(&rs))
+    }
+
+    fn challenge(&self, mut rng: &mut dyn CryptoRngCore) -> Vec<u8> {
+        self.encode_scalar(&self.challenge_with_rng(&mut rng))
+    }
+
+    fn solve(&self, realm: &str, user: &str, password: &str, k: &[u8], c: &[u8]) -> Result<Vec<u8>, Error> {
+        let s = MultiChaumPedersen::solve(self, realm, user, password, &self.decode_scalar(k)?, &self.decode_scalar(c)?);
+        Ok(self.encode_scalar(&s))
+    }
+
+    fn verify(&self, ys: &[Vec<u8>], rs: &[Vec<u8>], c: &[u8], s: &[u8]) -> Result<bool, Error> {
+        Ok(MultiChaumPedersen::verify(
+            self,
+            &self.decode_elements(ys)?,
+            &self.decode_elements(rs)?,
+            &self.decode_scalar(c)?,
+            &self.decode_scalar(s)?,
+        ))
+    }
+
+    fn prove_registration(&self, realm: &str, user: &str, password: &str, mut rng: &mut dyn CryptoRngCore) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let (rs, s) = self.prove_registration_with_rng(realm, user, &self.secret(realm, user, password), &mut rng);
+        (self.encode_elements(&rs), self.encode_scalar(&s))
+    }
+
+    fn verify_registration(&self, realm: &str, user: &str, ys: &[Vec<u8>], rs: &[Vec<u8>], s: &[u8]) -> Result<bool, Error> {
+        Ok(MultiChaumPedersen::verify_registration(
+            self,
+            realm,
+            user,
+            &self.decode_elements(ys)?,
+            &self.decode_elements(rs)?,
+            &self.decode_scalar(s)?,
+        ))
+    }
+
+    fn prove(&self, realm: &str, user: &str, password: &str, context: &[u8], mut rng: &mut dyn CryptoRngCore) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let (rs, s) = self.prove_with_rng(&self.secret(realm, user, password), context, &mut rng);
+        (self.encode_elements(&rs), self.encode_scalar(&s))
+    }
+
+    fn verify_proof(&self, ys: &[Vec<u8>], rs: &[Vec<u8>], s: &[u8], context: &[u8]) -> Result<bool, Error> {
+        Ok(MultiChaumPedersen::verify_proof(
+            self,
+            &self.decode_elements(ys)?,
+            &self.decode_elements(rs)?,
+            &self.decode_scalar(s)?,
+            context,
+        ))
+    }
+}
+
+
+struct Entry {
+    suite: Box<dyn Ciphersuite>,
+    deprecated: bool,
+}
+
+
+/// Ciphersuites a deployment supports, by id. Deprecated suites keep verifying the users that
+/// registered with them but should not be offered for new registrations.
+#[derive(Default)]
+pub struct Registry {
+    suites: BTreeMap<&'static str, Entry>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `suite`, replacing a suite with the same id.
+    pub fn insert<C: Ciphersuite + 'static>(&mut self, suite: C) {
+        self.suites.insert(suite.id(), Entry { suite: Box::new(suite), deprecated: false });
+    }
+
+    pub fn deprecate(&mut self, id: &str) -> Result<(), Error> {
+        self.suites.get_mut(id).ok_or(Error::UnknownCiphersuite)?.deprecated = true;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Result<&dyn Ciphersuite, Error> {
+        self.suites.get(id).map(|entry| entry.suite.as_ref()).ok_or(Error::UnknownCiphersuite)
+    }
+
+    pub fn is_deprecated(&self, id: &str) -> bool {
+        self.suites.get(id).is_some_and(|entry| entry.deprecated)
+    }
+
+    /// Ids of all suites in lexicographic order, with whether they are deprecated.
+    pub fn ids(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        self.suites.iter().map(|(id, entry)| (*id, entry.deprecated))
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::vec;
+
+    use ::k256::{AffinePoint, Scalar};
+    use num_bigint::BigUint;
+
+    use super::*;
+    use crate::{group::{ExpGroup, K256Group}, multi::session_context};
+
+    const REALM: &str = "chaum-pedersen-auth";
+    const USER: &str = "testuser";
+
+    fn setup_registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.insert(MultiChaumPedersen::new(
+            ExpGroup::new(
+                BigUint::from(363967321904221003u64),
+                BigUint::from(7696033u64),
+                BigUint::from(165950041202038920u64),
+            ),
+            vec![BigUint::from(165950041202038920u64), BigUint::from(96429580695728554u64)],
+        ));
+        registry.insert(MultiChaumPedersen::new(
+            K256Group,
+            vec![
+                AffinePoint::GENERATOR,
+                AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(107211496160805127u64)),
+            ],
+        ));
+        registry
+    }
+
+    #[test]
+    fn test_ids() {
+        let mut registry = setup_registry();
+        assert_eq!(registry.ids().collect::<Vec<_>>(), [("CP-EXP-SHA256-SHA256", false), ("CP-K256-SHA256-SHA256", false)]);
+        registry.deprecate("CP-EXP-SHA256-SHA256").unwrap();
+        assert!(registry.is_deprecated("CP-EXP-SHA256-SHA256"));
+        assert_eq!(registry.deprecate("CP-EXP-ARGON2ID-SHA256"), Err(Error::UnknownCiphersuite));
+        assert!(registry.get("CP-EXP-ARGON2ID-SHA256").is_err());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let registry = setup_registry();
+        let exp = registry.get("CP-EXP-SHA256-SHA256").unwrap().fingerprint();
+        let k256 = registry.get("CP-K256-SHA256-SHA256").unwrap().fingerprint();
+        assert_ne!(exp, k256);
+        assert_eq!(k256, setup_registry().get("CP-K256-SHA256-SHA256").unwrap().fingerprint());
+        let other_h = MultiChaumPedersen::new(
+            K256Group,
+            vec![AffinePoint::GENERATOR, AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(7u64))],
+        );
+        assert_ne!(Ciphersuite::fingerprint(&other_h), k256);
+        let other_p = MultiChaumPedersen::new(
+            ExpGroup::new(
+                BigUint::from(363967321904221003u64),
+                BigUint::from(7696033u64) * 2u8,
+                BigUint::from(165950041202038920u64),
+            ),
+            vec![BigUint::from(165950041202038920u64), BigUint::from(96429580695728554u64)],
+        );
+        assert_ne!(Ciphersuite::fingerprint(&other_p), exp);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let registry = setup_registry();
+        for (id, _) in registry.ids() {
+            let suite = registry.get(id).unwrap();
+            let ys = suite.register(REALM, USER, "password");
+            suite.check(&ys).unwrap();
+            let (k, rs) = suite.commit(&mut rand::thread_rng());
+            let c = suite.challenge(&mut rand::thread_rng());
+            let s = suite.solve(REALM, USER, "password", &k, &c).unwrap();
+            assert_eq!(suite.verify(&ys, &rs, &c, &s), Ok(true));
+            let s = suite.solve(REALM, USER, "passw0rd", &k, &c).unwrap();
+            assert_eq!(suite.verify(&ys, &rs, &c, &s), Ok(false));
+
+            let (rs, s) = suite.prove_registration(REALM, USER, "password", &mut rand::thread_rng());
+            assert_eq!(suite.verify_registration(REALM, USER, &ys, &rs, &s), Ok(true));
+            assert_eq!(suite.verify_registration(REALM, "otheruser", &ys, &rs, &s), Ok(false));
+            assert_eq!(suite.verify_registration(REALM, USER, &ys, &rs[..1], &s), Err(Error::InvalidLength));
+
+            let context = session_context(REALM, USER, "token");
+            let (rs, s) = suite.prove(REALM, USER, "password", &context, &mut rand::thread_rng());
+            assert_eq!(suite.verify_proof(&ys, &rs, &s, &context), Ok(true));
+            assert_eq!(suite.verify_proof(&ys, &rs, &s, &session_context(REALM, USER, "other token")), Ok(false));
+        }
+    }
+
+    #[test]
+    fn test_rejects_non_canonical() {
+        let registry = setup_registry();
+        for (id, _) in registry.ids() {
+            let suite = registry.get(id).unwrap();
+            let mut ys = suite.register(REALM, USER, "password");
+            assert_eq!(suite.check(&ys[..1]), Err(Error::InvalidLength));
+            ys[0].insert(0, 0);
+            assert_eq!(suite.check(&ys), Err(Error::InvalidEncoding));
+            ys[0].remove(0);
+            ys[0].iter_mut().for_each(|byte| *byte = 0xff);
+            assert_eq!(suite.check(&ys), Err(Error::InvalidEncoding));
+        }
+    }
+}
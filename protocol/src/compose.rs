@@ -0,0 +1,264 @@
+use alloc::vec::Vec;
+
+use rand::{CryptoRng, RngCore};
+
+use crate::{group::Group, multi::MultiChaumPedersen};
+
+
+/// Public part of one branch of a composed proof: the protocol it is proven in and the public
+/// values `y_i` of the credential.
+#[derive(Clone)]
+pub struct Statement<G: Group> {
+    pub protocol: MultiChaumPedersen<G>,
+    pub ys: Vec<G::Element>,
+}
+
+impl<G: Group> Statement<G> {
+    pub fn new(protocol: MultiChaumPedersen<G>, ys: Vec<G::Element>) -> Self {
+        Self { protocol, ys }
+    }
+}
+
+
+/// Proof of knowledge of the secrets of every statement. Each branch runs its own commitment and
+/// all of them answer the same challenge.
+#[derive(Clone)]
+pub struct And<G: Group> {
+    group: G,
+    statements: Vec<Statement<G>>,
+}
+
+impl<G: Group> And<G> {
+    pub fn new(group: G, statements: Vec<Statement<G>>) -> Self {
+        Self { group, statements }
+    }
+
+    pub fn statements(&self) -> &[Statement<G>] {
+        &self.statements
+    }
+
+    #[cfg(feature = "std")]
+    pub fn commit(&self) -> (Vec<G::Scalar>, Vec<Vec<G::Element>>) {
+        self.commit_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn commit_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (Vec<G::Scalar>, Vec<Vec<G::Element>>) {
+        self.statements.iter().map(|statement| statement.protocol.commit_with_rng(rng)).unzip()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn challenge(&self) -> G::Scalar {
+        self.challenge_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> G::Scalar {
+        self.group.random_scalar(rng)
+    }
+
+    /// Takes the secret of every statement, in order.
+    pub fn solve(&self, xs: &[G::Scalar], ks: &[G::Scalar], c: &G::Scalar) -> Vec<G::Scalar> {
+        self.statements.iter().zip(xs).zip(ks)
+            .map(|((statement, x), k)| statement.protocol.solve_secret(x, k, c))
+            .collect()
+    }
+
+    pub fn verify(&self, rs: &[Vec<G::Element>], c: &G::Scalar, ss: &[G::Scalar]) -> bool {
+        rs.len() == self.statements.len() &&
+        ss.len() == self.statements.len() &&
+        self.statements.iter().zip(rs).zip(ss).all(|((statement, r), s)| {
+            statement.protocol.verify(&statement.ys, r, c, s)
+        })
+    }
+}
+
+
+/// Commitments of every branch of a composed proof, one element per base.
+type Commitments<G> = Vec<Vec<<G as Group>::Element>>;
+
+
+/// Prover state of an OR proof between commitment and response. It holds the nonce of the known
+/// branch and the simulated challenges and responses of the others, and must stay secret.
+pub struct OrCommitment<G: Group> {
+    known: usize,
+    k: G::Scalar,
+    cs: Vec<G::Scalar>,
+    ss: Vec<G::Scalar>,
+}
+
+
+/// Cramer-Damgård-Schoenmakers proof of knowledge of the secret of at least one statement,
+/// without revealing which. The prover simulates every other branch with a challenge of its
+/// choosing and the branch challenges have to add up to the verifier's challenge, so only one of
+/// them can be picked after the commitments are fixed.
+#[derive(Clone)]
+pub struct Or<G: Group> {
+    group: G,
+    statements: Vec<Statement<G>>,
+}
+
+impl<G: Group> Or<G> {
+    pub fn new(group: G, statements: Vec<Statement<G>>) -> Self {
+        Self { group, statements }
+    }
+
+    pub fn statements(&self) -> &[Statement<G>] {
+        &self.statements
+    }
+
+    /// Commits to every branch, `known` being the index of the statement the prover has the
+    /// secret for, none if there is no such statement.
+    #[cfg(feature = "std")]
+    pub fn commit(&self, known: usize) -> Option<(OrCommitment<G>, Commitments<G>)> {
+        self.commit_with_rng(known, &mut rand::thread_rng())
+    }
+
+    pub fn commit_with_rng<R: RngCore + CryptoRng>(&self, known: usize, rng: &mut R) -> Option<(OrCommitment<G>, Commitments<G>)> {
+        if known >= self.statements.len() {
+            return None;
+        }
+        let mut k = None;
+        let mut cs = Vec::with_capacity(self.statements.len());
+        let mut ss = Vec::with_capacity(self.statements.len());
+        let mut rs = Vec::with_capacity(self.statements.len());
+        for (i, statement) in self.statements.iter().enumerate() {
+            let c = self.group.random_scalar(rng);
+            let s = self.group.random_scalar(rng);
+            if i == known {
+                let (nonce, r) = statement.protocol.commit_with_rng(rng);
+                k = Some(nonce);
+                rs.push(r);
+            } else {
+                rs.push(statement.protocol.simulate(&statement.ys, &c, &s));
+            }
+            cs.push(c);
+            ss.push(s);
+        }
+        Some((OrCommitment { known, k: k?, cs, ss }, rs))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn challenge(&self) -> G::Scalar {
+        self.challenge_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> G::Scalar {
+        self.group.random_scalar(rng)
+    }
+
+    /// Answers the challenge with the secret of the known branch, returning the challenge and
+    /// response of every branch.
+    pub fn solve(&self, commitment: OrCommitment<G>, x: &G::Scalar, c: &G::Scalar) -> (Vec<G::Scalar>, Vec<G::Scalar>) {
+        let OrCommitment { known, k, mut cs, mut ss } = commitment;
+        let simulated = cs.iter().enumerate()
+            .filter(|(i, _)| *i != known)
+            .fold(self.group.zero(), |sum, (_, c)| self.group.add(&sum, c));
+        cs[known] = self.group.sub(c, &simulated);
+        ss[known] = self.statements[known].protocol.solve_secret(x, &k, &cs[known]);
+        (cs, ss)
+    }
+
+    pub fn verify(&self, rs: &[Vec<G::Element>], c: &G::Scalar, cs: &[G::Scalar], ss: &[G::Scalar]) -> bool {
+        if rs.len() != self.statements.len() || cs.len() != self.statements.len() || ss.len() != self.statements.len() {
+            return false;
+        }
+        let sum = cs.iter().fold(self.group.zero(), |sum, c| self.group.add(&sum, c));
+        sum == *c &&
+        self.statements.iter().zip(rs).zip(cs.iter().zip(ss)).all(|((statement, r), (c, s))| {
+            statement.protocol.verify(&statement.ys, r, c, s)
+        })
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::vec;
+
+    use ::k256::{AffinePoint, Scalar};
+    use num_bigint::BigUint;
+
+    use super::*;
+    use crate::group::{ExpGroup, K256Group};
+
+    const REALM: &str = "chaum-pedersen-auth";
+
+    fn statements<G: Group>(protocol: &MultiChaumPedersen<G>) -> Vec<Statement<G>> {
+        vec![
+            Statement::new(protocol.clone(), protocol.register(REALM, "alice", "password")),
+            Statement::new(protocol.clone(), protocol.register(REALM, "alice", "new password")),
+        ]
+    }
+
+    fn test_and<G: Group>(protocol: MultiChaumPedersen<G>) {
+        let and = And::new(protocol.group().clone(), statements(&protocol));
+        let xs = [protocol.secret(REALM, "alice", "password"), protocol.secret(REALM, "alice", "new password")];
+        let (ks, rs) = and.commit();
+        let c = and.challenge();
+        let ss = and.solve(&xs, &ks, &c);
+        assert!(and.verify(&rs, &c, &ss));
+
+        let xs = [xs[0].clone(), xs[0].clone()];
+        let ss = and.solve(&xs, &ks, &c);
+        assert!(!and.verify(&rs, &c, &ss));
+    }
+
+    fn test_or<G: Group>(protocol: MultiChaumPedersen<G>) {
+        let or = Or::new(protocol.group().clone(), statements(&protocol));
+        for (known, password) in [(0, "password"), (1, "new password")] {
+            let x = protocol.secret(REALM, "alice", password);
+            let (commitment, rs) = or.commit(known).unwrap();
+            let c = or.challenge();
+            let (cs, ss) = or.solve(commitment, &x, &c);
+            assert!(or.verify(&rs, &c, &cs, &ss));
+            assert!(!or.verify(&rs, &or.challenge(), &cs, &ss));
+        }
+
+        let x = protocol.secret(REALM, "alice", "wrong password");
+        let (commitment, rs) = or.commit(0).unwrap();
+        let c = or.challenge();
+        let (cs, ss) = or.solve(commitment, &x, &c);
+        assert!(!or.verify(&rs, &c, &cs, &ss));
+        assert!(or.commit(2).is_none());
+    }
+
+    fn exp_protocol() -> MultiChaumPedersen<ExpGroup> {
+        MultiChaumPedersen::new(
+            ExpGroup::new(
+                BigUint::from(363967321904221003u64),
+                BigUint::from(7696033u64),
+                BigUint::from(165950041202038920u64),
+            ),
+            vec![BigUint::from(165950041202038920u64), BigUint::from(96429580695728554u64)],
+        )
+    }
+
+    fn k256_protocol() -> MultiChaumPedersen<K256Group> {
+        MultiChaumPedersen::new(
+            K256Group,
+            vec![
+                AffinePoint::GENERATOR,
+                AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(107211496160805127u64)),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_exp_and() {
+        test_and(exp_protocol());
+    }
+
+    #[test]
+    fn test_exp_or() {
+        test_or(exp_protocol());
+    }
+
+    #[test]
+    fn test_k256_and() {
+        test_and(k256_protocol());
+    }
+
+    #[test]
+    fn test_k256_or() {
+        test_or(k256_protocol());
+    }
+}
@@ -0,0 +1,252 @@
+use alloc::vec::Vec;
+use core::fmt::Debug;
+
+use k256::{
+    AffinePoint,
+    ProjectivePoint,
+    Scalar,
+    U256,
+    elliptic_curve::{group::GroupEncoding, ops::Reduce, Field, PrimeField},
+};
+use num_bigint::{BigUint, RandBigInt};
+use rand::{CryptoRng, RngCore};
+
+use crate::derive_secret;
+
+
+/// Prime order group the generalised protocols are built on.
+///
+/// Encodings are fixed width so that transcripts are unambiguous: exponentiation scalars and
+/// elements are reduced modulo `q` and `p` and encoded big endian, left padded to the byte length
+/// of `q` and `p` respectively, k256 scalars are 32 byte big endian and k256 elements are 33 byte
+/// SEC1 compressed points. The exponentiation parameters are `p`, `q` and `g`, each padded to the
+/// byte length of `p`, k256 has none as the ciphersuite fixes the curve.
+pub trait Group: Clone {
+    type Scalar: Clone + PartialEq + Debug;
+    type Element: Clone + PartialEq + Debug;
+
+    /// Ciphersuite id the secret derivation is bound to, shared with the two base variant of the
+    /// same group so that one password yields the same secret in both.
+    const CIPHERSUITE: &'static str;
+
+    fn generator(&self) -> Self::Element;
+    fn zero(&self) -> Self::Scalar;
+    fn scalar_from_u64(&self, value: u64) -> Self::Scalar;
+    fn scalar_from_digest(&self, digest: &[u8; 32]) -> Self::Scalar;
+    fn random_scalar<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Self::Scalar;
+
+    fn add(&self, a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+    fn sub(&self, a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+    fn mul(&self, a: &Self::Scalar, b: &Self::Scalar) -> Self::Scalar;
+    fn invert(&self, a: &Self::Scalar) -> Option<Self::Scalar>;
+
+    /// Group operation, written multiplicatively.
+    fn op(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+    fn exp(&self, base: &Self::Element, exponent: &Self::Scalar) -> Self::Element;
+
+    fn encode_parameters(&self, out: &mut Vec<u8>);
+    fn encode_scalar(&self, scalar: &Self::Scalar, out: &mut Vec<u8>);
+    fn encode_element(&self, element: &Self::Element, out: &mut Vec<u8>);
+
+    /// Inverse of `encode_scalar`, rejecting anything but the canonical encoding.
+    fn decode_scalar(&self, bytes: &[u8]) -> Option<Self::Scalar>;
+    /// Inverse of `encode_element`, rejecting anything but the canonical encoding of an element
+    /// of the prime order group other than the identity.
+    fn decode_element(&self, bytes: &[u8]) -> Option<Self::Element>;
+
+    fn secret(&self, realm: &str, user: &str, password: &str) -> Self::Scalar {
+        self.scalar_from_digest(&derive_secret(Self::CIPHERSUITE, realm, user, password))
+    }
+}
+
+
+/// Subgroup of order `q` of the multiplicative group modulo `p`, generated by `g`.
+#[derive(Clone, Debug)]
+pub struct ExpGroup {
+    p: BigUint,
+    q: BigUint,
+    g: BigUint,
+}
+
+impl ExpGroup {
+    pub fn new(p: BigUint, q: BigUint, g: BigUint) -> Self {
+        Self { p, q, g }
+    }
+
+    /// Whether `element` is an element of the subgroup of order `q` other than the identity.
+    pub fn contains(&self, element: &BigUint) -> bool {
+        let one = BigUint::from(1u8);
+        *element > one && *element < self.p && element.modpow(&self.q, &self.p) == one
+    }
+
+    /// Appends `value` left padded to the byte length of `modulus`, returning false and appending
+    /// nothing if it is wider.
+    fn encode_padded(value: &BigUint, modulus: &BigUint, out: &mut Vec<u8>) -> bool {
+        let bytes = value.to_bytes_be();
+        let Some(padding) = (modulus.bits().div_ceil(8) as usize).checked_sub(bytes.len()) else {
+            return false;
+        };
+        out.resize(out.len() + padding, 0);
+        out.extend_from_slice(&bytes);
+        true
+    }
+
+    fn decode_padded(bytes: &[u8], modulus: &BigUint) -> Option<BigUint> {
+        let value = BigUint::from_bytes_be(bytes);
+        (bytes.len() == modulus.bits().div_ceil(8) as usize && &value < modulus).then_some(value)
+    }
+}
+
+impl Group for ExpGroup {
+    type Scalar = BigUint;
+    type Element = BigUint;
+
+    const CIPHERSUITE: &'static str = "CP-EXP-SHA256-SHA256";
+
+    fn generator(&self) -> BigUint {
+        self.g.clone()
+    }
+
+    fn zero(&self) -> BigUint {
+        BigUint::from(0u8)
+    }
+
+    fn scalar_from_u64(&self, value: u64) -> BigUint {
+        BigUint::from(value) % &self.q
+    }
+
+    fn scalar_from_digest(&self, digest: &[u8; 32]) -> BigUint {
+        BigUint::from_bytes_be(digest) % &self.q
+    }
+
+    fn random_scalar<R: RngCore + CryptoRng>(&self, rng: &mut R) -> BigUint {
+        rng.gen_biguint_below(&self.q)
+    }
+
+    fn add(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + b) % &self.q
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + &self.q - b % &self.q) % &self.q
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a * b % &self.q
+    }
+
+    fn invert(&self, a: &BigUint) -> Option<BigUint> {
+        let a = a % &self.q;
+        (a != BigUint::from(0u8)).then(|| a.modpow(&(&self.q - 2u8), &self.q))
+    }
+
+    fn op(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a * b % &self.p
+    }
+
+    fn exp(&self, base: &BigUint, exponent: &BigUint) -> BigUint {
+        base.modpow(exponent, &self.p)
+    }
+
+    fn encode_parameters(&self, out: &mut Vec<u8>) {
+        // `q` or `g` wider than `p` make an invalid group, see `ChaumPedersen::check_parameters`,
+        // whose parameters are still bound, unpadded.
+        for value in [&self.p, &self.q, &self.g] {
+            if !Self::encode_padded(value, &self.p, out) {
+                out.extend_from_slice(&value.to_bytes_be());
+            }
+        }
+    }
+
+    fn encode_scalar(&self, scalar: &BigUint, out: &mut Vec<u8>) {
+        Self::encode_padded(&(scalar % &self.q), &self.q, out);
+    }
+
+    fn encode_element(&self, element: &BigUint, out: &mut Vec<u8>) {
+        Self::encode_padded(&(element % &self.p), &self.p, out);
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Option<BigUint> {
+        Self::decode_padded(bytes, &self.q)
+    }
+
+    fn decode_element(&self, bytes: &[u8]) -> Option<BigUint> {
+        Self::decode_padded(bytes, &self.p).filter(|element| self.contains(element))
+    }
+}
+
+
+/// The secp256k1 group generated by its standard base point.
+#[derive(Clone, Debug, Default)]
+pub struct K256Group;
+
+impl Group for K256Group {
+    type Scalar = Scalar;
+    type Element = AffinePoint;
+
+    const CIPHERSUITE: &'static str = "CP-K256-SHA256-SHA256";
+
+    fn generator(&self) -> AffinePoint {
+        AffinePoint::GENERATOR
+    }
+
+    fn zero(&self) -> Scalar {
+        Scalar::ZERO
+    }
+
+    fn scalar_from_u64(&self, value: u64) -> Scalar {
+        Scalar::from(value)
+    }
+
+    fn scalar_from_digest(&self, digest: &[u8; 32]) -> Scalar {
+        <Scalar as Reduce<U256>>::reduce_bytes(&(*digest).into())
+    }
+
+    fn random_scalar<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Scalar {
+        Scalar::random(rng)
+    }
+
+    fn add(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a + b
+    }
+
+    fn sub(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a - b
+    }
+
+    fn mul(&self, a: &Scalar, b: &Scalar) -> Scalar {
+        a * b
+    }
+
+    fn invert(&self, a: &Scalar) -> Option<Scalar> {
+        a.invert().into()
+    }
+
+    fn op(&self, a: &AffinePoint, b: &AffinePoint) -> AffinePoint {
+        AffinePoint::from(ProjectivePoint::from(*a) + b)
+    }
+
+    fn exp(&self, base: &AffinePoint, exponent: &Scalar) -> AffinePoint {
+        AffinePoint::from(*base * exponent)
+    }
+
+    fn encode_parameters(&self, _out: &mut Vec<u8>) {}
+
+    fn encode_scalar(&self, scalar: &Scalar, out: &mut Vec<u8>) {
+        out.extend_from_slice(&scalar.to_repr());
+    }
+
+    fn encode_element(&self, element: &AffinePoint, out: &mut Vec<u8>) {
+        out.extend_from_slice(&element.to_bytes());
+    }
+
+    fn decode_scalar(&self, bytes: &[u8]) -> Option<Scalar> {
+        let bytes: [u8; 32] = bytes.try_into().ok()?;
+        Scalar::from_repr(bytes.into()).into()
+    }
+
+    fn decode_element(&self, bytes: &[u8]) -> Option<AffinePoint> {
+        let bytes: [u8; 33] = bytes.try_into().ok()?;
+        Option::<AffinePoint>::from(AffinePoint::from_bytes(&bytes.into())).filter(|element| *element != AffinePoint::IDENTITY)
+    }
+}
@@ -1,15 +1,61 @@
-use std::{
-    hash::{Hash, Hasher},
-    collections::hash_map::DefaultHasher
-};
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "alloc")]
+pub mod ciphersuite;
+#[cfg(feature = "alloc")]
+pub mod compose;
+#[cfg(feature = "alloc")]
+pub mod group;
+#[cfg(feature = "alloc")]
+pub mod multi;
+#[cfg(feature = "alloc")]
+pub mod shamir;
+pub mod transcript;
 
 use k256::{
     AffinePoint,
     Scalar,
+    U256,
+    elliptic_curve::{ops::Reduce, Field},
 };
-use num_bigint::BigUint;
+#[cfg(feature = "alloc")]
+use num_bigint::{BigUint, RandBigInt};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "alloc")]
+pub use ciphersuite::{Ciphersuite, Registry};
+#[cfg(feature = "alloc")]
+pub use compose::{And, Or, OrCommitment, Statement};
+#[cfg(feature = "alloc")]
+pub use group::{ExpGroup, Group, K256Group};
+#[cfg(feature = "alloc")]
+pub use multi::{change_context, registration_context, session_context, MultiChaumPedersen, MultiChaumPedersenExp, MultiChaumPedersenK256};
+#[cfg(feature = "alloc")]
+pub use shamir::{Shamir, Share, Sharing};
+pub use transcript::Transcript;
+
+
+/// Derives the secret digest `SHA-256(len(ciphersuite) || ciphersuite || len(realm) || realm ||
+/// len(user) || user || len(password) || password)`, where every length is the 4 byte big endian
+/// byte length of the following utf-8 string. Binding the ciphersuite, the server realm and the
+/// username makes the same password yield unrelated secrets across users and deployments.
+fn derive_secret(ciphersuite: &str, realm: &str, user: &str, password: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for field in [ciphersuite, realm, user, password] {
+        hasher.update((field.len() as u32).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hasher.finalize().into()
+}
 
 
+#[cfg(feature = "alloc")]
 #[derive(Clone)]
 pub struct ChaumPedersen {
     p: BigUint,
@@ -18,32 +64,77 @@ pub struct ChaumPedersen {
     h: BigUint,
 }
 
+#[cfg(feature = "alloc")]
 impl ChaumPedersen {
+    pub const CIPHERSUITE: &'static str = "CP-EXP-SHA256-SHA256";
+
     pub fn new(p: BigUint, q: BigUint, g: BigUint, h: BigUint) -> Self {
         Self { p, q, g, h }
     }
 
-    pub fn register(&self, password: &str) -> (BigUint, BigUint) {
-        let mut hasher = DefaultHasher::new();
-        password.hash(&mut hasher);
-        let x = hasher.finish().into();
-        (self.g.modpow(&x, &self.p), self.h.modpow(&x, &self.p))
+    /// Whether `q` is below `p` and `g` and `h` are elements of the subgroup of order `q` modulo
+    /// `p` other than the identity, which the constructor leaves to the caller.
+    pub fn check_parameters(&self) -> bool {
+        let group = ExpGroup::new(self.p.clone(), self.q.clone(), self.g.clone());
+        self.q < self.p && group.contains(&self.g) && group.contains(&self.h)
+    }
+
+    fn secret(&self, realm: &str, user: &str, password: &str) -> BigUint {
+        BigUint::from_bytes_be(&derive_secret(Self::CIPHERSUITE, realm, user, password)) % &self.q
+    }
+
+    fn public(&self, x: &BigUint) -> (BigUint, BigUint) {
+        (self.g.modpow(x, &self.p), self.h.modpow(x, &self.p))
     }
 
+    pub fn register(&self, realm: &str, user: &str, password: &str) -> (BigUint, BigUint) {
+        self.public(&self.secret(realm, user, password))
+    }
+
+    /// Random device secret `x_dev` for two factor credentials.
+    #[cfg(feature = "std")]
+    pub fn device_key(&self) -> BigUint {
+        self.device_key_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn device_key_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> BigUint {
+        rng.gen_biguint_below(&self.q)
+    }
+
+    /// Credentials for the combined secret `x = x_pw + x_dev`, so that one run proves knowledge
+    /// of both the password and the device secret. They verify with `verify` as usual.
+    pub fn register_with_device(&self, realm: &str, user: &str, password: &str, device_key: &BigUint) -> (BigUint, BigUint) {
+        self.public(&((self.secret(realm, user, password) + device_key) % &self.q))
+    }
+
+    #[cfg(feature = "std")]
     pub fn commit(&self) -> (BigUint, BigUint, BigUint) {
-        let k: BigUint = rand::random::<u64>().into();
+        self.commit_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn commit_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (BigUint, BigUint, BigUint) {
+        let k = rng.gen_biguint_below(&self.q);
         (k.clone(), self.g.modpow(&k, &self.p), self.h.modpow(&k, &self.p))
     }
 
+    #[cfg(feature = "std")]
     pub fn challenge(&self) -> BigUint {
-        rand::random::<u64>() % &self.q
+        self.challenge_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> BigUint {
+        rng.gen_biguint_below(&self.q)
+    }
+
+    pub fn solve(&self, realm: &str, user: &str, password: &str, k: &BigUint, c: &BigUint) -> BigUint {
+        self.respond(&self.secret(realm, user, password), k, c)
     }
 
-    pub fn solve(&self, password: &str, k: &BigUint, c: &BigUint) -> BigUint {
-        let mut hasher = DefaultHasher::new();
-        password.hash(&mut hasher);
-        let x: BigUint = hasher.finish().into();
+    pub fn solve_with_device(&self, realm: &str, user: &str, password: &str, device_key: &BigUint, k: &BigUint, c: &BigUint) -> BigUint {
+        self.respond(&((self.secret(realm, user, password) + device_key) % &self.q), k, c)
+    }
 
+    fn respond(&self, x: &BigUint, k: &BigUint, c: &BigUint) -> BigUint {
         if k >= &self.q {
             k - (c * x) % &self.q
         } else {
@@ -55,6 +146,47 @@ impl ChaumPedersen {
         r1 == &(self.g.modpow(s, &self.p) * y1.modpow(c, &self.p) % &self.p) &&
         r2 == &(self.h.modpow(s, &self.p) * y2.modpow(c, &self.p) % &self.p)
     }
+
+    /// Proof of possession `(r1, r2, s)` of the credentials of `register`, to send along them.
+    #[cfg(feature = "std")]
+    pub fn prove_registration(&self, realm: &str, user: &str, password: &str) -> (BigUint, BigUint, BigUint) {
+        self.prove_registration_with_rng(realm, user, password, &mut rand::thread_rng())
+    }
+
+    pub fn prove_registration_with_rng<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, password: &str, rng: &mut R) -> (BigUint, BigUint, BigUint) {
+        self.prove_possession(realm, user, &self.secret(realm, user, password), rng)
+    }
+
+    /// Proof of possession of the credentials of `register_with_device`.
+    #[cfg(feature = "std")]
+    pub fn prove_registration_with_device(&self, realm: &str, user: &str, password: &str, device_key: &BigUint) -> (BigUint, BigUint, BigUint) {
+        self.prove_registration_with_device_with_rng(realm, user, password, device_key, &mut rand::thread_rng())
+    }
+
+    pub fn prove_registration_with_device_with_rng<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, password: &str, device_key: &BigUint, rng: &mut R) -> (BigUint, BigUint, BigUint) {
+        let x = (self.secret(realm, user, password) + device_key) % &self.q;
+        self.prove_possession(realm, user, &x, rng)
+    }
+
+    fn prove_possession<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, x: &BigUint, rng: &mut R) -> (BigUint, BigUint, BigUint) {
+        let (rs, s) = self.as_multi().prove_registration_with_rng(realm, user, x, rng);
+        let [r1, r2]: [BigUint; 2] = rs.try_into().expect("two bases");
+        (r1, r2, s)
+    }
+
+    /// Checks that `(y1, y2)` are valid credentials with a common discrete log the client knows.
+    pub fn verify_registration(&self, realm: &str, user: &str, y1: &BigUint, y2: &BigUint, proof: &(BigUint, BigUint, BigUint)) -> bool {
+        let (r1, r2, s) = proof;
+        self.as_multi().verify_registration(realm, user, &[y1.clone(), y2.clone()], &[r1.clone(), r2.clone()], s)
+    }
+
+    /// The same protocol as a multi-base proof over `[g, h]`, accepting the same credentials.
+    pub fn as_multi(&self) -> MultiChaumPedersenExp {
+        MultiChaumPedersen::new(
+            ExpGroup::new(self.p.clone(), self.q.clone(), self.g.clone()),
+            alloc::vec![self.g.clone(), self.h.clone()],
+        )
+    }
 }
 
 
@@ -65,6 +197,8 @@ pub struct ChaumPedersenK256 {
 }
 
 impl ChaumPedersenK256 {
+    pub const CIPHERSUITE: &'static str = "CP-K256-SHA256-SHA256";
+
     pub fn new(h_offset: u64) -> Self {
         Self {
             g: AffinePoint::GENERATOR,
@@ -72,26 +206,60 @@ impl ChaumPedersenK256 {
         }
     }
 
-    pub fn register(&self, password: &str) -> (AffinePoint, AffinePoint) {
-        let mut hasher = DefaultHasher::new();
-        password.hash(&mut hasher);
-        let x = Scalar::from(hasher.finish());
+    fn secret(&self, realm: &str, user: &str, password: &str) -> Scalar {
+        <Scalar as Reduce<U256>>::reduce_bytes(&derive_secret(Self::CIPHERSUITE, realm, user, password).into())
+    }
+
+    fn public(&self, x: &Scalar) -> (AffinePoint, AffinePoint) {
         (AffinePoint::from(self.g * x), AffinePoint::from(self.h * x))
     }
 
+    pub fn register(&self, realm: &str, user: &str, password: &str) -> (AffinePoint, AffinePoint) {
+        self.public(&self.secret(realm, user, password))
+    }
+
+    /// Random device secret `x_dev` for two factor credentials.
+    #[cfg(feature = "std")]
+    pub fn device_key(&self) -> Scalar {
+        self.device_key_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn device_key_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Scalar {
+        Scalar::random(rng)
+    }
+
+    /// Credentials for the combined secret `x = x_pw + x_dev`, so that one run proves knowledge
+    /// of both the password and the device secret. They verify with `verify` as usual.
+    pub fn register_with_device(&self, realm: &str, user: &str, password: &str, device_key: &Scalar) -> (AffinePoint, AffinePoint) {
+        self.public(&(self.secret(realm, user, password) + device_key))
+    }
+
+    #[cfg(feature = "std")]
     pub fn commit(&self) -> (Scalar, AffinePoint, AffinePoint) {
-        let k = Scalar::from(rand::random::<u64>());
-        (k.clone(), AffinePoint::from(self.g * k), AffinePoint::from(self.h * k))
+        self.commit_with_rng(&mut rand::thread_rng())
     }
 
+    pub fn commit_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (Scalar, AffinePoint, AffinePoint) {
+        let k = Scalar::random(rng);
+        (k, AffinePoint::from(self.g * k), AffinePoint::from(self.h * k))
+    }
+
+    #[cfg(feature = "std")]
     pub fn challenge(&self) -> Scalar {
-        Scalar::from(rand::random::<u64>())
+        self.challenge_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Scalar {
+        Scalar::random(rng)
+    }
+
+    pub fn solve(&self, realm: &str, user: &str, password: &str, k: &Scalar, c: &Scalar) -> Scalar {
+        let x = self.secret(realm, user, password);
+        *k - c * &x
     }
 
-    pub fn solve(&self, password: &str, k: &Scalar, c: &Scalar) -> Scalar {
-        let mut hasher = DefaultHasher::new();
-        password.hash(&mut hasher);
-        let x = Scalar::from(hasher.finish());
+    pub fn solve_with_device(&self, realm: &str, user: &str, password: &str, device_key: &Scalar, k: &Scalar, c: &Scalar) -> Scalar {
+        let x = self.secret(realm, user, password) + device_key;
         *k - c * &x
     }
 
@@ -99,13 +267,60 @@ impl ChaumPedersenK256 {
         *r1 == self.g * s + *y1 * *c &&
         *r2 == self.h * s + *y2 * *c
     }
+
+    /// Proof of possession `(r1, r2, s)` of the credentials of `register`, to send along them.
+    #[cfg(feature = "std")]
+    pub fn prove_registration(&self, realm: &str, user: &str, password: &str) -> (AffinePoint, AffinePoint, Scalar) {
+        self.prove_registration_with_rng(realm, user, password, &mut rand::thread_rng())
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn prove_registration_with_rng<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, password: &str, rng: &mut R) -> (AffinePoint, AffinePoint, Scalar) {
+        self.prove_possession(realm, user, &self.secret(realm, user, password), rng)
+    }
+
+    /// Proof of possession of the credentials of `register_with_device`.
+    #[cfg(feature = "std")]
+    pub fn prove_registration_with_device(&self, realm: &str, user: &str, password: &str, device_key: &Scalar) -> (AffinePoint, AffinePoint, Scalar) {
+        self.prove_registration_with_device_with_rng(realm, user, password, device_key, &mut rand::thread_rng())
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn prove_registration_with_device_with_rng<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, password: &str, device_key: &Scalar, rng: &mut R) -> (AffinePoint, AffinePoint, Scalar) {
+        let x = self.secret(realm, user, password) + device_key;
+        self.prove_possession(realm, user, &x, rng)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn prove_possession<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, x: &Scalar, rng: &mut R) -> (AffinePoint, AffinePoint, Scalar) {
+        let (rs, s) = self.as_multi().prove_registration_with_rng(realm, user, x, rng);
+        (rs[0], rs[1], s)
+    }
+
+    /// Checks that `(y1, y2)` are valid credentials with a common discrete log the client knows.
+    #[cfg(feature = "alloc")]
+    pub fn verify_registration(&self, realm: &str, user: &str, y1: &AffinePoint, y2: &AffinePoint, proof: &(AffinePoint, AffinePoint, Scalar)) -> bool {
+        let (r1, r2, s) = proof;
+        self.as_multi().verify_registration(realm, user, &[*y1, *y2], &[*r1, *r2], s)
+    }
+
+    /// The same protocol as a multi-base proof over `[g, h]`, accepting the same credentials.
+    #[cfg(feature = "alloc")]
+    pub fn as_multi(&self) -> MultiChaumPedersenK256 {
+        MultiChaumPedersen::new(K256Group, alloc::vec![self.g, self.h])
+    }
 }
 
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
     use super::*;
 
+    const REALM: &str = "chaum-pedersen-auth";
+    const USER: &str = "testuser";
+
     mod exponent {
         use super::*;
 
@@ -121,9 +336,40 @@ mod tests {
         #[test]
         fn test_register() {
             let protocol = setup_protocol();
-            let (y1, y2) = protocol.register("password");
-            assert_eq!(y1, BigUint::from(180020373440730202u64));
-            assert_eq!(y2, BigUint::from(138713557362284185u64));
+            let (y1, y2) = protocol.register(REALM, USER, "password");
+            assert_eq!(y1, BigUint::from(348596868091682774u64));
+            assert_eq!(y2, BigUint::from(289175850390861676u64));
+        }
+
+        #[test]
+        fn test_register_domain_separation() {
+            let protocol = setup_protocol();
+            let y = protocol.register(REALM, USER, "password");
+            assert_ne!(y, protocol.register(REALM, "otheruser", "password"));
+            assert_ne!(y, protocol.register("other-realm", USER, "password"));
+        }
+
+        #[test]
+        fn test_check_parameters() {
+            assert!(setup_protocol().check_parameters());
+            let (p, q, g, h) = (363967321904221003u64, 7696033u64, 165950041202038920u64, 96429580695728554u64);
+            let protocol = |p: u64, q: u64, g: u64, h: u64| ChaumPedersen::new(BigUint::from(p), BigUint::from(q), BigUint::from(g), BigUint::from(h));
+            assert!(!protocol(p, q, g + p, h).check_parameters());
+            assert!(!protocol(p, q, g, 1).check_parameters());
+            assert!(!protocol(p, q, g, 2).check_parameters());
+            assert!(!protocol(p, p + 1, g, h).check_parameters());
+        }
+
+        #[test]
+        fn test_wide_parameters() {
+            let protocol = ChaumPedersen::new(BigUint::from(23u8), BigUint::from(1u32 << 20), BigUint::from(4u8), BigUint::from(9u8));
+            assert!(!protocol.check_parameters());
+            let mut parameters = alloc::vec::Vec::new();
+            protocol.as_multi().group().encode_parameters(&mut parameters);
+            assert_eq!(parameters, [23, 16, 0, 0, 4]);
+            let (y1, y2) = protocol.register(REALM, USER, "password");
+            let proof = protocol.prove_registration(REALM, USER, "password");
+            protocol.verify_registration(REALM, USER, &y1, &y2, &proof);
         }
 
         #[test]
@@ -134,6 +380,15 @@ mod tests {
             assert_eq!(r2, BigUint::from(96429580695728554u64).modpow(&k, &BigUint::from(363967321904221003u64)));
         }
 
+        #[test]
+        fn test_commit_with_rng() {
+            let protocol = setup_protocol();
+            let (k1, r1, _) = protocol.commit_with_rng(&mut StdRng::seed_from_u64(42));
+            let (k2, r2, _) = protocol.commit_with_rng(&mut StdRng::seed_from_u64(42));
+            assert_eq!(k1, k2);
+            assert_eq!(r1, r2);
+        }
+
         #[test]
         fn test_challenge() {
             let protocol = setup_protocol();
@@ -144,20 +399,20 @@ mod tests {
         #[test]
         fn test_solve() {
             let protocol = setup_protocol();
-            let s = protocol.solve("password", &BigUint::from(9223918093844043694u64), &BigUint::from(4051888u64));
-            assert_eq!(s, BigUint::from(9223918093840913154u64));
+            let s = protocol.solve(REALM, USER, "password", &BigUint::from(9223918093844043694u64), &BigUint::from(4051888u64));
+            assert_eq!(s, BigUint::from(9223918093836746925u64));
         }
 
         #[test]
         fn test_verify() {
             let protocol = setup_protocol();
             assert!(protocol.verify(
-                &BigUint::from(180020373440730202u64),
-                &BigUint::from(138713557362284185u64),
+                &BigUint::from(348596868091682774u64),
+                &BigUint::from(289175850390861676u64),
                 &BigUint::from(254414293247193407u64),
                 &BigUint::from(320950112331669597u64),
                 &BigUint::from(4051888u64),
-                &BigUint::from(9223918093840913154u64),
+                &BigUint::from(9223918093836746925u64),
             ));
         }
 
@@ -165,14 +420,47 @@ mod tests {
         fn test_verify_fails() {
             let protocol = setup_protocol();
             assert!(!protocol.verify(
-                &BigUint::from(180020373440730202u64),
-                &BigUint::from(138713557362284185u64),
+                &BigUint::from(348596868091682774u64),
+                &BigUint::from(289175850390861676u64),
                 &BigUint::from(254414293247193407u64),
                 &BigUint::from(320950112331669597u64),
                 &BigUint::from(4051888u64),
                 &BigUint::from(1337u64),
             ));
         }
+
+        #[test]
+        fn test_device_factor() {
+            let protocol = setup_protocol();
+            let device_key = protocol.device_key();
+            let (y1, y2) = protocol.register_with_device(REALM, USER, "password", &device_key);
+            let (k, r1, r2) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve_with_device(REALM, USER, "password", &device_key, &k, &c);
+            assert!(protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(!protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+            let s = protocol.solve_with_device(REALM, USER, "password", &protocol.device_key(), &k, &c);
+            assert!(!protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+        }
+
+        #[test]
+        fn test_prove_registration() {
+            let protocol = setup_protocol();
+            let (y1, y2) = protocol.register(REALM, USER, "password");
+            let proof = protocol.prove_registration(REALM, USER, "password");
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            assert!(!protocol.verify_registration(REALM, "otheruser", &y1, &y2, &proof));
+            let (other, _) = protocol.register(REALM, USER, "passw0rd");
+            assert!(!protocol.verify_registration(REALM, USER, &other, &y2, &proof));
+            let device_key = protocol.device_key();
+            let (y1, y2) = protocol.register_with_device(REALM, USER, "password", &device_key);
+            let proof = protocol.prove_registration_with_device(REALM, USER, "password", &device_key);
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            let proof = protocol.prove_registration_with_device_with_rng(REALM, USER, "password", &device_key, &mut StdRng::seed_from_u64(42));
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            assert_eq!(proof, protocol.prove_registration_with_device_with_rng(REALM, USER, "password", &device_key, &mut StdRng::seed_from_u64(42)));
+        }
     }
 
     mod k256 {
@@ -190,17 +478,25 @@ mod tests {
         #[test]
         fn test_register() {
             let protocol = setup_protocol();
-            let (y1, y2) = protocol.register("password");
+            let (y1, y2) = protocol.register(REALM, USER, "password");
             assert_eq!(y1, AffinePoint::decompress(
-                &[187, 39, 77, 94, 91, 203, 139, 150, 167, 164, 62, 83, 208, 74, 209, 123, 172, 90, 139, 251, 210, 68, 183, 121, 174, 51, 126, 104, 44, 106, 161, 185].into(),
+                &[155, 22, 43, 29, 159, 79, 237, 71, 29, 22, 168, 243, 173, 197, 254, 208, 13, 179, 55, 134, 60, 76, 0, 181, 189, 129, 107, 44, 111, 82, 192, 47].into(),
                 Choice::from(1),
             ).unwrap());
             assert_eq!(y2, AffinePoint::decompress(
-                &[195, 56, 233, 7, 21, 30, 207, 163, 175, 82, 207, 135, 51, 182, 124, 218, 193, 110, 212, 26, 163, 42, 91, 11, 192, 158, 228, 233, 74, 169, 125, 87].into(),
-                Choice::from(0)
+                &[223, 130, 156, 206, 25, 157, 186, 111, 207, 141, 250, 66, 84, 140, 13, 125, 127, 102, 4, 132, 183, 34, 134, 37, 116, 187, 176, 51, 216, 198, 52, 223].into(),
+                Choice::from(1)
             ).unwrap());
         }
 
+        #[test]
+        fn test_register_domain_separation() {
+            let protocol = setup_protocol();
+            let y = protocol.register(REALM, USER, "password");
+            assert_ne!(y, protocol.register(REALM, "otheruser", "password"));
+            assert_ne!(y, protocol.register("other-realm", USER, "password"));
+        }
+
         #[test]
         fn test_commit() {
             let protocol = setup_protocol();
@@ -209,21 +505,34 @@ mod tests {
             assert_eq!(r2, AffinePoint::from(AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(107211496160805127u64)) * k));
         }
 
+        #[test]
+        fn test_commit_with_rng() {
+            let protocol = setup_protocol();
+            let (k1, r1, _) = protocol.commit_with_rng(&mut StdRng::seed_from_u64(42));
+            let (k2, r2, _) = protocol.commit_with_rng(&mut StdRng::seed_from_u64(42));
+            assert_eq!(k1, k2);
+            assert_eq!(r1, r2);
+            assert!(k1.to_bytes()[..24].iter().any(|byte| *byte != 0));
+        }
+
         #[test]
         fn test_challenge() {
             let protocol = setup_protocol();
-            protocol.challenge();
+            let c = protocol.challenge_with_rng(&mut StdRng::seed_from_u64(42));
+            assert!(c.to_bytes()[..24].iter().any(|byte| *byte != 0));
         }
 
         #[test]
         fn test_solve() {
             let protocol = setup_protocol();
             let s = protocol.solve(
+                REALM,
+                USER,
                 "password",
                 &Scalar::from_repr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 122, 89, 221, 54, 45, 103, 228, 32].into()).unwrap(),
                 &Scalar::from_repr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 166, 184, 114, 226, 167, 121, 136, 254].into()).unwrap(),
             );
-            assert_eq!(s, Scalar::from_repr([255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 254, 75, 224, 144, 209, 44, 157, 92, 155, 237, 25, 142, 143, 185, 165, 204, 141].into()).unwrap());
+            assert_eq!(s, Scalar::from_repr([84, 195, 110, 201, 114, 163, 120, 69, 79, 222, 16, 126, 218, 222, 44, 68, 101, 87, 40, 78, 36, 149, 151, 248, 95, 7, 248, 95, 63, 219, 160, 69].into()).unwrap());
         }
 
         #[test]
@@ -231,12 +540,12 @@ mod tests {
             let protocol = setup_protocol();
             assert!(protocol.verify(
                 &AffinePoint::decompress(
-                    &[187, 39, 77, 94, 91, 203, 139, 150, 167, 164, 62, 83, 208, 74, 209, 123, 172, 90, 139, 251, 210, 68, 183, 121, 174, 51, 126, 104, 44, 106, 161, 185].into(),
+                    &[155, 22, 43, 29, 159, 79, 237, 71, 29, 22, 168, 243, 173, 197, 254, 208, 13, 179, 55, 134, 60, 76, 0, 181, 189, 129, 107, 44, 111, 82, 192, 47].into(),
                     Choice::from(1)
                 ).unwrap(),
                 &AffinePoint::decompress(
-                    &[195, 56, 233, 7, 21, 30, 207, 163, 175, 82, 207, 135, 51, 182, 124, 218, 193, 110, 212, 26, 163, 42, 91, 11, 192, 158, 228, 233, 74, 169, 125, 87].into(),
-                    Choice::from(0)
+                    &[223, 130, 156, 206, 25, 157, 186, 111, 207, 141, 250, 66, 84, 140, 13, 125, 127, 102, 4, 132, 183, 34, 134, 37, 116, 187, 176, 51, 216, 198, 52, 223].into(),
+                    Choice::from(1)
                 ).unwrap(),
                 &AffinePoint::decompress(
                     &[243, 69, 153, 44, 17, 5, 189, 149, 66, 129, 164, 182, 208, 47, 6, 102, 237, 102, 177, 174, 208, 95, 213, 70, 136, 61, 163, 1, 28, 198, 125, 158].into(),
@@ -247,7 +556,7 @@ mod tests {
                     Choice::from(1)
                 ).unwrap(),
                 &Scalar::from_repr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 166, 184, 114, 226, 167, 121, 136, 254].into()).unwrap(),
-                &Scalar::from_repr([255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 254, 75, 224, 144, 209, 44, 157, 92, 155, 237, 25, 142, 143, 185, 165, 204, 141].into()).unwrap(),
+                &Scalar::from_repr([84, 195, 110, 201, 114, 163, 120, 69, 79, 222, 16, 126, 218, 222, 44, 68, 101, 87, 40, 78, 36, 149, 151, 248, 95, 7, 248, 95, 63, 219, 160, 69].into()).unwrap(),
             ));
         }
 
@@ -256,12 +565,12 @@ mod tests {
             let protocol = setup_protocol();
             assert!(!protocol.verify(
                 &AffinePoint::decompress(
-                    &[187, 39, 77, 94, 91, 203, 139, 150, 167, 164, 62, 83, 208, 74, 209, 123, 172, 90, 139, 251, 210, 68, 183, 121, 174, 51, 126, 104, 44, 106, 161, 185].into(),
+                    &[155, 22, 43, 29, 159, 79, 237, 71, 29, 22, 168, 243, 173, 197, 254, 208, 13, 179, 55, 134, 60, 76, 0, 181, 189, 129, 107, 44, 111, 82, 192, 47].into(),
                     Choice::from(1)
                 ).unwrap(),
                 &AffinePoint::decompress(
-                    &[195, 56, 233, 7, 21, 30, 207, 163, 175, 82, 207, 135, 51, 182, 124, 218, 193, 110, 212, 26, 163, 42, 91, 11, 192, 158, 228, 233, 74, 169, 125, 87].into(),
-                    Choice::from(0)
+                    &[223, 130, 156, 206, 25, 157, 186, 111, 207, 141, 250, 66, 84, 140, 13, 125, 127, 102, 4, 132, 183, 34, 134, 37, 116, 187, 176, 51, 216, 198, 52, 223].into(),
+                    Choice::from(1)
                 ).unwrap(),
                 &AffinePoint::decompress(
                     &[243, 69, 153, 44, 17, 5, 189, 149, 66, 129, 164, 182, 208, 47, 6, 102, 237, 102, 177, 174, 208, 95, 213, 70, 136, 61, 163, 1, 28, 198, 125, 158].into(),
@@ -272,8 +581,41 @@ mod tests {
                     Choice::from(1)
                 ).unwrap(),
                 &Scalar::from_repr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 166, 184, 114, 226, 167, 121, 136, 254].into()).unwrap(),
-                &Scalar::from_repr([254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 254, 75, 224, 144, 209, 44, 157, 92, 155, 237, 25, 142, 143, 185, 165, 204, 141].into()).unwrap(),
+                &Scalar::from_repr([85, 195, 110, 201, 114, 163, 120, 69, 79, 222, 16, 126, 218, 222, 44, 68, 101, 87, 40, 78, 36, 149, 151, 248, 95, 7, 248, 95, 63, 219, 160, 69].into()).unwrap(),
             ));
         }
+
+        #[test]
+        fn test_device_factor() {
+            let protocol = setup_protocol();
+            let device_key = protocol.device_key();
+            let (y1, y2) = protocol.register_with_device(REALM, USER, "password", &device_key);
+            let (k, r1, r2) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve_with_device(REALM, USER, "password", &device_key, &k, &c);
+            assert!(protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(!protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+            let s = protocol.solve_with_device(REALM, USER, "password", &protocol.device_key(), &k, &c);
+            assert!(!protocol.verify(&y1, &y2, &r1, &r2, &c, &s));
+        }
+
+        #[test]
+        fn test_prove_registration() {
+            let protocol = setup_protocol();
+            let (y1, y2) = protocol.register(REALM, USER, "password");
+            let proof = protocol.prove_registration(REALM, USER, "password");
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            assert!(!protocol.verify_registration(REALM, "otheruser", &y1, &y2, &proof));
+            let (other, _) = protocol.register(REALM, USER, "passw0rd");
+            assert!(!protocol.verify_registration(REALM, USER, &other, &y2, &proof));
+            let device_key = protocol.device_key();
+            let (y1, y2) = protocol.register_with_device(REALM, USER, "password", &device_key);
+            let proof = protocol.prove_registration_with_device(REALM, USER, "password", &device_key);
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            let proof = protocol.prove_registration_with_device_with_rng(REALM, USER, "password", &device_key, &mut StdRng::seed_from_u64(42));
+            assert!(protocol.verify_registration(REALM, USER, &y1, &y2, &proof));
+            assert_eq!(proof, protocol.prove_registration_with_device_with_rng(REALM, USER, "password", &device_key, &mut StdRng::seed_from_u64(42)));
+        }
     }
 }
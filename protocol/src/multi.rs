@@ -0,0 +1,427 @@
+use alloc::{vec, vec::Vec};
+
+use rand::{CryptoRng, RngCore};
+
+use crate::{group::{ExpGroup, Group, K256Group}, transcript::Transcript};
+
+
+/// Chaum Pedersen proof of equality of discrete logs over `n` bases: given generators
+/// `g_1..g_n` and public values `y_i = g_i^x`, it proves knowledge of a single `x` behind all of
+/// them. With the generators `[g, h]` it accepts the same secret as the two base variants.
+#[derive(Clone)]
+pub struct MultiChaumPedersen<G: Group> {
+    group: G,
+    generators: Vec<G::Element>,
+}
+
+pub type MultiChaumPedersenExp = MultiChaumPedersen<ExpGroup>;
+pub type MultiChaumPedersenK256 = MultiChaumPedersen<K256Group>;
+
+impl<G: Group> MultiChaumPedersen<G> {
+    pub fn new(group: G, generators: Vec<G::Element>) -> Self {
+        Self { group, generators }
+    }
+
+    pub fn group(&self) -> &G {
+        &self.group
+    }
+
+    pub fn generators(&self) -> &[G::Element] {
+        &self.generators
+    }
+
+    pub fn secret(&self, realm: &str, user: &str, password: &str) -> G::Scalar {
+        self.group.secret(realm, user, password)
+    }
+
+    pub fn register(&self, realm: &str, user: &str, password: &str) -> Vec<G::Element> {
+        self.public(&self.secret(realm, user, password))
+    }
+
+    pub fn public(&self, x: &G::Scalar) -> Vec<G::Element> {
+        self.generators.iter().map(|g| self.group.exp(g, x)).collect()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn commit(&self) -> (G::Scalar, Vec<G::Element>) {
+        self.commit_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn commit_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> (G::Scalar, Vec<G::Element>) {
+        let k = self.group.random_scalar(rng);
+        let rs = self.public(&k);
+        (k, rs)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn challenge(&self) -> G::Scalar {
+        self.challenge_with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn challenge_with_rng<R: RngCore + CryptoRng>(&self, rng: &mut R) -> G::Scalar {
+        self.group.random_scalar(rng)
+    }
+
+    pub fn solve(&self, realm: &str, user: &str, password: &str, k: &G::Scalar, c: &G::Scalar) -> G::Scalar {
+        self.solve_secret(&self.secret(realm, user, password), k, c)
+    }
+
+    pub fn solve_secret(&self, x: &G::Scalar, k: &G::Scalar, c: &G::Scalar) -> G::Scalar {
+        self.group.sub(k, &self.group.mul(c, x))
+    }
+
+    /// Commitments `r_i = g_i^s * y_i^c` a verifier expects for the given challenge and response.
+    /// Picking `c` and `s` at random yields a transcript indistinguishable from an honest one,
+    /// which is what proof composition relies on.
+    pub fn simulate(&self, ys: &[G::Element], c: &G::Scalar, s: &G::Scalar) -> Vec<G::Element> {
+        self.generators.iter().zip(ys).map(|(g, y)| {
+            self.group.op(&self.group.exp(g, s), &self.group.exp(y, c))
+        }).collect()
+    }
+
+    pub fn verify(&self, ys: &[G::Element], rs: &[G::Element], c: &G::Scalar, s: &G::Scalar) -> bool {
+        ys.len() == self.generators.len() &&
+        rs.len() == self.generators.len() &&
+        self.simulate(ys, c, s) == rs
+    }
+
+    /// Canonical encoding of the proof statement and commitment, identical for every group:
+    /// `n` as a 4 byte big endian integer followed by `g_1..g_n`, `y_1..y_n` and `r_1..r_n` in
+    /// the fixed width element encoding of the group.
+    pub fn transcript(&self, ys: &[G::Element], rs: &[G::Element]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&(self.generators.len() as u32).to_be_bytes());
+        for element in self.generators.iter().chain(ys).chain(rs) {
+            self.group.encode_element(element, &mut out);
+        }
+        out
+    }
+
+    /// Absorbs the ciphersuite under `ciphersuite`, the group parameters under `parameters`, `n`
+    /// under `n` and then every generator, public value and commitment under `g`, `y` and `r`.
+    pub fn bind(&self, transcript: &mut Transcript, ys: &[G::Element], rs: &[G::Element]) {
+        let mut parameters = Vec::new();
+        self.group.encode_parameters(&mut parameters);
+        transcript.append_message(b"ciphersuite", G::CIPHERSUITE.as_bytes());
+        transcript.append_message(b"parameters", &parameters);
+        transcript.append_u64(b"n", self.generators.len() as u64);
+        for g in &self.generators {
+            transcript.append_element(b"g", &self.group, g);
+        }
+        for y in ys {
+            transcript.append_element(b"y", &self.group, y);
+        }
+        for r in rs {
+            transcript.append_element(b"r", &self.group, r);
+        }
+    }
+
+    /// Fiat-Shamir challenge of a non-interactive proof: the transcript `chaum-pedersen-proof`
+    /// with the statement and commitment bound, followed by `context` under `context`, squeezed
+    /// under `c`. The context should name everything the proof is meant for, such as the realm,
+    /// the username and a server nonce.
+    pub fn challenge_for(&self, ys: &[G::Element], rs: &[G::Element], context: &[u8]) -> G::Scalar {
+        let mut transcript = Transcript::new(b"chaum-pedersen-proof");
+        self.bind(&mut transcript, ys, rs);
+        transcript.append_message(b"context", context);
+        transcript.challenge_scalar(b"c", &self.group)
+    }
+
+    /// Non-interactive proof of knowledge of `x`, returning the commitments and the response.
+    #[cfg(feature = "std")]
+    pub fn prove(&self, x: &G::Scalar, context: &[u8]) -> (Vec<G::Element>, G::Scalar) {
+        self.prove_with_rng(x, context, &mut rand::thread_rng())
+    }
+
+    pub fn prove_with_rng<R: RngCore + CryptoRng>(&self, x: &G::Scalar, context: &[u8], rng: &mut R) -> (Vec<G::Element>, G::Scalar) {
+        let (k, rs) = self.commit_with_rng(rng);
+        let c = self.challenge_for(&self.public(x), &rs, context);
+        let s = self.solve_secret(x, &k, &c);
+        (rs, s)
+    }
+
+    pub fn verify_proof(&self, ys: &[G::Element], rs: &[G::Element], s: &G::Scalar, context: &[u8]) -> bool {
+        self.verify(ys, rs, &self.challenge_for(ys, rs, context), s)
+    }
+
+    /// Proof of possession sent along a registration: a non-interactive proof of knowledge of `x`
+    /// under the context of `registration_context`, so it cannot be replayed for another user or
+    /// realm.
+    pub fn prove_registration_with_rng<R: RngCore + CryptoRng>(&self, realm: &str, user: &str, x: &G::Scalar, rng: &mut R) -> (Vec<G::Element>, G::Scalar) {
+        self.prove_with_rng(x, &registration_context(realm, user), rng)
+    }
+
+    /// Checks a proof of possession of `prove_registration_with_rng` and that every public value
+    /// is a canonical element other than the identity, which no proof could rule out otherwise.
+    pub fn verify_registration(&self, realm: &str, user: &str, ys: &[G::Element], rs: &[G::Element], s: &G::Scalar) -> bool {
+        let canonical = |y: &G::Element| {
+            let mut bytes = Vec::new();
+            self.group.encode_element(y, &mut bytes);
+            self.group.decode_element(&bytes).as_ref() == Some(y)
+        };
+        ys.iter().all(canonical) && self.verify_proof(ys, rs, s, &registration_context(realm, user))
+    }
+}
+
+
+/// Concatenation of `fields`, each prefixed with its 4 byte big endian byte length.
+fn framed(fields: &[&[u8]]) -> Vec<u8> {
+    let mut context = Vec::new();
+    for field in fields {
+        context.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        context.extend_from_slice(field);
+    }
+    context
+}
+
+
+/// Context of registration proofs: `register`, the realm and the username, each prefixed with its
+/// 4 byte big endian byte length.
+pub fn registration_context(realm: &str, user: &str) -> Vec<u8> {
+    framed(&[b"register", realm.as_bytes(), user.as_bytes()])
+}
+
+
+/// Context of proofs made within an authenticated session, which are as fresh as the session:
+/// `session`, the realm, the username and the session token, framed like `registration_context`.
+pub fn session_context(realm: &str, user: &str, session: &str) -> Vec<u8> {
+    framed(&[b"session", realm.as_bytes(), user.as_bytes(), session.as_bytes()])
+}
+
+
+/// Context of proofs replacing a user's credentials, which are bound to the replacement: `change`,
+/// the realm, the username, a one-time nonce of the server and the new credentials' encodings,
+/// framed like `registration_context`.
+pub fn change_context(realm: &str, user: &str, nonce: &[u8], ys: &[Vec<u8>]) -> Vec<u8> {
+    let mut fields: Vec<&[u8]> = vec![b"change", realm.as_bytes(), user.as_bytes(), nonce];
+    fields.extend(ys.iter().map(Vec::as_slice));
+    framed(&fields)
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::vec;
+
+    use num_bigint::BigUint;
+    use ::k256::{AffinePoint, Scalar};
+
+    use super::*;
+    use crate::{ChaumPedersen, ChaumPedersenK256};
+
+    const REALM: &str = "chaum-pedersen-auth";
+    const USER: &str = "testuser";
+
+    mod exponent {
+        use super::*;
+
+        pub fn setup_protocol() -> MultiChaumPedersenExp {
+            let p = BigUint::from(363967321904221003u64);
+            MultiChaumPedersen::new(
+                ExpGroup::new(p.clone(), BigUint::from(7696033u64), BigUint::from(165950041202038920u64)),
+                vec![
+                    BigUint::from(165950041202038920u64),
+                    BigUint::from(96429580695728554u64),
+                    BigUint::from(165950041202038920u64).modpow(&BigUint::from(1337u64), &p),
+                ],
+            )
+        }
+
+        #[test]
+        fn test_register_matches_two_bases() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let two_bases = ChaumPedersen::new(
+                BigUint::from(363967321904221003u64),
+                BigUint::from(7696033u64),
+                BigUint::from(165950041202038920u64),
+                BigUint::from(96429580695728554u64),
+            );
+            let (y1, y2) = two_bases.register(REALM, USER, "password");
+            assert_eq!(ys[..2], [y1, y2]);
+        }
+
+        #[test]
+        fn test_verify() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (k, rs) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(protocol.verify(&ys, &rs, &c, &s));
+        }
+
+        #[test]
+        fn test_verify_fails() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (k, rs) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve(REALM, USER, "passw0rd", &k, &c);
+            assert!(!protocol.verify(&ys, &rs, &c, &s));
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(!protocol.verify(&ys[..2], &rs[..2], &c, &s));
+        }
+
+        #[test]
+        fn test_transcript() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (_, rs) = protocol.commit();
+            let transcript = protocol.transcript(&ys, &rs);
+            assert_eq!(transcript.len(), 4 + 9 * 8);
+            assert_eq!(transcript[..4], [0, 0, 0, 3]);
+        }
+
+        #[test]
+        fn test_prove() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (rs, s) = protocol.prove(&protocol.secret(REALM, USER, "password"), b"context");
+            assert!(protocol.verify_proof(&ys, &rs, &s, b"context"));
+            assert!(!protocol.verify_proof(&ys, &rs, &s, b"other context"));
+            let (rs, s) = protocol.prove(&protocol.secret(REALM, USER, "passw0rd"), b"context");
+            assert!(!protocol.verify_proof(&ys, &rs, &s, b"context"));
+        }
+
+        #[test]
+        fn test_verify_registration_rejects_identity() {
+            let protocol = setup_protocol();
+            let ys = vec![BigUint::from(1u8); 3];
+            let s = BigUint::from(42u8);
+            let rs = protocol.public(&s);
+            assert!(protocol.verify_proof(&ys, &rs, &s, &registration_context(REALM, USER)));
+            assert!(!protocol.verify_registration(REALM, USER, &ys, &rs, &s));
+        }
+
+        #[test]
+        fn test_verify_registration_rejects_other_subgroup() {
+            let protocol = setup_protocol();
+            let p = BigUint::from(363967321904221003u64);
+            let x = protocol.secret(REALM, USER, "password");
+            let ys: Vec<_> = protocol.register(REALM, USER, "password").iter().map(|y| &p - y).collect();
+            let context = registration_context(REALM, USER);
+            let (rs, s) = loop {
+                let (k, rs) = protocol.commit();
+                let c = protocol.challenge_for(&ys, &rs, &context);
+                if !c.bit(0) {
+                    break (rs, protocol.solve_secret(&x, &k, &c));
+                }
+            };
+            assert!(protocol.verify_proof(&ys, &rs, &s, &context));
+            assert!(!protocol.verify_registration(REALM, USER, &ys, &rs, &s));
+        }
+
+        #[test]
+        fn test_prove_registration() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let x = protocol.secret(REALM, USER, "password");
+            let (rs, s) = protocol.prove_registration_with_rng(REALM, USER, &x, &mut rand::thread_rng());
+            assert!(protocol.verify_registration(REALM, USER, &ys, &rs, &s));
+            assert!(!protocol.verify_registration(REALM, "otheruser", &ys, &rs, &s));
+            assert!(!protocol.verify_registration("other realm", USER, &ys, &rs, &s));
+            let other = protocol.register(REALM, USER, "passw0rd");
+            let mut mixed = ys.clone();
+            mixed[1..2].clone_from_slice(&other[1..2]);
+            assert!(!protocol.verify_registration(REALM, USER, &mixed, &rs, &s));
+        }
+
+        #[test]
+        fn test_session_context() {
+            assert_ne!(session_context(REALM, USER, "token"), session_context(REALM, USER, "other token"));
+            assert_ne!(session_context(REALM, USER, ""), registration_context(REALM, USER));
+            assert_eq!(session_context("a", "b", "c")[..11], [0, 0, 0, 7, b's', b'e', b's', b's', b'i', b'o', b'n']);
+        }
+
+        #[test]
+        fn test_change_context() {
+            let ys = vec![vec![1, 2], vec![3]];
+            assert_ne!(change_context(REALM, USER, b"nonce", &ys), change_context(REALM, USER, b"other nonce", &ys));
+            assert_ne!(change_context(REALM, USER, b"nonce", &ys), change_context(REALM, USER, b"nonce", &[vec![1], vec![2, 3]]));
+            assert_eq!(change_context("a", "b", b"c", &[vec![4]])[..10], [0, 0, 0, 6, b'c', b'h', b'a', b'n', b'g', b'e']);
+            assert_eq!(change_context("a", "b", b"c", &[vec![4]])[25..], [0, 0, 0, 1, 4]);
+        }
+    }
+
+    mod k256 {
+        use super::*;
+
+        pub fn setup_protocol() -> MultiChaumPedersenK256 {
+            MultiChaumPedersen::new(
+                K256Group,
+                vec![
+                    AffinePoint::GENERATOR,
+                    AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(107211496160805127u64)),
+                    AffinePoint::from(AffinePoint::GENERATOR * Scalar::from(1337u64)),
+                ],
+            )
+        }
+
+        #[test]
+        fn test_register_matches_two_bases() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (y1, y2) = ChaumPedersenK256::new(107211496160805127).register(REALM, USER, "password");
+            assert_eq!(ys[..2], [y1, y2]);
+        }
+
+        #[test]
+        fn test_verify() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (k, rs) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(protocol.verify(&ys, &rs, &c, &s));
+        }
+
+        #[test]
+        fn test_verify_fails() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (k, rs) = protocol.commit();
+            let c = protocol.challenge();
+            let s = protocol.solve(REALM, USER, "passw0rd", &k, &c);
+            assert!(!protocol.verify(&ys, &rs, &c, &s));
+            let s = protocol.solve(REALM, USER, "password", &k, &c);
+            assert!(!protocol.verify(&ys[..2], &rs[..2], &c, &s));
+        }
+
+        #[test]
+        fn test_transcript() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (_, rs) = protocol.commit();
+            let transcript = protocol.transcript(&ys, &rs);
+            assert_eq!(transcript.len(), 4 + 9 * 33);
+            assert_eq!(transcript[..4], [0, 0, 0, 3]);
+        }
+
+        #[test]
+        fn test_prove() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let (rs, s) = protocol.prove(&protocol.secret(REALM, USER, "password"), b"context");
+            assert!(protocol.verify_proof(&ys, &rs, &s, b"context"));
+            assert!(!protocol.verify_proof(&ys, &rs, &s, b"other context"));
+            let (rs, s) = protocol.prove(&protocol.secret(REALM, USER, "passw0rd"), b"context");
+            assert!(!protocol.verify_proof(&ys, &rs, &s, b"context"));
+        }
+
+        #[test]
+        fn test_prove_registration() {
+            let protocol = setup_protocol();
+            let ys = protocol.register(REALM, USER, "password");
+            let x = protocol.secret(REALM, USER, "password");
+            let (rs, s) = protocol.prove_registration_with_rng(REALM, USER, &x, &mut rand::thread_rng());
+            assert!(protocol.verify_registration(REALM, USER, &ys, &rs, &s));
+            assert!(!protocol.verify_registration(REALM, "otheruser", &ys, &rs, &s));
+            assert!(!protocol.verify_registration("other realm", USER, &ys, &rs, &s));
+            let other = protocol.register(REALM, USER, "passw0rd");
+            let mut mixed = ys.clone();
+            mixed[1..2].clone_from_slice(&other[1..2]);
+            assert!(!protocol.verify_registration(REALM, USER, &mixed, &rs, &s));
+        }
+    }
+}
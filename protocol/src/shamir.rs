@@ -0,0 +1,283 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use rand::{CryptoRng, RngCore};
+
+use crate::group::Group;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    InvalidThreshold,
+    NotEnoughShares,
+    InvalidIndex,
+    DuplicateIndex,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidThreshold => write!(f, "threshold must be between 1 and the number of shares"),
+            Error::NotEnoughShares => write!(f, "not enough shares"),
+            Error::InvalidIndex => write!(f, "share index must not be 0"),
+            Error::DuplicateIndex => write!(f, "share index is given twice"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+
+/// Value `f(index)` of the sharing polynomial. Indices start at 1, `f(0)` being the secret.
+pub struct Share<G: Group> {
+    pub index: u8,
+    pub value: G::Scalar,
+}
+
+impl<G: Group> Clone for Share<G> {
+    fn clone(&self) -> Self {
+        Self { index: self.index, value: self.value.clone() }
+    }
+}
+
+impl<G: Group> PartialEq for Share<G> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.value == other.value
+    }
+}
+
+impl<G: Group> fmt::Debug for Share<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Share").field("index", &self.index).field("value", &self.value).finish()
+    }
+}
+
+
+/// Shares of a secret together with the commitments to the polynomial.
+pub type Sharing<G> = (Vec<Share<G>>, Vec<<G as Group>::Element>);
+
+
+/// Shamir `t`-of-`n` sharing of a secret scalar with Feldman commitments `g^a_j` to the
+/// coefficients of the polynomial, so that every holder can check their share without learning
+/// the secret. The first commitment is `g^x`, the `y1` the user registered with.
+///
+/// Shares are numbered `1..=n` with `n` at most 255, which is below the order of every group the
+/// crate provides.
+pub struct Shamir<G: Group> {
+    group: G,
+}
+
+impl<G: Group> Shamir<G> {
+    pub fn new(group: G) -> Self {
+        Self { group }
+    }
+
+    /// Splits `x` into `n` shares any `t` of which reconstruct it, returning the shares and the
+    /// commitments to the polynomial.
+    #[cfg(feature = "std")]
+    pub fn split(&self, x: &G::Scalar, t: u8, n: u8) -> Result<Sharing<G>, Error> {
+        self.split_with_rng(x, t, n, &mut rand::thread_rng())
+    }
+
+    pub fn split_with_rng<R: RngCore + CryptoRng>(&self, x: &G::Scalar, t: u8, n: u8, rng: &mut R) -> Result<Sharing<G>, Error> {
+        if t == 0 || t > n {
+            return Err(Error::InvalidThreshold);
+        }
+        let mut coefficients = Vec::with_capacity(t as usize);
+        coefficients.push(x.clone());
+        coefficients.extend((1..t).map(|_| self.group.random_scalar(rng)));
+        let shares = (1..=n).map(|index| {
+            let point = self.group.scalar_from_u64(index as u64);
+            let value = coefficients.iter().rev().fold(self.group.zero(), |value, coefficient| {
+                self.group.add(&self.group.mul(&value, &point), coefficient)
+            });
+            Share { index, value }
+        }).collect();
+        let generator = self.group.generator();
+        let commitments = coefficients.iter().map(|coefficient| self.group.exp(&generator, coefficient)).collect();
+        Ok((shares, commitments))
+    }
+
+    /// Checks `g^f(i) = prod_j C_j^(i^j)` for the share `f(i)`.
+    pub fn verify_share(&self, share: &Share<G>, commitments: &[G::Element]) -> bool {
+        if share.index == 0 || commitments.is_empty() {
+            return false;
+        }
+        let point = self.group.scalar_from_u64(share.index as u64);
+        let mut power = self.group.scalar_from_u64(1);
+        let mut expected = None;
+        for commitment in commitments {
+            let term = self.group.exp(commitment, &power);
+            expected = Some(match expected {
+                None => term,
+                Some(expected) => self.group.op(&expected, &term),
+            });
+            power = self.group.mul(&power, &point);
+        }
+        expected == Some(self.group.exp(&self.group.generator(), &share.value))
+    }
+
+    /// Recovers `f(0)` from at least `t` shares by Lagrange interpolation. Fewer shares yield an
+    /// unrelated scalar, which can be told apart by comparing `g^x` to the first commitment.
+    pub fn reconstruct(&self, shares: &[Share<G>]) -> Result<G::Scalar, Error> {
+        if shares.is_empty() {
+            return Err(Error::NotEnoughShares);
+        }
+        for (i, share) in shares.iter().enumerate() {
+            if share.index == 0 {
+                return Err(Error::InvalidIndex);
+            }
+            if shares[..i].iter().any(|other| other.index == share.index) {
+                return Err(Error::DuplicateIndex);
+            }
+        }
+        let mut x = self.group.zero();
+        for share in shares {
+            let point = self.group.scalar_from_u64(share.index as u64);
+            let mut numerator = self.group.scalar_from_u64(1);
+            let mut denominator = self.group.scalar_from_u64(1);
+            for other in shares.iter().filter(|other| other.index != share.index) {
+                let other = self.group.scalar_from_u64(other.index as u64);
+                numerator = self.group.mul(&numerator, &other);
+                denominator = self.group.mul(&denominator, &self.group.sub(&other, &point));
+            }
+            let denominator = self.group.invert(&denominator).ok_or(Error::DuplicateIndex)?;
+            let coefficient = self.group.mul(&numerator, &denominator);
+            x = self.group.add(&x, &self.group.mul(&share.value, &coefficient));
+        }
+        Ok(x)
+    }
+
+    /// Encodes a share for storage as its index byte followed by the fixed width scalar.
+    pub fn encode_share(&self, share: &Share<G>) -> Vec<u8> {
+        let mut out = alloc::vec![share.index];
+        self.group.encode_scalar(&share.value, &mut out);
+        out
+    }
+
+    pub fn decode_share(&self, bytes: &[u8]) -> Option<Share<G>> {
+        let (&index, value) = bytes.split_first()?;
+        if index == 0 {
+            return None;
+        }
+        Some(Share { index, value: self.group.decode_scalar(value)? })
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use num_bigint::BigUint;
+
+    use super::*;
+    use crate::group::{ExpGroup, K256Group};
+
+    const REALM: &str = "chaum-pedersen-auth";
+    const USER: &str = "testuser";
+
+    fn test_split<G: Group>(group: G) {
+        let shamir = Shamir::new(group.clone());
+        let x = group.secret(REALM, USER, "password");
+        let (shares, commitments) = shamir.split(&x, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert_eq!(commitments.len(), 3);
+        assert_eq!(commitments[0], group.exp(&group.generator(), &x));
+        assert!(shares.iter().all(|share| shamir.verify_share(share, &commitments)));
+
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let subset: Vec<_> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(shamir.reconstruct(&subset), Ok(x.clone()));
+        }
+        assert_eq!(shamir.reconstruct(&shares), Ok(x.clone()));
+        assert_ne!(shamir.reconstruct(&shares[..2]), Ok(x.clone()));
+    }
+
+    fn test_verify_share_fails<G: Group>(group: G) {
+        let shamir = Shamir::new(group.clone());
+        let x = group.secret(REALM, USER, "password");
+        let (mut shares, commitments) = shamir.split(&x, 2, 3).unwrap();
+        shares[0].value = group.add(&shares[0].value, &group.scalar_from_u64(1));
+        assert!(!shamir.verify_share(&shares[0], &commitments));
+        shares[1].index = 3;
+        assert!(!shamir.verify_share(&shares[1], &commitments));
+    }
+
+    fn test_invalid<G: Group>(group: G) {
+        let shamir = Shamir::new(group.clone());
+        let x = group.secret(REALM, USER, "password");
+        assert_eq!(shamir.split(&x, 0, 3).err(), Some(Error::InvalidThreshold));
+        assert_eq!(shamir.split(&x, 4, 3).err(), Some(Error::InvalidThreshold));
+        let (shares, _) = shamir.split(&x, 2, 3).unwrap();
+        assert_eq!(shamir.reconstruct(&[]), Err(Error::NotEnoughShares));
+        assert_eq!(shamir.reconstruct(&[shares[0].clone(), shares[0].clone()]), Err(Error::DuplicateIndex));
+        let zero = Share { index: 0, value: shares[0].value.clone() };
+        assert_eq!(shamir.reconstruct(&[zero, shares[1].clone()]), Err(Error::InvalidIndex));
+    }
+
+    fn test_encode_share<G: Group>(group: G) {
+        let shamir = Shamir::new(group.clone());
+        let (shares, _) = shamir.split(&group.secret(REALM, USER, "password"), 2, 3).unwrap();
+        let bytes = shamir.encode_share(&shares[1]);
+        assert_eq!(bytes[0], 2);
+        assert_eq!(shamir.decode_share(&bytes), Some(shares[1].clone()));
+        assert_eq!(shamir.decode_share(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(shamir.decode_share(&[]), None);
+    }
+
+    mod exponent {
+        use super::*;
+
+        pub fn setup_group() -> ExpGroup {
+            ExpGroup::new(
+                BigUint::from(363967321904221003u64),
+                BigUint::from(7696033u64),
+                BigUint::from(165950041202038920u64),
+            )
+        }
+
+        #[test]
+        fn test_split() {
+            super::test_split(setup_group());
+        }
+
+        #[test]
+        fn test_verify_share_fails() {
+            super::test_verify_share_fails(setup_group());
+        }
+
+        #[test]
+        fn test_invalid() {
+            super::test_invalid(setup_group());
+        }
+
+        #[test]
+        fn test_encode_share() {
+            super::test_encode_share(setup_group());
+        }
+    }
+
+    mod k256 {
+        use super::*;
+
+        #[test]
+        fn test_split() {
+            super::test_split(K256Group);
+        }
+
+        #[test]
+        fn test_verify_share_fails() {
+            super::test_verify_share_fails(K256Group);
+        }
+
+        #[test]
+        fn test_invalid() {
+            super::test_invalid(K256Group);
+        }
+
+        #[test]
+        fn test_encode_share() {
+            super::test_encode_share(K256Group);
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Labelled transcript of protocol messages in the style of Merlin, built on SHA-256 so that it
+//! can be reproduced without a STROBE implementation.
+//!
+//! Every operation appends a frame `op || len(label) || label || len(data) || data` to a running
+//! SHA-256 hash, where `op` is a single byte (`1` to absorb a message, `2` to squeeze a
+//! challenge) and each length is 4 byte big endian. `Transcript::new(label)` starts with the
+//! absorb frame of `label` under the label `CP-TRANSCRIPT-SHA256-V1`.
+//!
+//! Squeezing `n` bytes under `label` appends the frame with `data` being `n` as 4 byte big
+//! endian, takes the digest of everything absorbed so far as `seed` and outputs the first `n`
+//! bytes of `SHA-256(seed || 0) || SHA-256(seed || 1) || ...`, with 4 byte big endian counters.
+//! The squeeze frame stays in the transcript, so later challenges depend on earlier ones.
+//!
+//! Integers are absorbed as 8 byte big endian, scalars and group elements in the fixed width
+//! encodings of [`Group`](crate::group::Group) and challenge scalars are reduced from 32 squeezed
+//! bytes with `Group::scalar_from_digest`.
+
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "alloc")]
+use crate::group::Group;
+
+
+const PROTOCOL: &[u8] = b"CP-TRANSCRIPT-SHA256-V1";
+const ABSORB: u8 = 1;
+const SQUEEZE: u8 = 2;
+
+
+#[derive(Clone)]
+pub struct Transcript {
+    hasher: Sha256,
+}
+
+impl Transcript {
+    /// Starts a transcript for the protocol named by `label`.
+    pub fn new(label: &'static [u8]) -> Self {
+        let mut transcript = Self { hasher: Sha256::new() };
+        transcript.append_message(PROTOCOL, label);
+        transcript
+    }
+
+    fn frame(&mut self, op: u8, label: &[u8], data: &[u8]) {
+        self.hasher.update([op]);
+        self.hasher.update((label.len() as u32).to_be_bytes());
+        self.hasher.update(label);
+        self.hasher.update((data.len() as u32).to_be_bytes());
+        self.hasher.update(data);
+    }
+
+    pub fn append_message(&mut self, label: &'static [u8], message: &[u8]) {
+        self.frame(ABSORB, label, message);
+    }
+
+    pub fn append_u64(&mut self, label: &'static [u8], value: u64) {
+        self.append_message(label, &value.to_be_bytes());
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn append_scalar<G: Group>(&mut self, label: &'static [u8], group: &G, scalar: &G::Scalar) {
+        let mut bytes = alloc::vec::Vec::new();
+        group.encode_scalar(scalar, &mut bytes);
+        self.append_message(label, &bytes);
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn append_element<G: Group>(&mut self, label: &'static [u8], group: &G, element: &G::Element) {
+        let mut bytes = alloc::vec::Vec::new();
+        group.encode_element(element, &mut bytes);
+        self.append_message(label, &bytes);
+    }
+
+    pub fn challenge_bytes(&mut self, label: &'static [u8], dest: &mut [u8]) {
+        self.frame(SQUEEZE, label, &(dest.len() as u32).to_be_bytes());
+        let seed = self.hasher.clone().finalize();
+        for (counter, chunk) in dest.chunks_mut(32).enumerate() {
+            let block = Sha256::new()
+                .chain_update(seed)
+                .chain_update((counter as u32).to_be_bytes())
+                .finalize();
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn challenge_scalar<G: Group>(&mut self, label: &'static [u8], group: &G) -> G::Scalar {
+        let mut digest = [0u8; 32];
+        self.challenge_bytes(label, &mut digest);
+        group.scalar_from_digest(&digest)
+    }
+
+    /// Digest of everything absorbed so far, to record which values were bound together without
+    /// changing the transcript.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        self.hasher.clone().finalize().into()
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_bytes() {
+        let mut transcript = Transcript::new(b"test");
+        transcript.append_message(b"message", b"hello");
+        let mut challenge = [0u8; 40];
+        transcript.challenge_bytes(b"c", &mut challenge);
+        assert_eq!(challenge, [
+            154, 68, 103, 140, 211, 193, 103, 34, 83, 65, 132, 23, 170, 7, 208, 23, 247, 135, 189, 67,
+            123, 79, 78, 36, 214, 26, 228, 224, 252, 109, 220, 181, 148, 53, 29, 108, 135, 154, 148, 227,
+        ]);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let challenge = |message: &[u8]| {
+            let mut transcript = Transcript::new(b"test");
+            transcript.append_message(b"message", message);
+            let mut challenge = [0u8; 32];
+            transcript.challenge_bytes(b"c", &mut challenge);
+            challenge
+        };
+        assert_eq!(challenge(b"hello"), challenge(b"hello"));
+        assert_ne!(challenge(b"hello"), challenge(b"hellO"));
+    }
+
+    #[test]
+    fn test_labels_are_bound() {
+        let mut a = Transcript::new(b"test");
+        a.append_message(b"ab", b"c");
+        let mut b = Transcript::new(b"test");
+        b.append_message(b"a", b"bc");
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+
+    #[test]
+    fn test_successive_challenges_differ() {
+        let mut transcript = Transcript::new(b"test");
+        let mut first = [0u8; 32];
+        let mut second = [0u8; 32];
+        transcript.challenge_bytes(b"c", &mut first);
+        transcript.challenge_bytes(b"c", &mut second);
+        assert_ne!(first, second);
+    }
+}
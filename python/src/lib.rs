@@ -0,0 +1,126 @@
+use k256::{
+    AffinePoint,
+    Scalar,
+    elliptic_curve::{point::{AffineCoordinates, DecompressPoint}, subtle::Choice, PrimeField},
+};
+use num_bigint::BigUint;
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+
+/// Points are exchanged as `(x, is_y_odd)` tuples, mirroring the `Point` message of `zkp_auth.proto`.
+type Point<'py> = (Bound<'py, PyBytes>, bool);
+
+
+fn point_to_py(py: Python<'_>, point: AffinePoint) -> Point<'_> {
+    (PyBytes::new(py, &point.x()), point.y_is_odd().into())
+}
+
+fn point_from_py(point: &Point<'_>) -> PyResult<AffinePoint> {
+    let (x, is_y_odd) = point;
+    let x: [u8; 32] = x.as_bytes().try_into()
+        .map_err(|_| PyValueError::new_err("point x coordinate must be 32 bytes"))?;
+    Option::from(AffinePoint::decompress(&x.into(), Choice::from(*is_y_odd as u8)))
+        .ok_or_else(|| PyValueError::new_err("invalid point"))
+}
+
+fn scalar_to_py(py: Python<'_>, scalar: Scalar) -> Bound<'_, PyBytes> {
+    PyBytes::new(py, &scalar.to_repr())
+}
+
+fn scalar_from_py(scalar: &Bound<'_, PyBytes>) -> PyResult<Scalar> {
+    let scalar: [u8; 32] = scalar.as_bytes().try_into()
+        .map_err(|_| PyValueError::new_err("scalar must be 32 bytes"))?;
+    Option::from(Scalar::from_repr(scalar.into()))
+        .ok_or_else(|| PyValueError::new_err("invalid scalar"))
+}
+
+
+#[pyclass]
+struct ChaumPedersen(protocol::ChaumPedersen);
+
+#[pymethods]
+impl ChaumPedersen {
+    #[new]
+    fn new(p: BigUint, q: BigUint, g: BigUint, h: BigUint) -> Self {
+        Self(protocol::ChaumPedersen::new(p, q, g, h))
+    }
+
+    fn register(&self, realm: &str, user: &str, password: &str) -> (BigUint, BigUint) {
+        self.0.register(realm, user, password)
+    }
+
+    fn prove_registration(&self, realm: &str, user: &str, password: &str) -> (BigUint, BigUint, BigUint) {
+        self.0.prove_registration(realm, user, password)
+    }
+
+    fn commit(&self) -> (BigUint, BigUint, BigUint) {
+        self.0.commit()
+    }
+
+    fn challenge(&self) -> BigUint {
+        self.0.challenge()
+    }
+
+    fn solve(&self, realm: &str, user: &str, password: &str, k: BigUint, c: BigUint) -> BigUint {
+        self.0.solve(realm, user, password, &k, &c)
+    }
+
+    fn verify(&self, y1: BigUint, y2: BigUint, r1: BigUint, r2: BigUint, c: BigUint, s: BigUint) -> bool {
+        self.0.verify(&y1, &y2, &r1, &r2, &c, &s)
+    }
+}
+
+
+#[pyclass]
+struct ChaumPedersenK256(protocol::ChaumPedersenK256);
+
+#[pymethods]
+impl ChaumPedersenK256 {
+    #[new]
+    fn new(h_offset: u64) -> Self {
+        Self(protocol::ChaumPedersenK256::new(h_offset))
+    }
+
+    fn register<'py>(&self, py: Python<'py>, realm: &str, user: &str, password: &str) -> (Point<'py>, Point<'py>) {
+        let (y1, y2) = self.0.register(realm, user, password);
+        (point_to_py(py, y1), point_to_py(py, y2))
+    }
+
+    fn prove_registration<'py>(&self, py: Python<'py>, realm: &str, user: &str, password: &str) -> (Point<'py>, Point<'py>, Bound<'py, PyBytes>) {
+        let (r1, r2, s) = self.0.prove_registration(realm, user, password);
+        (point_to_py(py, r1), point_to_py(py, r2), scalar_to_py(py, s))
+    }
+
+    fn commit<'py>(&self, py: Python<'py>) -> (Bound<'py, PyBytes>, Point<'py>, Point<'py>) {
+        let (k, r1, r2) = self.0.commit();
+        (scalar_to_py(py, k), point_to_py(py, r1), point_to_py(py, r2))
+    }
+
+    fn challenge<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        scalar_to_py(py, self.0.challenge())
+    }
+
+    fn solve<'py>(&self, py: Python<'py>, realm: &str, user: &str, password: &str, k: &Bound<'_, PyBytes>, c: &Bound<'_, PyBytes>) -> PyResult<Bound<'py, PyBytes>> {
+        let s = self.0.solve(realm, user, password, &scalar_from_py(k)?, &scalar_from_py(c)?);
+        Ok(scalar_to_py(py, s))
+    }
+
+    fn verify(&self, y1: Point<'_>, y2: Point<'_>, r1: Point<'_>, r2: Point<'_>, c: &Bound<'_, PyBytes>, s: &Bound<'_, PyBytes>) -> PyResult<bool> {
+        Ok(self.0.verify(
+            &point_from_py(&y1)?,
+            &point_from_py(&y2)?,
+            &point_from_py(&r1)?,
+            &point_from_py(&r2)?,
+            &scalar_from_py(c)?,
+            &scalar_from_py(s)?,
+        ))
+    }
+}
+
+
+#[pymodule]
+fn chaum_pedersen(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<ChaumPedersen>()?;
+    m.add_class::<ChaumPedersenK256>()?;
+    Ok(())
+}
@@ -0,0 +1,79 @@
+"""Checks the bindings against the test vectors of the protocol crate, whose outputs they have to
+reproduce byte for byte."""
+
+import unittest
+
+from chaum_pedersen import ChaumPedersen, ChaumPedersenK256
+
+REALM = "chaum-pedersen-auth"
+USER = "testuser"
+
+
+def scalar(value: int) -> bytes:
+    return value.to_bytes(32, "big")
+
+
+class TestChaumPedersen(unittest.TestCase):
+    def setUp(self):
+        self.protocol = ChaumPedersen(363967321904221003, 7696033, 165950041202038920, 96429580695728554)
+
+    def test_register(self):
+        self.assertEqual(self.protocol.register(REALM, USER, "password"), (348596868091682774, 289175850390861676))
+
+    def test_solve(self):
+        s = self.protocol.solve(REALM, USER, "password", 9223918093844043694, 4051888)
+        self.assertEqual(s, 9223918093836746925)
+
+    def test_verify(self):
+        y1, y2 = 348596868091682774, 289175850390861676
+        r1, r2 = 254414293247193407, 320950112331669597
+        self.assertTrue(self.protocol.verify(y1, y2, r1, r2, 4051888, 9223918093836746925))
+        self.assertFalse(self.protocol.verify(y1, y2, r1, r2, 4051888, 1337))
+
+    def test_round_trip(self):
+        y1, y2 = self.protocol.register(REALM, USER, "password")
+        k, r1, r2 = self.protocol.commit()
+        c = self.protocol.challenge()
+        self.assertTrue(self.protocol.verify(y1, y2, r1, r2, c, self.protocol.solve(REALM, USER, "password", k, c)))
+        self.assertFalse(self.protocol.verify(y1, y2, r1, r2, c, self.protocol.solve(REALM, USER, "passw0rd", k, c)))
+
+
+class TestChaumPedersenK256(unittest.TestCase):
+    Y1 = (bytes([155, 22, 43, 29, 159, 79, 237, 71, 29, 22, 168, 243, 173, 197, 254, 208, 13, 179, 55, 134, 60, 76, 0, 181, 189, 129, 107, 44, 111, 82, 192, 47]), True)
+    Y2 = (bytes([223, 130, 156, 206, 25, 157, 186, 111, 207, 141, 250, 66, 84, 140, 13, 125, 127, 102, 4, 132, 183, 34, 134, 37, 116, 187, 176, 51, 216, 198, 52, 223]), True)
+    R1 = (bytes([243, 69, 153, 44, 17, 5, 189, 149, 66, 129, 164, 182, 208, 47, 6, 102, 237, 102, 177, 174, 208, 95, 213, 70, 136, 61, 163, 1, 28, 198, 125, 158]), True)
+    R2 = (bytes([105, 24, 234, 88, 96, 244, 223, 122, 159, 252, 101, 34, 217, 107, 241, 62, 195, 13, 228, 61, 132, 230, 216, 58, 114, 5, 166, 88, 45, 0, 79, 10]), True)
+    K = scalar(int.from_bytes(bytes([122, 89, 221, 54, 45, 103, 228, 32]), "big"))
+    C = scalar(int.from_bytes(bytes([166, 184, 114, 226, 167, 121, 136, 254]), "big"))
+    S = bytes([84, 195, 110, 201, 114, 163, 120, 69, 79, 222, 16, 126, 218, 222, 44, 68, 101, 87, 40, 78, 36, 149, 151, 248, 95, 7, 248, 95, 63, 219, 160, 69])
+
+    def setUp(self):
+        self.protocol = ChaumPedersenK256(107211496160805127)
+
+    def test_register(self):
+        self.assertEqual(self.protocol.register(REALM, USER, "password"), (self.Y1, self.Y2))
+
+    def test_solve(self):
+        self.assertEqual(self.protocol.solve(REALM, USER, "password", self.K, self.C), self.S)
+
+    def test_verify(self):
+        self.assertTrue(self.protocol.verify(self.Y1, self.Y2, self.R1, self.R2, self.C, self.S))
+        self.assertFalse(self.protocol.verify(self.Y1, self.Y2, self.R1, self.R2, self.C, scalar(1337)))
+
+    def test_round_trip(self):
+        y1, y2 = self.protocol.register(REALM, USER, "password")
+        k, r1, r2 = self.protocol.commit()
+        c = self.protocol.challenge()
+        self.assertEqual(len(k), 32)
+        self.assertTrue(self.protocol.verify(y1, y2, r1, r2, c, self.protocol.solve(REALM, USER, "password", k, c)))
+        self.assertFalse(self.protocol.verify(y1, y2, r1, r2, c, self.protocol.solve(REALM, USER, "passw0rd", k, c)))
+
+    def test_invalid_encodings(self):
+        with self.assertRaises(ValueError):
+            self.protocol.solve(REALM, USER, "password", self.K[1:], self.C)
+        with self.assertRaises(ValueError):
+            self.protocol.verify((self.Y1[0][1:], True), self.Y2, self.R1, self.R2, self.C, self.S)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -1,138 +1,28 @@
-import random
+import sys
 
-from ecpy.curves import Curve,Point
+from chaum_pedersen import ChaumPedersenK256
 
+h_offset = 107211496160805127
 
-def modular_sqrt(a, p):
-    """ Find a quadratic residue (mod p) of 'a'. p
-        must be an odd prime.
+protocol = ChaumPedersenK256(h_offset)
+realm = "chaum-pedersen-auth"
+user = sys.argv[1] if len(sys.argv) > 1 else "testuser"
+password = sys.argv[2] if len(sys.argv) > 2 else "password"
 
-        Solve the congruence of the form:
-            x^2 = a (mod p)
-        And returns x. Note that p - x is also a root.
 
-        0 is returned is no square root exists for
-        these a and p.
-
-        The Tonelli-Shanks algorithm is used (except
-        for some simple cases in which the solution
-        is known from an identity). This algorithm
-        runs in polynomial time (unless the
-        generalized Riemann hypothesis is false).
-    """
-    # Simple cases
-    #
-    if legendre_symbol(a, p) != 1:
-        return 0
-    elif a == 0:
-        return 0
-    elif p == 2:
-        return 0
-    elif p % 4 == 3:
-        return pow(a, (p + 1) // 4, p)
-
-    # Partition p-1 to s * 2^e for an odd s (i.e.
-    # reduce all the powers of 2 from p-1)
-    #
-    s = p - 1
-    e = 0
-    while s % 2 == 0:
-        s //= 2
-        e += 1
-
-    # Find some 'n' with a legendre symbol n|p = -1.
-    # Shouldn't take long.
-    #
-    n = 2
-    while legendre_symbol(n, p) != -1:
-        n += 1
-
-    # Here be dragons!
-    # Read the paper "Square roots from 1; 24, 51,
-    # 10 to Dan Shanks" by Ezra Brown for more
-    # information
-    #
-
-    # x is a guess of the square root that gets better
-    # with each iteration.
-    # b is the "fudge factor" - by how much we're off
-    # with the guess. The invariant x^2 = ab (mod p)
-    # is maintained throughout the loop.
-    # g is used for successive powers of n to update
-    # both a and b
-    # r is the exponent - decreases with each update
-    #
-    x = pow(a, (s + 1) // 2, p)
-    b = pow(a, s, p)
-    g = pow(n, s, p)
-    r = e
-
-    while True:
-        t = b
-        m = 0
-        for m in range(r):
-            if t == 1:
-                break
-            t = pow(t, 2, p)
-
-        if m == 0:
-            return x
-
-        gs = pow(g, 2 ** (r - m - 1), p)
-        g = (gs * gs) % p
-        x = (x * gs) % p
-        b = (b * g) % p
-        r = m
-
-
-def legendre_symbol(a, p):
-    """ Compute the Legendre symbol a|p using
-        Euler's criterion. p is a prime, a is
-        relatively prime to p (if p divides
-        a, then a|p = 0)
-
-        Returns 1 if a has a square root modulo
-        p, -1 otherwise.
-    """
-    ls = pow(a, (p - 1) // 2, p)
-    return -1 if ls == p - 1 else ls
-
-
-curve = Curve.get_curve("Curve25519")
-p = 2 ** 255 - 19
-q = 2 ** 252 + 27742317777372353535851937790883648493
-key_size = 16
-
-x = 9
-y = modular_sqrt((x ** 3 + 486662 * x ** 2 + x) % p, p)
-g = Point(x, y, curve)
-h = g * random.getrandbits(key_size)
-
-print(g)
-print(h)
-
-x = random.getrandbits(key_size)
-print("x =", x)
-y1 = g * x
-y2 = h * x
+y1, y2 = protocol.register(realm, user, password)
 print("y1 =", y1)
 print("y2 =", y2)
 
-k = random.getrandbits(key_size)
-print("k =", k)
-r1 = g * k
-r2 = h * k
+k, r1, r2 = protocol.commit()
+print("k =", k.hex())
 print("r1 =", r1)
 print("r2 =", r2)
 
-c = random.getrandbits(key_size)
-print("c =", c)
+c = protocol.challenge()
+print("c =", c.hex())
 
-if k >= q:
-    s = (k - c * x) % q
-else:
-    s = q + k - (c * x) % q
-print("s =", s)
+s = protocol.solve(realm, user, password, k, c)
+print("s =", s.hex())
 
-print(r1 == g * s + y1 * c)
-print(r2 == h * s + y2 * c)
+print(protocol.verify(y1, y2, r1, r2, c, s))
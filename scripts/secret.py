@@ -1,33 +1,39 @@
-import random
+import sys
+
+from chaum_pedersen import ChaumPedersen
 
 p = 363967321904221003
 q = 7696033
 g = 165950041202038920
 h = 96429580695728554
 
+protocol = ChaumPedersen(p, q, g, h)
+realm = "chaum-pedersen-auth"
+user = sys.argv[1] if len(sys.argv) > 1 else "testuser"
+password = sys.argv[2] if len(sys.argv) > 2 else "password"
+
 
-x = random.getrandbits(64)
-print("x =", x)
-y1 = pow(g, x, p)
-y2 = pow(h, x, p)
+y1, y2 = protocol.register(realm, user, password)
 print("y1 =", y1)
 print("y2 =", y2)
 
+proof_r1, proof_r2, proof_s = protocol.prove_registration(realm, user, password)
+print("registration proof r1 =", proof_r1)
+print("registration proof r2 =", proof_r2)
+print("registration proof s =", proof_s)
+
 
-k = random.getrandbits(64)
+k, r1, r2 = protocol.commit()
 print("k =", k)
-r1 = pow(g, k, p)
-r2 = pow(h, k, p)
 print("r1 =", r1)
 print("r2 =", r2)
 
 
-# c = random.getrandbits(64) % q
-# print("c =", c)
+c = protocol.challenge()
+print("c =", c)
 
-# s = (k - c * x) % q
-# print("s =", s)
+s = protocol.solve(realm, user, password, k, c)
+print("s =", s)
 
 
-# print(r1, pow(g, s, p) * pow(y1, c, p) % p)
-# print(r2, pow(h, s, p) * pow(y2, c, p) % p)
+print(protocol.verify(y1, y2, r1, r2, c, s))
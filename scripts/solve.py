@@ -1,7 +0,0 @@
-q = 7696033
-x = 9295755033335607571
-k = 3931124109183598580
-c = 6295471
-
-s = (k - c * x) % q
-print(s)
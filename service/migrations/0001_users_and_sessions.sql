@@ -0,0 +1,31 @@
+-- Credentials are stored as the elements' byte encodings, each prefixed by its 4 byte big endian
+-- length, with `kind` naming the `Credentials` variant: `exp` (big endian integers), `k256`
+-- (compressed SEC1 points) or `encoded` (fixed width encodings of the user's ciphersuite).
+
+CREATE TABLE users (
+    name TEXT PRIMARY KEY NOT NULL,
+    realm TEXT NOT NULL,
+    ciphersuite TEXT NOT NULL,
+    factors TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    y BLOB NOT NULL
+);
+
+CREATE TABLE sessions (
+    auth_id TEXT PRIMARY KEY NOT NULL,
+    session_id TEXT,
+    user TEXT NOT NULL REFERENCES users (name),
+    kind TEXT NOT NULL,
+    r BLOB NOT NULL,
+    c BLOB NOT NULL,
+    composition INTEGER
+);
+
+CREATE TABLE session_branches (
+    auth_id TEXT NOT NULL REFERENCES sessions (auth_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    user TEXT NOT NULL REFERENCES users (name),
+    kind TEXT NOT NULL,
+    r BLOB NOT NULL,
+    PRIMARY KEY (auth_id, position)
+);
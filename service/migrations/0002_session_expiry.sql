@@ -0,0 +1,6 @@
+-- Seconds since the Unix epoch after which a challenge or session is reaped. Rows from before
+-- expiry was tracked are reaped right away.
+
+ALTER TABLE sessions ADD COLUMN expires INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX sessions_expires ON sessions (expires);
@@ -0,0 +1,5 @@
+-- Seconds since the Unix epoch the session was last used at, for the idle timeout.
+
+ALTER TABLE sessions ADD COLUMN last_seen INTEGER NOT NULL DEFAULT 0;
+
+CREATE UNIQUE INDEX sessions_session_id ON sessions (session_id);
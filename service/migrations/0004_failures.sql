@@ -0,0 +1,8 @@
+-- Failed verifications of a user since its last successful one, for backing off and locking it.
+
+CREATE TABLE failures (
+    user TEXT PRIMARY KEY NOT NULL REFERENCES users (name) ON DELETE CASCADE,
+    count INTEGER NOT NULL,
+    -- Seconds since the Unix epoch of the last failure.
+    last INTEGER NOT NULL
+);
@@ -0,0 +1,15 @@
+-- Registration times and the audit log of every user, which exports of its account include.
+
+-- Seconds since the Unix epoch, null for users registered before it was recorded.
+ALTER TABLE users ADD COLUMN registered INTEGER;
+
+CREATE TABLE audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user TEXT NOT NULL REFERENCES users (name) ON DELETE CASCADE,
+    -- Seconds since the Unix epoch.
+    time INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    session_id TEXT
+);
+
+CREATE INDEX audit_user ON audit (user);
@@ -0,0 +1,11 @@
+-- Deleted users, kept apart from the users table so that the record outlives the cascade that
+-- removes their audit log.
+
+CREATE TABLE deletions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user TEXT NOT NULL,
+    -- Seconds since the Unix epoch.
+    time INTEGER NOT NULL
+);
+
+CREATE INDEX deletions_user ON deletions (user);
@@ -0,0 +1,7 @@
+-- One-time nonce last issued to an answered session, which a password change within it is bound
+-- to, removed along with the session.
+
+CREATE TABLE session_nonces (
+    session_id TEXT PRIMARY KEY NOT NULL REFERENCES sessions (session_id) ON DELETE CASCADE,
+    nonce BLOB NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Fingerprint of the group parameters and generators of every ciphersuite, recorded on the first
+-- start with this table, which credentials of the ciphersuite are registered under. Users stored
+-- before it existed are assumed to match the parameters configured then.
+
+CREATE TABLE parameters (
+    ciphersuite TEXT PRIMARY KEY NOT NULL,
+    fingerprint BLOB NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Seconds since the Unix epoch the session was started at, for the maximum session age. Sessions
+-- from before it was tracked count as started when they were last used.
+
+ALTER TABLE sessions ADD COLUMN started INTEGER NOT NULL DEFAULT 0;
+
+UPDATE sessions SET started = last_seen;
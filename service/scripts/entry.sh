@@ -4,7 +4,7 @@ set -e
 case "$1" in
   app )
     echo 'entry.sh: Running in app mode'
-    ./chaum-pedersen-auth
+    ./chaum-pedersen-auth "${@:2}"
     ;;
   test )
     echo 'entry.sh: Running in Test mode'
@@ -0,0 +1,512 @@
+//! Service configuration: defaults, overridden by a TOML file, then by the environment variables
+//! the service has always read and finally by command line flags.
+
+use std::{net::{IpAddr, SocketAddr}, path::{Path, PathBuf}, time::Duration};
+
+use eyre::{eyre, Result, WrapErr};
+use http::{header::HeaderName, HeaderValue};
+use protocol::{ChaumPedersen, ChaumPedersenK256, Registry};
+use serde::Deserialize;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub listen: SocketAddr,
+    /// Address to serve Prometheus metrics on at `/metrics`, none if not set.
+    pub metrics_listen: Option<SocketAddr>,
+    /// `env_logger` filter, such as `info` or `chaum_pedersen_auth=debug`.
+    pub log_level: String,
+    pub realm: Option<String>,
+    pub parameters: Parameters,
+    /// Ciphersuites that keep verifying their users but refuse new registrations.
+    pub deprecated_ciphersuites: Vec<String>,
+    pub storage: Storage,
+    pub sessions: Sessions,
+    pub lockout: Lockout,
+    pub rate_limit: RateLimit,
+    pub enumeration: Enumeration,
+    pub cors: Cors,
+    /// Keys signing session tokens, the first one signing and the rest only verifying.
+    pub signing_keys: Vec<SigningKey>,
+    /// Serves plaintext if not set.
+    pub tls: Option<Tls>,
+}
+
+
+/// Group parameters, decimal strings since they do not fit into TOML integers.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    pub p: Option<String>,
+    pub q: Option<String>,
+    pub g: Option<String>,
+    pub h: Option<String>,
+    pub k256_h_offset: Option<u64>,
+}
+
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(tag = "backend", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Storage {
+    #[default]
+    Memory,
+    Sqlite { path: PathBuf },
+}
+
+
+/// Lifetimes in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sessions {
+    pub challenge_ttl: u64,
+    pub session_ttl: u64,
+    pub idle_timeout: u64,
+    /// Age after which a session cannot be refreshed any more, however often it was.
+    pub max_age: u64,
+}
+
+
+/// Backing off users that fail to verify, delays in seconds.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Lockout {
+    /// Failures after which every further attempt has to wait, doubling from `base_delay`.
+    pub threshold: u32,
+    pub base_delay: u64,
+    pub max_delay: u64,
+    /// Failures after which the user is locked for `lock_duration`.
+    pub lock_after: u32,
+    pub lock_duration: u64,
+}
+
+
+/// Budgets of calls per client address, refilled continuously.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub enabled: bool,
+    /// Proxies whose `x-forwarded-for` header names the client.
+    pub trusted_proxies: Vec<IpAddr>,
+    pub register: Limit,
+    pub challenge: Limit,
+    pub verify: Limit,
+}
+
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Calls a client can make at once.
+    pub burst: u32,
+    /// Calls a client regains per minute.
+    pub per_minute: u32,
+}
+
+
+/// Hiding which users are registered.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Enumeration {
+    /// Answer challenges of unknown users with decoys and registrations of taken names as if
+    /// they succeeded.
+    pub protect: bool,
+    /// Secret the decoys derive from, at least 32 characters. Changing it changes every decoy.
+    pub secret: Option<String>,
+    /// Milliseconds registrations, challenges and verifications take at least when protecting.
+    pub min_response_time: u64,
+    /// Ciphersuites whose decoys enrol the device factor along with the password, which should be
+    /// those whose clients register their users with a device key.
+    pub device_ciphersuites: Vec<String>,
+}
+
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cors {
+    /// Origins allowed to call the service from a browser, any origin if empty.
+    pub origins: Vec<String>,
+    /// Seconds browsers may cache a preflight response.
+    pub max_age: u64,
+    pub exposed_headers: Vec<String>,
+    pub allow_headers: Vec<String>,
+}
+
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SigningKey {
+    pub kid: String,
+    /// PKCS#8 PEM Ed25519 key.
+    pub path: PathBuf,
+}
+
+
+/// PEM files, re-read on SIGHUP.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// CA whose client certificates admin and internal RPCs require. Clients without a
+    /// certificate can still call the other RPCs.
+    pub client_ca: Option<PathBuf>,
+}
+
+
+impl Enumeration {
+    pub fn secret(&self) -> Result<&str> {
+        let secret = self.secret.as_deref()
+            .ok_or_else(|| eyre!("enumeration secret is not set, neither in the config file nor as ENUMERATION_SECRET"))?;
+        if secret.len() < 32 {
+            return Err(eyre!("enumeration secret must have at least 32 characters"));
+        }
+        Ok(secret)
+    }
+}
+
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: "[::0]:50051".parse().unwrap(),
+            metrics_listen: None,
+            log_level: "info".to_string(),
+            realm: None,
+            parameters: Parameters::default(),
+            deprecated_ciphersuites: Vec::new(),
+            storage: Storage::default(),
+            sessions: Sessions::default(),
+            lockout: Lockout::default(),
+            rate_limit: RateLimit::default(),
+            enumeration: Enumeration::default(),
+            cors: Cors::default(),
+            signing_keys: Vec::new(),
+            tls: None,
+        }
+    }
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self { challenge_ttl: 60, session_ttl: 24 * 60 * 60, idle_timeout: 30 * 60, max_age: 7 * 24 * 60 * 60 }
+    }
+}
+
+impl Default for Lockout {
+    fn default() -> Self {
+        Self { threshold: 3, base_delay: 1, max_delay: 60, lock_after: 10, lock_duration: 15 * 60 }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trusted_proxies: Vec::new(),
+            register: Limit { burst: 10, per_minute: 10 },
+            challenge: Limit { burst: 30, per_minute: 60 },
+            verify: Limit { burst: 30, per_minute: 60 },
+        }
+    }
+}
+
+impl Default for Enumeration {
+    fn default() -> Self {
+        Self {
+            protect: false,
+            secret: None,
+            min_response_time: 200,
+            device_ciphersuites: vec![ChaumPedersenK256::CIPHERSUITE.to_string()],
+        }
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            origins: Vec::new(),
+            max_age: 24 * 60 * 60,
+            exposed_headers: ["grpc-status", "grpc-message", "grpc-status-details-bin", "retry-after"].map(String::from).to_vec(),
+            allow_headers: ["x-grpc-web", "content-type", "x-user-agent", "grpc-timeout"].map(String::from).to_vec(),
+        }
+    }
+}
+
+
+fn parse_env<T: std::str::FromStr>(name: &str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| eyre!("{} is not an integer", name))
+}
+
+
+impl Config {
+    /// Reads the TOML file at `path`, if any, and applies the environment as seen through `env`.
+    pub fn load(path: Option<&Path>, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).wrap_err_with(|| format!("cannot read {}", path.display()))?;
+                Self::parse(&text).wrap_err_with(|| format!("invalid config file {}", path.display()))?
+            }
+            None => Self::default(),
+        };
+        config.apply_env(env)?;
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(level) = env("RUST_LOG") {
+            self.log_level = level;
+        }
+        if let Some(realm) = env("REALM") {
+            self.realm = Some(realm);
+        }
+        for (name, value) in [
+            ("P", &mut self.parameters.p),
+            ("Q", &mut self.parameters.q),
+            ("G", &mut self.parameters.g),
+            ("H", &mut self.parameters.h),
+        ] {
+            if let Some(env) = env(name) {
+                *value = Some(env);
+            }
+        }
+        if let Some(offset) = env("K256_H_OFFSET") {
+            self.parameters.k256_h_offset = Some(parse_env("K256_H_OFFSET", &offset)?);
+        }
+        if let Some(ids) = env("DEPRECATED_CIPHERSUITES") {
+            self.deprecated_ciphersuites = ids.split(',').map(str::trim).filter(|id| !id.is_empty()).map(String::from).collect();
+        }
+        for (name, value) in [
+            ("CHALLENGE_TTL", &mut self.sessions.challenge_ttl),
+            ("SESSION_TTL", &mut self.sessions.session_ttl),
+            ("SESSION_IDLE_TIMEOUT", &mut self.sessions.idle_timeout),
+            ("SESSION_MAX_AGE", &mut self.sessions.max_age),
+        ] {
+            if let Some(env) = env(name) {
+                *value = parse_env(name, &env)?;
+            }
+        }
+        if let Some(secret) = env("ENUMERATION_SECRET") {
+            self.enumeration.secret = Some(secret);
+        }
+        Ok(())
+    }
+
+    pub fn realm(&self) -> Result<&str> {
+        self.realm.as_deref().ok_or_else(|| eyre!("realm is not set, neither in the config file nor as REALM"))
+    }
+
+    pub fn protocols(&self) -> Result<(ChaumPedersen, ChaumPedersenK256)> {
+        let parameter = |name: &str, value: &Option<String>| {
+            value.as_deref()
+                .ok_or_else(|| eyre!("parameter {} is not set, neither in the config file nor as {}", name, name.to_uppercase()))?
+                .parse()
+                .map_err(|_| eyre!("parameter {} is not an integer", name))
+        };
+        let parameters = &self.parameters;
+        let protocol = ChaumPedersen::new(
+            parameter("p", &parameters.p)?,
+            parameter("q", &parameters.q)?,
+            parameter("g", &parameters.g)?,
+            parameter("h", &parameters.h)?,
+        );
+        let offset = parameters.k256_h_offset
+            .ok_or_else(|| eyre!("parameter k256_h_offset is not set, neither in the config file nor as K256_H_OFFSET"))?;
+        Ok((protocol, ChaumPedersenK256::new(offset)))
+    }
+
+    /// Registry of the ciphersuites of `protocols`, with the configured ones deprecated.
+    pub fn registry(&self, protocols: &(ChaumPedersen, ChaumPedersenK256)) -> Result<Registry> {
+        let mut registry = Registry::new();
+        registry.insert(protocols.0.as_multi());
+        registry.insert(protocols.1.as_multi());
+        for id in &self.deprecated_ciphersuites {
+            registry.deprecate(id).map_err(|error| eyre!("cannot deprecate {}: {}", id, error))?;
+        }
+        Ok(registry)
+    }
+
+    pub fn challenge_ttl(&self) -> Duration {
+        Duration::from_secs(self.sessions.challenge_ttl)
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.sessions.session_ttl)
+    }
+
+    pub fn session_idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.sessions.idle_timeout)
+    }
+
+    pub fn session_max_age(&self) -> Duration {
+        Duration::from_secs(self.sessions.max_age)
+    }
+
+    /// How long registrations, challenges and verifications take at least, zero if they are not
+    /// padded.
+    pub fn min_response_time(&self) -> Duration {
+        if self.enumeration.protect {
+            Duration::from_millis(self.enumeration.min_response_time)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    pub fn cors(&self) -> Result<CorsLayer> {
+        let headers = |names: &[String]| {
+            names.iter()
+                .map(|name| HeaderName::try_from(name.as_str()).map_err(|_| eyre!("invalid header name {}", name)))
+                .collect::<Result<Vec<_>>>()
+        };
+        let origin = if self.cors.origins.is_empty() {
+            AllowOrigin::mirror_request()
+        } else {
+            AllowOrigin::list(self.cors.origins.iter()
+                .map(|origin| HeaderValue::try_from(origin.as_str()).map_err(|_| eyre!("invalid origin {}", origin)))
+                .collect::<Result<Vec<_>>>()?)
+        };
+        Ok(CorsLayer::new()
+            .allow_origin(origin)
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(self.cors.max_age))
+            .expose_headers(headers(&self.cors.exposed_headers)?)
+            .allow_headers(headers(&self.cors.allow_headers)?))
+    }
+
+    /// Checks everything that can be checked without binding the listener or opening the store.
+    pub fn check(&self) -> Result<()> {
+        self.realm()?;
+        let protocols = self.protocols()?;
+        if !protocols.0.check_parameters() {
+            return Err(eyre!("parameters g and h must be elements of the subgroup of order q modulo p other than 1, with q below p"));
+        }
+        let registry = self.registry(&protocols)?;
+        for id in &self.enumeration.device_ciphersuites {
+            registry.get(id).map_err(|error| eyre!("cannot give decoys of {} a device factor: {}", id, error))?;
+        }
+        self.cors().map(drop)?;
+        if self.sessions.challenge_ttl == 0 || self.sessions.session_ttl == 0 || self.sessions.idle_timeout == 0 {
+            return Err(eyre!("session lifetimes must be at least a second"));
+        }
+        if self.sessions.max_age < self.sessions.session_ttl {
+            return Err(eyre!("session max_age must not be below session_ttl"));
+        }
+        let limits = [self.rate_limit.register, self.rate_limit.challenge, self.rate_limit.verify];
+        if limits.iter().any(|limit| limit.burst == 0 || limit.per_minute == 0) {
+            return Err(eyre!("rate limits must allow at least one call"));
+        }
+        if self.lockout.lock_after < self.lockout.threshold {
+            return Err(eyre!("lockout lock_after must not be below its threshold"));
+        }
+        if self.enumeration.protect {
+            self.enumeration.secret()?;
+        }
+        if let Storage::Sqlite { path } = &self.storage {
+            if path.as_os_str().is_empty() {
+                return Err(eyre!("storage path is empty"));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../config.example.toml");
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_example() {
+        let config = Config::parse(EXAMPLE).unwrap();
+        config.check().unwrap();
+        assert_eq!(config.listen, "[::0]:50051".parse().unwrap());
+        assert_eq!(config.realm().unwrap(), "chaum-pedersen-auth");
+        assert_eq!(config.storage, Storage::Sqlite { path: "chaum-pedersen-auth.db".into() });
+        assert_eq!(config.signing_keys, [SigningKey { kid: "2023-10".to_string(), path: "2023-10.pem".into() }]);
+        assert_eq!(config.cors.origins, ["http://localhost:8080"]);
+        assert_eq!(config.session_idle_timeout(), Duration::from_secs(30 * 60));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.storage, Storage::Memory);
+        assert_eq!(config.challenge_ttl(), Duration::from_secs(60));
+        assert!(config.cors.origins.is_empty());
+        assert!(config.check().is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = env(&[
+            ("REALM", "other"),
+            ("P", "363967321904221003"),
+            ("Q", "7696033"),
+            ("G", "165950041202038920"),
+            ("H", "96429580695728554"),
+            ("K256_H_OFFSET", "107211496160805127"),
+            ("DEPRECATED_CIPHERSUITES", " CP-EXP-SHA256-SHA256 ,"),
+            ("SESSION_TTL", "120"),
+            ("SESSION_MAX_AGE", "3600"),
+            ("ENUMERATION_SECRET", "0123456789abcdef0123456789abcdef"),
+        ]);
+        let mut config = Config::parse("realm = \"chaum-pedersen-auth\"\n[sessions]\nsession_ttl = 60\nchallenge_ttl = 30").unwrap();
+        config.apply_env(vars).unwrap();
+        config.check().unwrap();
+        assert_eq!(config.realm().unwrap(), "other");
+        assert_eq!(config.session_ttl(), Duration::from_secs(120));
+        assert_eq!(config.session_max_age(), Duration::from_secs(3600));
+        assert_eq!(config.challenge_ttl(), Duration::from_secs(30));
+        assert_eq!(config.enumeration.secret().unwrap(), "0123456789abcdef0123456789abcdef");
+        assert!(config.registry(&config.protocols().unwrap()).unwrap().is_deprecated(ChaumPedersen::CIPHERSUITE));
+
+        assert!(Config::default().apply_env(env(&[("CHALLENGE_TTL", "a minute")])).is_err());
+        assert!(Config::default().apply_env(env(&[("K256_H_OFFSET", "-1")])).is_err());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Config::parse("listen = \"localhost\"").is_err());
+        assert!(Config::parse("[storage]\nbackend = \"postgres\"").is_err());
+        assert!(Config::parse("[sessions]\nsession_tll = 60").is_err());
+
+        let valid = Config::parse(EXAMPLE).unwrap();
+        let invalid = |edit: fn(&mut Config)| {
+            let mut config = valid.clone();
+            edit(&mut config);
+            config.check().is_err()
+        };
+        assert!(invalid(|config| config.parameters.p = Some("0x10".to_string())));
+        assert!(invalid(|config| config.parameters.k256_h_offset = None));
+        assert!(invalid(|config| config.parameters.g = Some("363967321904221004".to_string())));
+        assert!(invalid(|config| config.parameters.h = Some("2".to_string())));
+        assert!(invalid(|config| config.parameters.q = Some("363967321904221004".to_string())));
+        assert!(invalid(|config| config.deprecated_ciphersuites.push("CP-P256-SHA256-SHA256".to_string())));
+        assert!(invalid(|config| config.sessions.challenge_ttl = 0));
+        assert!(invalid(|config| config.sessions.max_age = config.sessions.session_ttl - 1));
+        assert!(invalid(|config| config.lockout.lock_after = 2));
+        assert!(invalid(|config| config.rate_limit.verify.per_minute = 0));
+        assert!(invalid(|config| config.enumeration.protect = true));
+        assert!(invalid(|config| config.enumeration.device_ciphersuites.push("CP-P256-SHA256-SHA256".to_string())));
+        assert!(invalid(|config| {
+            config.enumeration.protect = true;
+            config.enumeration.secret = Some("too short".to_string());
+        }));
+        assert!(invalid(|config| config.cors.allow_headers.push("not a header".to_string())));
+        assert!(invalid(|config| config.cors.origins.push("http://\n".to_string())));
+    }
+}
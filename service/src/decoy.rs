@@ -0,0 +1,443 @@
+//! Resisting user enumeration. Unknown users get decoy credentials derived from a server secret,
+//! stable across calls and restarts, so that their challenges look like those of registered users
+//! and their verification fails like a wrong password. Registration, challenge and verification
+//! calls are padded to a minimum duration so that their timing does not tell either.
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::{Duration, Instant, SystemTime}};
+
+use eyre::Result;
+use hmac::{Hmac, Mac};
+use protocol::{ChaumPedersen, ChaumPedersenK256, Registry};
+use sha2::Sha256;
+use tonic::{async_trait, body::BoxBody, server::NamedService};
+use tower::{Layer, Service};
+
+use crate::{config::Config, pb2, rate_limit::Budget, store::{AuditEntry, Credentials, Failures, User, UserStore}};
+
+
+/// Failures of decoys kept before stale ones are dropped.
+const MAX_FAILURES: usize = 10_000;
+
+/// Failures of decoys dropped, least recently failed first, when none of them are stale.
+const EVICTED_FAILURES: usize = MAX_FAILURES / 10;
+
+
+/// How a decoy is registered: like users of the Exp or K256 RPCs, or of a registry suite.
+#[derive(Clone, Copy, Debug)]
+enum Kind {
+    Exp,
+    K256,
+    Suite(&'static str),
+}
+
+
+/// Users of `inner`, and a decoy for every name that is not registered.
+pub struct DecoyStore {
+    inner: Arc<dyn UserStore>,
+    secret: Vec<u8>,
+    realm: String,
+    protocol: ChaumPedersen,
+    protocol_k256: ChaumPedersenK256,
+    registry: Registry,
+    /// Kinds of decoys, of the ciphersuites users can still register with.
+    kinds: Vec<Kind>,
+    /// Ciphersuites whose decoys enrol the device factor too.
+    device_ciphersuites: Vec<String>,
+    /// Failures of decoys, counted like those of users so that lockouts do not tell them apart.
+    failures: Mutex<HashMap<String, Failures>>,
+    /// How long failures of decoys matter for a lockout.
+    failures_ttl: Duration,
+}
+
+impl DecoyStore {
+    pub fn new(config: &Config, inner: Arc<dyn UserStore>) -> Result<Self> {
+        let protocols = config.protocols()?;
+        let registry = config.registry(&protocols)?;
+        let kinds = decoy_kinds(&registry);
+        Ok(Self {
+            inner,
+            secret: config.enumeration.secret()?.as_bytes().to_vec(),
+            realm: config.realm()?.to_string(),
+            protocol: protocols.0,
+            protocol_k256: protocols.1,
+            registry,
+            kinds,
+            device_ciphersuites: config.enumeration.device_ciphersuites.clone(),
+            failures: Mutex::new(HashMap::new()),
+            failures_ttl: Duration::from_secs(config.lockout.lock_duration.max(config.lockout.max_delay)),
+        })
+    }
+
+    fn decoy(&self, name: &str) -> User {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC takes keys of any length");
+        mac.update(b"chaum-pedersen-auth decoy");
+        mac.update(name.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        let password: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        let (ciphersuite, y) = match self.kinds[digest[0] as usize % self.kinds.len()] {
+            Kind::Exp => {
+                let y = self.protocol.register(&self.realm, name, &password);
+                (ChaumPedersen::CIPHERSUITE, Credentials::Exp(y))
+            }
+            Kind::K256 => {
+                let y = self.protocol_k256.register(&self.realm, name, &password);
+                (ChaumPedersenK256::CIPHERSUITE, Credentials::K256(y))
+            }
+            Kind::Suite(id) => {
+                let y = self.registry.get(id).expect("registered ciphersuite").register(&self.realm, name, &password);
+                (id, Credentials::Encoded(y))
+            }
+        };
+        let factors = if self.device_ciphersuites.iter().any(|id| id == ciphersuite) {
+            vec![pb2::Factor::Password, pb2::Factor::Device]
+        } else {
+            vec![pb2::Factor::Password]
+        };
+        User {
+            name: name.to_string(),
+            realm: self.realm.clone(),
+            ciphersuite: ciphersuite.to_string(),
+            factors,
+            y,
+            registered: None,
+        }
+    }
+
+    async fn is_registered(&self, name: &str) -> Result<bool> {
+        Ok(self.inner.get_user(name).await?.is_some())
+    }
+}
+
+
+/// Kinds of decoys: decoys in a ciphersuite new users cannot register with would stand out, so
+/// deprecated ones are left out unless every ciphersuite is.
+fn decoy_kinds(registry: &Registry) -> Vec<Kind> {
+    let mut suites: Vec<_> = registry.ids().filter(|(_, deprecated)| !deprecated).map(|(id, _)| id).collect();
+    if suites.is_empty() {
+        suites = registry.ids().map(|(id, _)| id).collect();
+    }
+    let mut kinds = Vec::new();
+    if suites.contains(&ChaumPedersen::CIPHERSUITE) {
+        kinds.push(Kind::Exp);
+    }
+    if suites.contains(&ChaumPedersenK256::CIPHERSUITE) {
+        kinds.push(Kind::K256);
+    }
+    kinds.extend(suites.into_iter().map(Kind::Suite));
+    kinds
+}
+
+
+#[async_trait]
+impl UserStore for DecoyStore {
+    async fn insert_user(&self, user: User) -> Result<bool> {
+        let name = user.name.clone();
+        let inserted = self.inner.insert_user(user).await?;
+        if inserted {
+            self.failures.lock().unwrap().remove(&name);
+        }
+        Ok(inserted)
+    }
+
+    async fn get_user(&self, name: &str) -> Result<Option<User>> {
+        Ok(Some(match self.inner.get_user(name).await? {
+            Some(user) => user,
+            None => self.decoy(name),
+        }))
+    }
+
+    async fn get_failures(&self, name: &str) -> Result<Option<Failures>> {
+        if self.is_registered(name).await? {
+            self.inner.get_failures(name).await
+        } else {
+            Ok(self.failures.lock().unwrap().get(name).cloned())
+        }
+    }
+
+    async fn record_failure(&self, name: &str, now: SystemTime) -> Result<Failures> {
+        if self.is_registered(name).await? {
+            return self.inner.record_failure(name, now).await;
+        }
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= MAX_FAILURES {
+            failures.retain(|_, failures| now.duration_since(failures.last).unwrap_or_default() < self.failures_ttl);
+            if failures.len() >= MAX_FAILURES {
+                let mut last: Vec<_> = failures.values().map(|failures| failures.last).collect();
+                let (_, cutoff, _) = last.select_nth_unstable(EVICTED_FAILURES);
+                let cutoff = *cutoff;
+                failures.retain(|_, failures| failures.last >= cutoff);
+            }
+        }
+        let entry = failures.entry(name.to_string()).or_insert(Failures { count: 0, last: now });
+        entry.count = entry.count.saturating_add(1);
+        entry.last = now;
+        Ok(entry.clone())
+    }
+
+    async fn reset_failures(&self, name: &str) -> Result<bool> {
+        if self.is_registered(name).await? {
+            self.inner.reset_failures(name).await
+        } else {
+            Ok(self.failures.lock().unwrap().remove(name).is_some())
+        }
+    }
+
+    async fn replace_credentials(&self, name: &str, old: &Credentials, new: Credentials) -> Result<bool> {
+        self.inner.replace_credentials(name, old, new).await
+    }
+
+    async fn remove_user(&self, name: &str, now: SystemTime) -> Result<bool> {
+        self.failures.lock().unwrap().remove(name);
+        self.inner.remove_user(name, now).await
+    }
+
+    async fn record_event(&self, name: &str, entry: AuditEntry) -> Result<()> {
+        self.inner.record_event(name, entry).await
+    }
+
+    async fn get_events(&self, name: &str) -> Result<Vec<AuditEntry>> {
+        self.inner.get_events(name).await
+    }
+
+    async fn pin_parameters(&self, ciphersuite: &str, fingerprint: &[u8]) -> Result<bool> {
+        self.inner.pin_parameters(ciphersuite, fingerprint).await
+    }
+}
+
+
+/// Layer delaying the responses of registration, challenge and verification calls until they
+/// took at least `minimum`.
+#[derive(Clone)]
+pub struct PaddingLayer {
+    minimum: Duration,
+}
+
+impl PaddingLayer {
+    pub fn new(minimum: Duration) -> Self {
+        Self { minimum }
+    }
+}
+
+impl<S> Layer<S> for PaddingLayer {
+    type Service = PaddingService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PaddingService { inner, minimum: self.minimum }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct PaddingService<S> {
+    inner: S,
+    minimum: Duration,
+}
+
+impl<S, B> Service<http::Request<B>> for PaddingService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        if self.minimum.is_zero() || Budget::of(request.uri().path()).is_none() {
+            return Box::pin(self.inner.call(request));
+        }
+        let deadline = tokio::time::Instant::from_std(Instant::now() + self.minimum);
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await;
+            tokio::time::sleep_until(deadline).await;
+            response
+        })
+    }
+}
+
+impl<S: NamedService> NamedService for PaddingService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, convert::Infallible};
+
+    use k256::{elliptic_curve::{point::AffineCoordinates, PrimeField}, AffinePoint};
+    use tonic::{Code, Request};
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+    use crate::{pb2::auth_server::Auth, store::{Event, MemoryStore}, token::Keys, API};
+
+    fn config(secret: &str) -> Config {
+        let mut config = Config::parse(include_str!("../config.example.toml")).unwrap();
+        config.enumeration.protect = true;
+        config.enumeration.secret = Some(secret.to_string());
+        config
+    }
+
+    fn setup_store(secret: &str) -> DecoyStore {
+        DecoyStore::new(&config(secret), Arc::new(MemoryStore::default())).unwrap()
+    }
+
+    fn encoding(user: &User) -> String {
+        format!("{} {:?} {:?}", user.ciphersuite, user.factors, user.y)
+    }
+
+    #[tokio::test]
+    async fn test_decoys() {
+        let store = setup_store("0123456789abcdef0123456789abcdef");
+        let alice = store.get_user("alice").await.unwrap().unwrap();
+        assert_eq!(alice.realm, "chaum-pedersen-auth");
+        assert_eq!(encoding(&alice), encoding(&store.get_user("alice").await.unwrap().unwrap()));
+        assert_eq!(encoding(&alice), encoding(&setup_store("0123456789abcdef0123456789abcdef").get_user("alice").await.unwrap().unwrap()));
+        assert_ne!(encoding(&alice), encoding(&setup_store("fedcba9876543210fedcba9876543210").get_user("alice").await.unwrap().unwrap()));
+        let mut kinds = HashSet::new();
+        for name in 0..32 {
+            let decoy = store.get_user(&name.to_string()).await.unwrap().unwrap();
+            kinds.insert(format!("{} {}", decoy.ciphersuite, matches!(decoy.y, Credentials::Encoded(_))));
+        }
+        assert_eq!(kinds.len(), 4);
+
+        let mut config = config("0123456789abcdef0123456789abcdef");
+        config.deprecated_ciphersuites.push(ChaumPedersen::CIPHERSUITE.to_string());
+        let deprecated = DecoyStore::new(&config, Arc::new(MemoryStore::default())).unwrap();
+        let mut kinds = HashSet::new();
+        for name in 0..32 {
+            let decoy = deprecated.get_user(&name.to_string()).await.unwrap().unwrap();
+            assert_eq!(decoy.ciphersuite, ChaumPedersenK256::CIPHERSUITE);
+            kinds.insert(matches!(decoy.y, Credentials::Encoded(_)));
+        }
+        assert_eq!(kinds.len(), 2);
+
+        let now = SystemTime::now();
+        assert_eq!(store.record_failure("alice", now).await.unwrap().count, 1);
+        assert_eq!(store.get_failures("alice").await.unwrap().unwrap().count, 1);
+        assert!(store.insert_user(User { factors: vec![pb2::Factor::Password], ..alice.clone() }).await.unwrap());
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert_eq!(store.record_failure("alice", now).await.unwrap().count, 1);
+        assert_eq!(store.inner.get_failures("alice").await.unwrap().unwrap().count, 1);
+        assert_eq!(store.get_user("alice").await.unwrap().unwrap().factors, [pb2::Factor::Password]);
+    }
+
+    #[tokio::test]
+    async fn test_max_failures() {
+        let store = setup_store("0123456789abcdef0123456789abcdef");
+        let now = SystemTime::now();
+        for name in 0..MAX_FAILURES {
+            store.record_failure(&name.to_string(), now + Duration::from_millis(name as u64)).await.unwrap();
+        }
+        let later = now + Duration::from_secs(1);
+        assert_eq!(store.record_failure("mallory", later).await.unwrap().count, 1);
+        assert_eq!(store.failures.lock().unwrap().len(), MAX_FAILURES - EVICTED_FAILURES + 1);
+        assert!(store.get_failures("0").await.unwrap().is_none());
+        assert_eq!(store.get_failures(&EVICTED_FAILURES.to_string()).await.unwrap().unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user() {
+        let config = config("0123456789abcdef0123456789abcdef");
+        let store = Arc::new(MemoryStore::default());
+        let users = Arc::new(DecoyStore::new(&config, store.clone()).unwrap());
+        let api = API::new(&config, users.clone(), store, Keys::generate().unwrap()).unwrap();
+        let registry = config.registry(&config.protocols().unwrap()).unwrap();
+        let suite = registry.get(ChaumPedersenK256::CIPHERSUITE).unwrap();
+        let register = || {
+            let (r, s) = suite.prove_registration("chaum-pedersen-auth", "alice", "password", &mut rand::thread_rng());
+            Request::new(pb2::SuiteRegisterRequest {
+                user: "alice".to_string(),
+                ciphersuite: suite.id().to_string(),
+                y: suite.register("chaum-pedersen-auth", "alice", "password"),
+                factors: Vec::new(),
+                r,
+                s,
+            })
+        };
+        api.suite_register(register()).await.unwrap();
+        api.suite_register(register()).await.unwrap();
+        api.suite_register(register()).await.unwrap();
+        let events = users.get_events("alice").await.unwrap();
+        assert_eq!(events.iter().map(|entry| entry.event).collect::<Vec<_>>(), [Event::RegistrationAttempt]);
+
+        let user = (0..).map(|name| format!("mallory-{}", name))
+            .find(|name| {
+                let decoy = users.decoy(name);
+                decoy.ciphersuite == suite.id() && matches!(decoy.y, Credentials::Encoded(_))
+            })
+            .unwrap();
+        for (user, password) in [("alice", "wrong"), (user.as_str(), "password")] {
+            let (k, r) = suite.commit(&mut rand::thread_rng());
+            let challenge = api.suite_create_authentication_challenge(Request::new(pb2::SuiteAuthenticationChallengeRequest {
+                user: user.to_string(),
+                ciphersuite: suite.id().to_string(),
+                r,
+            })).await.unwrap().into_inner();
+            assert_eq!(challenge.realm, "chaum-pedersen-auth");
+            let s = suite.solve(&challenge.realm, user, password, &k, &challenge.c).unwrap();
+            let status = api.suite_verify_authentication(Request::new(pb2::SuiteAuthenticationAnswerRequest { auth_id: challenge.auth_id, s })).await.unwrap_err();
+            assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "invalid password"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_decoys() {
+        let config = config("0123456789abcdef0123456789abcdef");
+        let store = Arc::new(MemoryStore::default());
+        let users = Arc::new(DecoyStore::new(&config, store.clone()).unwrap());
+        let api = API::new(&config, users.clone(), store, Keys::generate().unwrap()).unwrap();
+        let protocol = config.protocols().unwrap().1;
+        let point = |point: AffinePoint| Some(pb2::Point { x: point.x().to_vec(), is_y_odd: point.y_is_odd().into() });
+        let device_key = protocol.device_key();
+        let (y1, y2) = protocol.register_with_device("chaum-pedersen-auth", "alice", "password", &device_key);
+        let (r1, r2, s) = protocol.prove_registration_with_device("chaum-pedersen-auth", "alice", "password", &device_key);
+        api.k256_register(Request::new(pb2::K256RegisterRequest {
+            user: "alice".to_string(),
+            y1: point(y1),
+            y2: point(y2),
+            factors: vec![pb2::Factor::Device as i32],
+            r1: point(r1),
+            r2: point(r2),
+            s: s.to_repr().to_vec(),
+        })).await.unwrap();
+
+        let decoy = (0..).map(|name| format!("mallory-{}", name))
+            .find(|name| matches!(users.decoy(name).y, Credentials::K256(_)))
+            .unwrap();
+        let mut challenges = Vec::new();
+        for user in ["alice", decoy.as_str()] {
+            let (_, r1, r2) = protocol.commit();
+            let challenge = api.k256_create_authentication_challenge(Request::new(pb2::K256AuthenticationChallengeRequest {
+                user: user.to_string(),
+                r1: point(r1),
+                r2: point(r2),
+            })).await.unwrap().into_inner();
+            challenges.push((challenge.realm, challenge.factors, challenge.c.len()));
+        }
+        assert_eq!(challenges[0], challenges[1]);
+        assert_eq!(challenges[0].1, [pb2::Factor::Password as i32, pb2::Factor::Device as i32]);
+    }
+
+    async fn ok(_request: http::Request<()>) -> Result<http::Response<BoxBody>, Infallible> {
+        Ok(http::Response::new(tonic::body::empty_body()))
+    }
+
+    #[tokio::test]
+    async fn test_padding() {
+        let service = PaddingLayer::new(Duration::from_millis(50)).layer(service_fn(ok));
+        let started = Instant::now();
+        let request = http::Request::builder().uri("/zkp_auth.Auth/VerifyAuthentication").body(()).unwrap();
+        service.clone().oneshot(request).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        let started = Instant::now();
+        service.oneshot(http::Request::builder().uri("/zkp_auth.Auth/GetRealm").body(()).unwrap()).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+}
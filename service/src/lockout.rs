@@ -0,0 +1,42 @@
+//! Backing off users that fail to verify, so that weak passwords cannot be guessed online.
+
+use std::time::{Duration, SystemTime};
+
+use crate::{config::Lockout, store::Failures};
+
+
+/// How long after `now` the user with `failures` has to wait before trying again, if at all.
+pub fn wait(lockout: &Lockout, failures: &Failures, now: SystemTime) -> Option<Duration> {
+    if failures.count < lockout.threshold {
+        return None;
+    }
+    let delay = if failures.count >= lockout.lock_after {
+        lockout.lock_duration
+    } else {
+        let doublings = failures.count - lockout.threshold;
+        lockout.base_delay.saturating_mul(1u64.checked_shl(doublings).unwrap_or(u64::MAX)).min(lockout.max_delay)
+    };
+    let elapsed = now.duration_since(failures.last).unwrap_or_default();
+    Duration::from_secs(delay).checked_sub(elapsed).filter(|wait| !wait.is_zero())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wait() {
+        let lockout = Lockout::default();
+        let last = SystemTime::now();
+        let wait = |count, after| wait(&lockout, &Failures { count, last }, last + Duration::from_secs(after));
+        assert_eq!(wait(2, 0), None);
+        assert_eq!(wait(3, 0), Some(Duration::from_secs(1)));
+        assert_eq!(wait(3, 1), None);
+        assert_eq!(wait(5, 1), Some(Duration::from_secs(3)));
+        assert_eq!(wait(9, 0), Some(Duration::from_secs(60)));
+        assert_eq!(wait(10, 60), Some(Duration::from_secs(15 * 60 - 60)));
+        assert_eq!(wait(u32::MAX, 15 * 60), None);
+        assert_eq!(super::wait(&Lockout { lock_after: u32::MAX, max_delay: u64::MAX, ..lockout }, &Failures { count: 80, last }, last), Some(Duration::from_secs(u64::MAX)));
+    }
+}
@@ -1,16 +1,31 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use http::header::HeaderName;
+use clap::Parser;
 use eyre::Result;
-use k256::{elliptic_curve::{PrimeField, point::DecompressPoint, subtle::Choice, generic_array::GenericArray}, AffinePoint, Scalar};
+use k256::{elliptic_curve::{PrimeField, point::DecompressPoint, subtle::Choice}, AffinePoint, Scalar};
 use num_bigint::BigUint;
-use protocol::{ChaumPedersen, ChaumPedersenK256};
-use tokio::sync::Mutex;
+use protocol::{change_context, And, ChaumPedersen, ChaumPedersenK256, Group, K256Group, Registry, Statement, Transcript};
+use tokio::net::TcpListener;
 use tonic::{transport::Server, Request, Response, Status};
 use tonic_web::GrpcWebLayer;
-use tower_http::cors::{AllowOrigin, CorsLayer};
 use uuid::Uuid;
 
+use config::{Config, Lockout, SigningKey, Storage};
+use decoy::{DecoyStore, PaddingLayer};
+use store::{AuditEntry, Composition, Credentials, Event, MemoryStore, Session, SessionStore, SqliteStore, User, UserStore};
+use middleware::Claims;
+use rate_limit::{RateLimitLayer, RateLimiter};
+use tls::Tls;
+use token::Keys;
+
+mod config;
+mod decoy;
+mod lockout;
+mod rate_limit;
+mod store;
+mod tls;
+mod token;
+
 mod pb2 {
     tonic::include_proto!("zkp_auth");
     pub(crate) const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("descriptor");
@@ -18,70 +33,398 @@ mod pb2 {
 
 
 
-#[derive(Debug)]
-enum Credentials {
-    Exp((BigUint, BigUint)),
-    K256((AffinePoint, AffinePoint)),
+/// Registration attempts of a taken name merged into one audit entry, so that callers cannot
+/// flood the audit log of a user.
+const REGISTRATION_ATTEMPT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+
+fn decompress(point: &pb2::Point) -> Option<AffinePoint> {
+    AffinePoint::decompress(point.x.as_slice().into(), Choice::from(point.is_y_odd as u8)).into()
+}
+
+
+/// Factors enrolled for a new user, the password always being one of them.
+fn enrolled_factors(factors: &[i32]) -> Option<Vec<pb2::Factor>> {
+    let mut enrolled = vec![pb2::Factor::Password];
+    for factor in factors {
+        let factor = pb2::Factor::try_from(*factor).ok()?;
+        if !enrolled.contains(&factor) {
+            enrolled.push(factor);
+        }
+    }
+    Some(enrolled)
+}
+
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+}
+
+
+/// Hex fingerprint of the transcript binding a verified login together, for the logs.
+fn fingerprint(transcript: &Transcript) -> String {
+    transcript.fingerprint().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+
+fn k256_scalar(bytes: &[u8]) -> Option<Scalar> {
+    let bytes: [u8; 32] = bytes.try_into().ok()?;
+    Scalar::from_repr(bytes.into()).into()
+}
+
+
+/// `resource_exhausted`, with the seconds to wait in the `retry-after` metadata.
+fn throttled(message: &str, wait: Duration) -> Status {
+    let mut status = Status::resource_exhausted(message);
+    status.metadata_mut().insert("retry-after", (wait.as_secs() + u64::from(wait.subsec_nanos() > 0)).into());
+    status
 }
 
 
-#[derive(Debug)]
-struct Session {
-    id: Option<Uuid>,
-    user: String,
-    r: Credentials,
-    c: BigUint,
+/// Logs a storage or signing failure and hides its details from the client.
+fn internal_error(error: eyre::Report) -> Status {
+    log::error!("internal error: {:?}", error);
+    Status::internal("internal error")
 }
 
 
-#[derive(Debug)]
-struct User {
-    name: String,
-    y: Credentials,
+/// How a caller proved again to be a user.
+enum Reauthenticated {
+    /// By answering one of its challenges, which has been taken.
+    Challenge(Box<Session>),
+    /// With one of its live sessions.
+    Session(Claims),
 }
 
 
 pub struct API {
-    users: Arc<Mutex<HashMap<String, User>>>,
-    sessions: Arc<Mutex<HashMap<Uuid, Session>>>,
+    users: Arc<dyn UserStore>,
+    sessions: Arc<dyn SessionStore>,
+    realm: String,
     protocol: ChaumPedersen,
     protocol_k256: ChaumPedersenK256,
+    registry: Registry,
+    challenge_ttl: Duration,
+    session_ttl: Duration,
+    session_idle_timeout: Duration,
+    session_max_age: Duration,
+    keys: Keys,
+    lockout: Lockout,
+    client_certificates: bool,
+    protect_enumeration: bool,
 }
 
+
 impl API {
-    fn new() -> Self {
-        Self {
-            users: Arc::new(Mutex::new(HashMap::new())),
-            sessions: Arc::new(Mutex::new(HashMap::new())),
-            protocol: ChaumPedersen::new(
-                std::env::var("P").expect("P env var must be set.").parse().expect("P is not an integer"),
-                std::env::var("Q").expect("Q env var must be set.").parse().expect("Q is not an integer"),
-                std::env::var("G").expect("G env var must be set.").parse().expect("G is not an integer"),
-                std::env::var("H").expect("H env var must be set.").parse().expect("H is not an integer"),
-            ),
-            protocol_k256: ChaumPedersenK256::new(
-                std::env::var("K256_H_OFFSET").expect("K256_H_OFFSET env var must be set.").parse().expect("K256_H_OFFSET is not an integer"),
-            ),
+    fn new(config: &Config, users: Arc<dyn UserStore>, sessions: Arc<dyn SessionStore>, keys: Keys) -> Result<Self> {
+        let protocols = config.protocols()?;
+        let registry = config.registry(&protocols)?;
+        let (protocol, protocol_k256) = protocols;
+        Ok(Self {
+            users,
+            sessions,
+            realm: config.realm()?.to_string(),
+            protocol,
+            protocol_k256,
+            registry,
+            challenge_ttl: config.challenge_ttl(),
+            session_ttl: config.session_ttl(),
+            session_idle_timeout: config.session_idle_timeout(),
+            session_max_age: config.session_max_age(),
+            keys,
+            lockout: config.lockout.clone(),
+            client_certificates: config.tls.as_ref().is_some_and(|tls| tls.client_ca.is_some()),
+            protect_enumeration: config.enumeration.protect,
+        })
+    }
+
+    /// Fails if users of a configured ciphersuite were registered under other group parameters or
+    /// generators than the configured ones, which the ciphersuite id does not tell apart and under
+    /// which their credentials could never be verified. The parameters are pinned on first use.
+    async fn pin_parameters(&self) -> eyre::Result<()> {
+        for (id, _) in self.registry.ids() {
+            if !self.users.pin_parameters(id, &self.registry.get(id)?.fingerprint()).await? {
+                return Err(eyre::eyre!("the parameters of ciphersuite {} differ from those its stored users were registered under", id));
+            }
+        }
+        Ok(())
+    }
+
+    /// How long `user` has to wait before its next attempt after failing too often, if at all.
+    async fn lockout_wait(&self, user: &str) -> eyre::Result<Option<Duration>> {
+        let failures = self.users.get_failures(user).await?;
+        Ok(failures.and_then(|failures| lockout::wait(&self.lockout, &failures, SystemTime::now())))
+    }
+
+    /// Forgets the failures of `users` after a successful verification, or counts one more, once
+    /// for every user however often it appears.
+    async fn record_verification(&self, users: &[&str], verified: bool) -> eyre::Result<()> {
+        for (i, user) in users.iter().enumerate() {
+            if users[..i].contains(user) {
+                continue;
+            }
+            if verified {
+                self.users.reset_failures(user).await?;
+            } else {
+                let failures = self.users.record_failure(user, SystemTime::now()).await?;
+                if failures.count == self.lockout.lock_after {
+                    log::warn!("locked user {} after {} failed verifications", user, failures.count);
+                }
+                self.audit(user, Event::FailedVerification, None).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn audit(&self, user: &str, event: Event, session_id: Option<Uuid>) -> eyre::Result<()> {
+        self.users.record_event(user, AuditEntry { time: SystemTime::now(), event, session_id }).await
+    }
+
+    /// Whether a registration is answered as successful: if it inserted the user, or if the name
+    /// is taken but answering so would tell that the user exists. The attempt then goes into the
+    /// audit log of the user, whose owner sees it in an export of the account, unless there is
+    /// one from within `REGISTRATION_ATTEMPT_WINDOW` already.
+    async fn registered(&self, inserted: bool, user: &str) -> eyre::Result<bool> {
+        if !inserted && self.protect_enumeration {
+            log::warn!("not registering {}, the name is taken", user);
+            let now = SystemTime::now();
+            let events = self.users.get_events(user).await?;
+            let recent = events.iter().rev()
+                .take_while(|entry| now.duration_since(entry.time).unwrap_or_default() < REGISTRATION_ATTEMPT_WINDOW)
+                .any(|entry| entry.event == Event::RegistrationAttempt);
+            if !recent {
+                self.audit(user, Event::RegistrationAttempt, None).await?;
+            }
+            return Ok(true);
+        }
+        Ok(inserted)
+    }
+
+    /// Whether the caller presented a certificate signed by the configured client CA, which the
+    /// TLS handshake has checked already. Admin RPCs always require one.
+    fn admin_authorized<T>(&self, request: &Request<T>) -> bool {
+        self.client_certificates && request.peer_certs().is_some_and(|certs| !certs.is_empty())
+    }
+
+    /// Whether the caller may use internal RPCs, which require a client certificate if the
+    /// service has a client CA.
+    fn client_authorized<T>(&self, request: &Request<T>) -> bool {
+        !self.client_certificates || self.admin_authorized(request)
+    }
+
+    /// Stores the answered challenge as a new session, returning its token.
+    async fn start_session(&self, session: Session, ciphersuite: &str) -> eyre::Result<String> {
+        let now = SystemTime::now();
+        let claims = Claims {
+            iss: self.realm.clone(),
+            sub: session.user.clone(),
+            ciphersuite: ciphersuite.to_string(),
+            iat: unix(now),
+            exp: unix(now + self.session_ttl),
+            jti: Uuid::new_v4(),
+        };
+        self.sessions.insert_session(claims.jti, Session {
+            id: Some(claims.jti),
+            expires: now + self.session_ttl,
+            last_seen: now,
+            started: now,
+            ..session
+        }).await?;
+        self.audit(&claims.sub, Event::Login, Some(claims.jti)).await?;
+        self.keys.issue(&claims)
+    }
+
+    /// Claims and session of `token` unless it is invalid or its session does not exist, has
+    /// expired, has been idle for too long or is older than the maximum age, removing the session
+    /// in the latter cases.
+    async fn live_session(&self, token: &str, now: SystemTime) -> eyre::Result<Option<(Claims, Session)>> {
+        let Some(claims) = self.keys.verify(token) else {
+            return Ok(None);
+        };
+        let Some(session) = self.sessions.get_session(&claims.jti).await? else {
+            return Ok(None);
+        };
+        if session.expires <= now || session.last_seen + self.session_idle_timeout <= now || session.started + self.session_max_age <= now {
+            self.sessions.remove_session(&claims.jti).await?;
+            return Ok(None);
+        }
+        Ok(Some((claims, session)))
+    }
+
+    /// Checks that the caller is `user` again, by a fresh answer `s` to its challenge `auth_id` or
+    /// with its live session `session_id`, counting wrong answers towards the lockout.
+    async fn reauthenticate(&self, user: &User, auth_id: &str, s: &[u8], session_id: &str) -> Result<Reauthenticated, Status> {
+        if !auth_id.is_empty() {
+            let auth_id = Uuid::parse_str(auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+            let session = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)?
+                .filter(|session| session.user == user.name)
+                .ok_or_else(|| Status::not_found("auth not found"))?;
+            let verified = self.answers(user, &session, s).ok_or_else(|| Status::unauthenticated("invalid protocol"))?;
+            self.record_verification(&[&user.name], verified).await.map_err(internal_error)?;
+            if !verified {
+                return Err(Status::unauthenticated("invalid password"));
+            }
+            Ok(Reauthenticated::Challenge(Box::new(session)))
+        } else if !session_id.is_empty() {
+            let (claims, _) = self.live_session(session_id, SystemTime::now()).await.map_err(internal_error)?
+                .filter(|(_, session)| session.user == user.name)
+                .ok_or_else(|| Status::unauthenticated("invalid session"))?;
+            Ok(Reauthenticated::Session(claims))
+        } else {
+            Err(Status::invalid_argument("an answered challenge or a session is required"))
+        }
+    }
+
+    /// Credentials in the encoding of their ciphersuite.
+    fn encoded(&self, credentials: &Credentials) -> Vec<Vec<u8>> {
+        fn encode<G: Group>(group: &G, elements: [&G::Element; 2]) -> Vec<Vec<u8>> {
+            elements.iter().map(|element| {
+                let mut out = Vec::new();
+                group.encode_element(element, &mut out);
+                out
+            }).collect()
+        }
+        match credentials {
+            Credentials::Exp((a, b)) => encode(self.protocol.as_multi().group(), [a, b]),
+            Credentials::K256((a, b)) => encode(&K256Group, [a, b]),
+            Credentials::Encoded(elements) => elements.clone(),
+        }
+    }
+
+    /// Credentials of `user` as elements of the Exp group, whether it registered through the Exp or
+    /// the suite RPCs, none for users of another ciphersuite.
+    fn exp_credentials(&self, user: &User) -> Option<Vec<BigUint>> {
+        match &user.y {
+            Credentials::Exp((y1, y2)) => Some(vec![y1.clone(), y2.clone()]),
+            Credentials::Encoded(ys) if user.ciphersuite == ChaumPedersen::CIPHERSUITE => {
+                let protocol = self.protocol.as_multi();
+                ys.iter().map(|y| protocol.group().decode_element(y)).collect()
+            }
+            _ => None,
+        }
+    }
+
+    /// Credentials of `user` as points, whether it registered through the K256 or the suite RPCs,
+    /// none for users of another ciphersuite.
+    fn k256_credentials(&self, user: &User) -> Option<Vec<AffinePoint>> {
+        match &user.y {
+            Credentials::K256((y1, y2)) => Some(vec![*y1, *y2]),
+            Credentials::Encoded(ys) if user.ciphersuite == ChaumPedersenK256::CIPHERSUITE => {
+                ys.iter().map(|y| K256Group.decode_element(y)).collect()
+            }
+            _ => None,
+        }
+    }
+
+    /// Encoded `ys`, checked by the ciphersuite already, as credentials of the same kind as `like`.
+    fn decoded(&self, like: &Credentials, ys: &[Vec<u8>]) -> Option<Credentials> {
+        match (like, ys) {
+            (Credentials::Exp(_), [a, b]) => Some(Credentials::Exp((BigUint::from_bytes_be(a), BigUint::from_bytes_be(b)))),
+            (Credentials::K256(_), [a, b]) => Some(Credentials::K256((K256Group.decode_element(a)?, K256Group.decode_element(b)?))),
+            (Credentials::Encoded(_), _) => Some(Credentials::Encoded(ys.to_vec())),
+            _ => None,
+        }
+    }
+
+    /// Whether `s` answers the challenge of `session` with the credentials of `user`, none if the
+    /// challenge is not one of the user's protocol.
+    fn answers(&self, user: &User, session: &Session, s: &[u8]) -> Option<bool> {
+        match (&user.y, &session.r, &session.composition) {
+            (Credentials::Exp((y1, y2)), Credentials::Exp((r1, r2)), None) => {
+                Some(self.protocol.verify(y1, y2, r1, r2, &BigUint::from_bytes_be(&session.c), &BigUint::from_bytes_be(s)))
+            }
+            (Credentials::K256((y1, y2)), Credentials::K256((r1, r2)), None) => {
+                let c = k256_scalar(&session.c)?;
+                Some(k256_scalar(s).is_some_and(|s| self.protocol_k256.verify(y1, y2, r1, r2, &c, &s)))
+            }
+            (Credentials::Encoded(ys), Credentials::Encoded(rs), None) => {
+                self.registry.get(&user.ciphersuite).ok()?.verify(ys, rs, &session.c, s).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Everything stored about `user`: its public credentials in hex, when it registered, its
+    /// sessions, failures and audit log, times being seconds since the Unix epoch.
+    async fn export(&self, user: &User) -> eyre::Result<serde_json::Value> {
+        let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        let sessions = self.sessions.user_sessions(&user.name).await?;
+        let failures = self.users.get_failures(&user.name).await?;
+        let events = self.users.get_events(&user.name).await?;
+        Ok(serde_json::json!({
+            "user": user.name,
+            "realm": user.realm,
+            "ciphersuite": user.ciphersuite,
+            "factors": user.factors.iter().map(|factor| factor.as_str_name()).collect::<Vec<_>>(),
+            "credentials": self.encoded(&user.y).iter().map(|y| hex(y)).collect::<Vec<_>>(),
+            "registered_at": user.registered.map(unix),
+            "sessions": sessions.iter().map(|session| serde_json::json!({
+                "session_id": session.id.map(|id| id.to_string()),
+                "last_seen_at": unix(session.last_seen),
+                "expires_at": unix(session.expires),
+            })).collect::<Vec<_>>(),
+            "failures": failures.map(|failures| serde_json::json!({
+                "count": failures.count,
+                "last_at": unix(failures.last),
+            })),
+            "audit": events.iter().map(|entry| serde_json::json!({
+                "time": unix(entry.time),
+                "event": entry.event.name(),
+                "session_id": entry.session_id.map(|id| id.to_string()),
+            })).collect::<Vec<_>>(),
+        }))
+    }
+}
+
+
+/// Removes expired challenges and sessions every `period`, as well as sessions idle for
+/// `idle_timeout`.
+async fn reap(sessions: Arc<dyn SessionStore>, period: Duration, idle_timeout: Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let now = SystemTime::now();
+        match sessions.reap(now, now - idle_timeout).await {
+            Ok(0) => {}
+            Ok(reaped) => log::info!("reaped {} expired challenges and sessions", reaped),
+            Err(error) => log::error!("reaping failed: {:?}", error),
         }
     }
 }
 
 #[tonic::async_trait]
 impl pb2::auth_server::Auth for API {
+    async fn get_realm(&self, _request: Request<pb2::RealmRequest>) -> Result<Response<pb2::RealmResponse>, Status> {
+        Ok(Response::new(pb2::RealmResponse {
+            realm: self.realm.clone(),
+        }))
+    }
+
     async fn register(&self, request: Request<pb2::RegisterRequest>) -> Result<Response<pb2::RegisterResponse>, Status> {
         let request = request.get_ref();
         let y1 = BigUint::from_bytes_be(&request.y1);
         let y2 = BigUint::from_bytes_be(&request.y2);
-        let mut users = self.users.lock().await;
-        if users.contains_key(&request.user) {
-            Err(Status::already_exists("user already is registered"))
-        } else {
-            log::info!("register {} with (y1={}, y2={})", request.user, y1, y2);
-            users.insert(request.user.clone(), User {
-                name: request.user.clone(),
-                y: Credentials::Exp((y1, y2)),
-            });
+        let factors = enrolled_factors(&request.factors).ok_or_else(|| Status::invalid_argument("unknown factor"))?;
+        if self.registry.is_deprecated(ChaumPedersen::CIPHERSUITE) {
+            return Err(Status::failed_precondition("ciphersuite is deprecated"));
+        }
+        let proof = (BigUint::from_bytes_be(&request.r1), BigUint::from_bytes_be(&request.r2), BigUint::from_bytes_be(&request.s));
+        if !self.protocol.verify_registration(&self.realm, &request.user, &y1, &y2, &proof) {
+            return Err(Status::invalid_argument("invalid proof of possession"));
+        }
+        log::info!("register {} in realm {} with (y1={}, y2={}) and factors {:?}", request.user, self.realm, y1, y2, factors);
+        let inserted = self.users.insert_user(User {
+            name: request.user.clone(),
+            realm: self.realm.clone(),
+            ciphersuite: ChaumPedersen::CIPHERSUITE.to_string(),
+            factors,
+            y: Credentials::Exp((y1, y2)),
+            registered: Some(SystemTime::now()),
+        }).await.map_err(internal_error)?;
+        if self.registered(inserted, &request.user).await.map_err(internal_error)? {
             Ok(Response::new(pb2::RegisterResponse {}))
+        } else {
+            Err(Status::already_exists("user already is registered"))
         }
     }
 
@@ -89,19 +432,28 @@ impl pb2::auth_server::Auth for API {
         let request = request.get_ref();
         let r1 = BigUint::from_bytes_be(&request.r1);
         let r2 = BigUint::from_bytes_be(&request.r2);
-        if let Some(user) = self.users.lock().await.get_mut(&request.user) {
+        if let Some(user) = self.users.get_user(&request.user).await.map_err(internal_error)? {
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
             log::info!("create_authentication_challenge for user {} with (r1={}, r2={})", user.name, r1, r2);
             let auth_id = Uuid::new_v4();
-            let c = self.protocol.challenge();
-            self.sessions.lock().await.insert(auth_id, Session {
+            let c = self.protocol.challenge().to_bytes_be();
+            self.sessions.insert_session(auth_id, Session {
                 id: None,
                 user: user.name.clone(),
                 r: Credentials::Exp((r1, r2)),
                 c: c.clone(),
-            });
+                composition: None,
+                expires: SystemTime::now() + self.challenge_ttl,
+                last_seen: SystemTime::now(),
+                started: SystemTime::now(),
+            }).await.map_err(internal_error)?;
             Ok(Response::new(pb2::AuthenticationChallengeResponse {
                 auth_id: auth_id.to_string(),
-                c: c.to_bytes_be(),
+                c,
+                realm: user.realm.clone(),
+                factors: user.factors.iter().map(|factor| *factor as i32).collect(),
             }))
         } else {
             Err(Status::not_found("user not found"))
@@ -112,16 +464,28 @@ impl pb2::auth_server::Auth for API {
         let request = request.get_ref();
         let s = BigUint::from_bytes_be(&request.s);
         log::info!("verify_authentication {} with (s={})", request.auth_id, s);
-        if let Some(session) = self.sessions.lock().await.get_mut(&Uuid::parse_str(&request.auth_id).expect("invalid auth id")) {
-            let user = &self.users.lock().await[&session.user];
-            if let (Credentials::Exp((y1, y2)), Credentials::Exp((r1, r2))) = (&user.y, &session.r) {
-                if self.protocol.verify(y1, y2, r1, r2, &session.c, &s) {
-                    let session_id = Uuid::new_v4();
-                    session.id = Some(session_id);
+        let auth_id = Uuid::parse_str(&request.auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+        if let Some(session) = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)? {
+            let user = self.users.get_user(&session.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
+            if let (Credentials::Exp((y1, y2)), Credentials::Exp((r1, r2)), None) = (&user.y, &session.r, &session.composition) {
+                let c = BigUint::from_bytes_be(&session.c);
+                if self.protocol.verify(y1, y2, r1, r2, &c, &s) {
+                    let protocol = self.protocol.as_multi();
+                    let mut transcript = Transcript::new(b"chaum-pedersen-auth");
+                    protocol.bind(&mut transcript, &[y1.clone(), y2.clone()], &[r1.clone(), r2.clone()]);
+                    transcript.append_message(b"auth_id", request.auth_id.as_bytes());
+                    transcript.append_scalar(b"c", protocol.group(), &c);
+                    log::info!("verified {} for user {} with transcript {}", request.auth_id, session.user, fingerprint(&transcript));
+                    self.record_verification(&[&user.name], true).await.map_err(internal_error)?;
+                    let session_id = self.start_session(session, &user.ciphersuite).await.map_err(internal_error)?;
                     Ok(Response::new(pb2::AuthenticationAnswerResponse {
-                        session_id: session_id.to_string(),
+                        session_id,
                     }))
                 } else {
+                    self.record_verification(&[&user.name], false).await.map_err(internal_error)?;
                     Err(Status::unauthenticated("invalid password"))
                 }
             } else {
@@ -133,23 +497,120 @@ impl pb2::auth_server::Auth for API {
     }
 
 
+    async fn create_composed_authentication_challenge(&self, request: Request<pb2::ComposedAuthenticationChallengeRequest>) -> Result<Response<pb2::ComposedAuthenticationChallengeResponse>, Status> {
+        let request = request.get_ref();
+        let kind = pb2::Composition::try_from(request.composition).map_err(|_| Status::invalid_argument("unknown composition"))?;
+        if request.users.is_empty() || request.users.len() != request.commitments.len() {
+            return Err(Status::invalid_argument("users and commitments must be non empty and of equal length"));
+        }
+        if kind != pb2::Composition::And {
+            return Err(Status::invalid_argument("only AND proofs are accepted, every user having a single credential"));
+        }
+        let mut branches = Vec::with_capacity(request.users.len());
+        let mut realms = Vec::with_capacity(request.users.len());
+        for (name, commitment) in request.users.iter().zip(&request.commitments) {
+            let user = self.users.get_user(name).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
+            let r1 = BigUint::from_bytes_be(&commitment.r1);
+            let r2 = BigUint::from_bytes_be(&commitment.r2);
+            log::info!("create_composed_authentication_challenge for user {} with (r1={}, r2={})", user.name, r1, r2);
+            realms.push(user.realm);
+            branches.push((user.name, Credentials::Exp((r1, r2))));
+        }
+        let auth_id = Uuid::new_v4();
+        let c = self.protocol.as_multi().challenge().to_bytes_be();
+        let (user, r) = branches.remove(0);
+        self.sessions.insert_session(auth_id, Session {
+            id: None,
+            user,
+            r,
+            c: c.clone(),
+            composition: Some(Composition { kind, branches }),
+            expires: SystemTime::now() + self.challenge_ttl,
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }).await.map_err(internal_error)?;
+        Ok(Response::new(pb2::ComposedAuthenticationChallengeResponse {
+            auth_id: auth_id.to_string(),
+            c,
+            realms,
+        }))
+    }
+
+    async fn verify_composed_authentication(&self, request: Request<pb2::ComposedAuthenticationAnswerRequest>) -> Result<Response<pb2::ComposedAuthenticationAnswerResponse>, Status> {
+        let request = request.get_ref();
+        log::info!("verify_composed_authentication {}", request.auth_id);
+        let auth_id = Uuid::parse_str(&request.auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+        let session = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("auth not found"))?;
+        let composition = session.composition.as_ref().ok_or_else(|| Status::unauthenticated("invalid protocol"))?;
+        let protocol = self.protocol.as_multi();
+        let mut statements = Vec::new();
+        let mut rs = Vec::new();
+        let branches = std::iter::once((&session.user, &session.r)).chain(composition.branches.iter().map(|(user, r)| (user, r)));
+        let names: Vec<String> = branches.clone().map(|(user, _)| user.clone()).collect();
+        if composition.kind != pb2::Composition::And {
+            return Err(Status::unauthenticated("invalid protocol"));
+        }
+        for (user, r) in branches {
+            let user = self.users.get_user(user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
+            if let (Some(ys), Credentials::Exp((r1, r2))) = (self.exp_credentials(&user), r) {
+                statements.push(Statement::new(protocol.clone(), ys));
+                rs.push(vec![r1.clone(), r2.clone()]);
+            } else {
+                return Err(Status::unauthenticated("invalid protocol"));
+            }
+        }
+        let ss: Vec<BigUint> = request.s.iter().map(|s| BigUint::from_bytes_be(s)).collect();
+        let c = BigUint::from_bytes_be(&session.c);
+        let valid = And::new(protocol.group().clone(), statements).verify(&rs, &c, &ss);
+        self.record_verification(&names.iter().map(String::as_str).collect::<Vec<_>>(), valid).await.map_err(internal_error)?;
+        if valid {
+            let session_id = self.start_session(session, ChaumPedersen::CIPHERSUITE).await.map_err(internal_error)?;
+            Ok(Response::new(pb2::ComposedAuthenticationAnswerResponse {
+                session_id,
+            }))
+        } else {
+            Err(Status::unauthenticated("invalid password"))
+        }
+    }
+
+
     async fn k256_register(&self, request: Request<pb2::K256RegisterRequest>) -> Result<Response<pb2::K256RegisterResponse>, Status> {
         let request = request.get_ref();
+        let factors = enrolled_factors(&request.factors).ok_or_else(|| Status::invalid_argument("unknown factor"))?;
+        if self.registry.is_deprecated(ChaumPedersenK256::CIPHERSUITE) {
+            return Err(Status::failed_precondition("ciphersuite is deprecated"));
+        }
         if let (Some(y1), Some(y2)) = (&request.y1, &request.y2) {
             if let (Some(y1), Some(y2)) = (
                 AffinePoint::decompress(y1.x.as_slice().into(), Choice::from(y1.is_y_odd as u8)).into(),
                 AffinePoint::decompress(y2.x.as_slice().into(), Choice::from(y2.is_y_odd as u8)).into(),
             ) {
-                let mut users = self.users.lock().await;
-                if users.contains_key(&request.user) {
-                    Err(Status::already_exists("user already is registered"))
-                } else {
-                    log::info!("register {} with (y1={:?}, y2={:?})", request.user, y1, y2);
-                    users.insert(request.user.clone(), User {
-                        name: request.user.clone(),
-                        y: Credentials::K256((y1, y2)),
-                    });
+                let proof = request.r1.as_ref().and_then(decompress)
+                    .zip(request.r2.as_ref().and_then(decompress))
+                    .zip(k256_scalar(&request.s))
+                    .map(|((r1, r2), s)| (r1, r2, s));
+                if !proof.is_some_and(|proof| self.protocol_k256.verify_registration(&self.realm, &request.user, &y1, &y2, &proof)) {
+                    return Err(Status::invalid_argument("invalid proof of possession"));
+                }
+                log::info!("register {} in realm {} with (y1={:?}, y2={:?}) and factors {:?}", request.user, self.realm, y1, y2, factors);
+                let inserted = self.users.insert_user(User {
+                    name: request.user.clone(),
+                    realm: self.realm.clone(),
+                    ciphersuite: ChaumPedersenK256::CIPHERSUITE.to_string(),
+                    factors,
+                    y: Credentials::K256((y1, y2)),
+                    registered: Some(SystemTime::now()),
+                }).await.map_err(internal_error)?;
+                if self.registered(inserted, &request.user).await.map_err(internal_error)? {
                     Ok(Response::new(pb2::K256RegisterResponse {}))
+                } else {
+                    Err(Status::already_exists("user already is registered"))
                 }
             } else {
                 Err(Status::invalid_argument("y1 point or y2 point is invalid"))
@@ -166,19 +627,28 @@ impl pb2::auth_server::Auth for API {
                 AffinePoint::decompress(r1.x.as_slice().into(), Choice::from(r1.is_y_odd as u8)).into(),
                 AffinePoint::decompress(r2.x.as_slice().into(), Choice::from(r2.is_y_odd as u8)).into(),
             ) {
-                if let Some(user) = self.users.lock().await.get_mut(&request.user) {
+                if let Some(user) = self.users.get_user(&request.user).await.map_err(internal_error)? {
+                    if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                        return Err(throttled("too many failed attempts, retry later", wait));
+                    }
                     log::info!("create_authentication_challenge for user {} with (r1={:?}, r2={:?})", user.name, r1, r2);
                     let auth_id = Uuid::new_v4();
                     let c = self.protocol_k256.challenge();
-                    self.sessions.lock().await.insert(auth_id, Session {
+                    self.sessions.insert_session(auth_id, Session {
                         id: None,
                         user: user.name.clone(),
                         r: Credentials::K256((r1, r2)),
-                        c: BigUint::from_bytes_be(c.to_repr().as_slice()),
-                    });
+                        c: c.to_repr().to_vec(),
+                        composition: None,
+                        expires: SystemTime::now() + self.challenge_ttl,
+                        last_seen: SystemTime::now(),
+                        started: SystemTime::now(),
+                    }).await.map_err(internal_error)?;
                     Ok(Response::new(pb2::K256AuthenticationChallengeResponse {
                         auth_id: auth_id.to_string(),
                         c: c.to_repr().to_vec(),
+                        realm: user.realm.clone(),
+                        factors: user.factors.iter().map(|factor| *factor as i32).collect(),
                     }))
                 } else {
                     Err(Status::not_found("user not found"))
@@ -193,26 +663,34 @@ impl pb2::auth_server::Auth for API {
 
     async fn k256_verify_authentication(&self, request: Request<pb2::K256AuthenticationAnswerRequest>) -> Result<Response<pb2::K256AuthenticationAnswerResponse>, Status> {
         let request = request.get_ref();
-        if let Some(s) = Scalar::from_repr(GenericArray::clone_from_slice(request.s.as_slice())).into() {
-            if let Some(session) = self.sessions.lock().await.get_mut(&Uuid::parse_str(&request.auth_id).expect("invalid auth id")) {
-                let user = &self.users.lock().await[&session.user];
-                let mut c: [u8; 32] = [0u8; 32];
-                for (i, v) in session.c.to_bytes_be().iter().rev().enumerate() {
-                    c[31 - i] = *v;
+        if let Some(s) = k256_scalar(&request.s) {
+            let auth_id = Uuid::parse_str(&request.auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+            if let Some(session) = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)? {
+                let user = self.users.get_user(&session.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+                if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                    return Err(throttled("too many failed attempts, retry later", wait));
                 }
                 if let (
                     Credentials::K256((y1, y2)),
                     Credentials::K256((r1, r2)),
-                    Some(c)
-                ) = (&user.y, &session.r, Scalar::from_repr(c.into()).into()) {
+                    Some(c),
+                    None,
+                ) = (&user.y, &session.r, k256_scalar(&session.c), &session.composition) {
                     log::info!("verify_authentication {} with (s={:?})", request.auth_id, s);
                     if self.protocol_k256.verify(y1, y2, r1, r2, &c, &s) {
-                        let session_id = Uuid::new_v4();
-                        session.id = Some(session_id);
+                        let protocol = self.protocol_k256.as_multi();
+                        let mut transcript = Transcript::new(b"chaum-pedersen-auth");
+                        protocol.bind(&mut transcript, &[*y1, *y2], &[*r1, *r2]);
+                        transcript.append_message(b"auth_id", request.auth_id.as_bytes());
+                        transcript.append_scalar(b"c", protocol.group(), &c);
+                        log::info!("verified {} for user {} with transcript {}", request.auth_id, session.user, fingerprint(&transcript));
+                        self.record_verification(&[&user.name], true).await.map_err(internal_error)?;
+                        let session_id = self.start_session(session, &user.ciphersuite).await.map_err(internal_error)?;
                         Ok(Response::new(pb2::K256AuthenticationAnswerResponse {
-                            session_id: session_id.to_string(),
+                            session_id,
                         }))
                     } else {
+                        self.record_verification(&[&user.name], false).await.map_err(internal_error)?;
                         Err(Status::unauthenticated("invalid password"))
                     }
                 } else {
@@ -225,23 +703,459 @@ impl pb2::auth_server::Auth for API {
             Err(Status::unauthenticated("invalid password"))
         }
     }
+
+    async fn k256_create_composed_authentication_challenge(&self, request: Request<pb2::K256ComposedAuthenticationChallengeRequest>) -> Result<Response<pb2::K256ComposedAuthenticationChallengeResponse>, Status> {
+        let request = request.get_ref();
+        let kind = pb2::Composition::try_from(request.composition).map_err(|_| Status::invalid_argument("unknown composition"))?;
+        if request.users.is_empty() || request.users.len() != request.commitments.len() {
+            return Err(Status::invalid_argument("users and commitments must be non empty and of equal length"));
+        }
+        if kind != pb2::Composition::And {
+            return Err(Status::invalid_argument("only AND proofs are accepted, every user having a single credential"));
+        }
+        let mut branches = Vec::with_capacity(request.users.len());
+        let mut realms = Vec::with_capacity(request.users.len());
+        for (name, commitment) in request.users.iter().zip(&request.commitments) {
+            let user = self.users.get_user(name).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
+            let (Some(r1), Some(r2)) = (&commitment.r1, &commitment.r2) else {
+                return Err(Status::invalid_argument("r1 or r2 is missing"));
+            };
+            let (Some(r1), Some(r2)) = (decompress(r1), decompress(r2)) else {
+                return Err(Status::invalid_argument("r1 point or r2 point is invalid"));
+            };
+            log::info!("create_composed_authentication_challenge for user {} with (r1={:?}, r2={:?})", user.name, r1, r2);
+            realms.push(user.realm);
+            branches.push((user.name, Credentials::K256((r1, r2))));
+        }
+        let auth_id = Uuid::new_v4();
+        let c = self.protocol_k256.challenge();
+        let (user, r) = branches.remove(0);
+        self.sessions.insert_session(auth_id, Session {
+            id: None,
+            user,
+            r,
+            c: c.to_repr().to_vec(),
+            composition: Some(Composition { kind, branches }),
+            expires: SystemTime::now() + self.challenge_ttl,
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }).await.map_err(internal_error)?;
+        Ok(Response::new(pb2::K256ComposedAuthenticationChallengeResponse {
+            auth_id: auth_id.to_string(),
+            c: c.to_repr().to_vec(),
+            realms,
+        }))
+    }
+
+    async fn k256_verify_composed_authentication(&self, request: Request<pb2::K256ComposedAuthenticationAnswerRequest>) -> Result<Response<pb2::K256ComposedAuthenticationAnswerResponse>, Status> {
+        let request = request.get_ref();
+        log::info!("verify_composed_authentication {}", request.auth_id);
+        let auth_id = Uuid::parse_str(&request.auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+        let ss = request.s.iter().map(|s| k256_scalar(s)).collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Status::unauthenticated("invalid password"))?;
+        let session = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("auth not found"))?;
+        let composition = session.composition.as_ref().ok_or_else(|| Status::unauthenticated("invalid protocol"))?;
+        let c = k256_scalar(&session.c).ok_or_else(|| Status::unauthenticated("invalid protocol"))?;
+        let protocol = self.protocol_k256.as_multi();
+        let mut statements = Vec::new();
+        let mut rs = Vec::new();
+        let branches = std::iter::once((&session.user, &session.r)).chain(composition.branches.iter().map(|(user, r)| (user, r)));
+        let names: Vec<String> = branches.clone().map(|(user, _)| user.clone()).collect();
+        if composition.kind != pb2::Composition::And {
+            return Err(Status::unauthenticated("invalid protocol"));
+        }
+        for (user, r) in branches {
+            let user = self.users.get_user(user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+            if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+                return Err(throttled("too many failed attempts, retry later", wait));
+            }
+            if let (Some(ys), Credentials::K256((r1, r2))) = (self.k256_credentials(&user), r) {
+                statements.push(Statement::new(protocol.clone(), ys));
+                rs.push(vec![*r1, *r2]);
+            } else {
+                return Err(Status::unauthenticated("invalid protocol"));
+            }
+        }
+        let valid = And::new(protocol.group().clone(), statements).verify(&rs, &c, &ss);
+        self.record_verification(&names.iter().map(String::as_str).collect::<Vec<_>>(), valid).await.map_err(internal_error)?;
+        if valid {
+            let session_id = self.start_session(session, ChaumPedersenK256::CIPHERSUITE).await.map_err(internal_error)?;
+            Ok(Response::new(pb2::K256ComposedAuthenticationAnswerResponse {
+                session_id,
+            }))
+        } else {
+            Err(Status::unauthenticated("invalid password"))
+        }
+    }
+
+    async fn get_ciphersuites(&self, _request: Request<pb2::CiphersuitesRequest>) -> Result<Response<pb2::CiphersuitesResponse>, Status> {
+        Ok(Response::new(pb2::CiphersuitesResponse {
+            ciphersuites: self.registry.ids()
+                .map(|(id, deprecated)| pb2::Ciphersuite { id: id.to_string(), deprecated })
+                .collect(),
+        }))
+    }
+
+    async fn suite_register(&self, request: Request<pb2::SuiteRegisterRequest>) -> Result<Response<pb2::SuiteRegisterResponse>, Status> {
+        let request = request.get_ref();
+        let suite = self.registry.get(&request.ciphersuite).map_err(|error| Status::not_found(error.to_string()))?;
+        if self.registry.is_deprecated(suite.id()) {
+            return Err(Status::failed_precondition("ciphersuite is deprecated"));
+        }
+        suite.check(&request.y).map_err(|error| Status::invalid_argument(error.to_string()))?;
+        let factors = enrolled_factors(&request.factors).ok_or_else(|| Status::invalid_argument("unknown factor"))?;
+        if !suite.verify_registration(&self.realm, &request.user, &request.y, &request.r, &request.s).unwrap_or(false) {
+            return Err(Status::invalid_argument("invalid proof of possession"));
+        }
+        log::info!("register {} in realm {} with {} and factors {:?}", request.user, self.realm, suite.id(), factors);
+        let inserted = self.users.insert_user(User {
+            name: request.user.clone(),
+            realm: self.realm.clone(),
+            ciphersuite: suite.id().to_string(),
+            factors,
+            y: Credentials::Encoded(request.y.clone()),
+            registered: Some(SystemTime::now()),
+        }).await.map_err(internal_error)?;
+        if self.registered(inserted, &request.user).await.map_err(internal_error)? {
+            Ok(Response::new(pb2::SuiteRegisterResponse {}))
+        } else {
+            Err(Status::already_exists("user already is registered"))
+        }
+    }
+
+    async fn suite_create_authentication_challenge(&self, request: Request<pb2::SuiteAuthenticationChallengeRequest>) -> Result<Response<pb2::SuiteAuthenticationChallengeResponse>, Status> {
+        let request = request.get_ref();
+        let user = self.users.get_user(&request.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+        if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+            return Err(throttled("too many failed attempts, retry later", wait));
+        }
+        if user.ciphersuite != request.ciphersuite {
+            return Err(Status::failed_precondition(format!("user is registered with {}", user.ciphersuite)));
+        }
+        let suite = self.registry.get(&user.ciphersuite).map_err(|error| Status::internal(error.to_string()))?;
+        suite.check(&request.r).map_err(|error| Status::invalid_argument(error.to_string()))?;
+        log::info!("create_authentication_challenge for user {} with {}", user.name, suite.id());
+        let auth_id = Uuid::new_v4();
+        let c = suite.challenge(&mut rand::thread_rng());
+        self.sessions.insert_session(auth_id, Session {
+            id: None,
+            user: user.name.clone(),
+            r: Credentials::Encoded(request.r.clone()),
+            c: c.clone(),
+            composition: None,
+            expires: SystemTime::now() + self.challenge_ttl,
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }).await.map_err(internal_error)?;
+        Ok(Response::new(pb2::SuiteAuthenticationChallengeResponse {
+            auth_id: auth_id.to_string(),
+            c,
+            realm: user.realm.clone(),
+            factors: user.factors.iter().map(|factor| *factor as i32).collect(),
+        }))
+    }
+
+    async fn suite_verify_authentication(&self, request: Request<pb2::SuiteAuthenticationAnswerRequest>) -> Result<Response<pb2::SuiteAuthenticationAnswerResponse>, Status> {
+        let request = request.get_ref();
+        log::info!("verify_authentication {}", request.auth_id);
+        let auth_id = Uuid::parse_str(&request.auth_id).map_err(|_| Status::invalid_argument("invalid auth id"))?;
+        let session = self.sessions.take_challenge(&auth_id).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("auth not found"))?;
+        let user = self.users.get_user(&session.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+        if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+            return Err(throttled("too many failed attempts, retry later", wait));
+        }
+        let (Credentials::Encoded(ys), Credentials::Encoded(rs), None) = (&user.y, &session.r, &session.composition) else {
+            return Err(Status::unauthenticated("invalid protocol"));
+        };
+        let suite = self.registry.get(&user.ciphersuite).map_err(|error| Status::internal(error.to_string()))?;
+        match suite.verify(ys, rs, &session.c, &request.s) {
+            Ok(true) => {
+                self.record_verification(&[&user.name], true).await.map_err(internal_error)?;
+                let session_id = self.start_session(session, &user.ciphersuite).await.map_err(internal_error)?;
+                Ok(Response::new(pb2::SuiteAuthenticationAnswerResponse {
+                    session_id,
+                }))
+            }
+            Ok(false) => {
+                self.record_verification(&[&user.name], false).await.map_err(internal_error)?;
+                Err(Status::unauthenticated("invalid password"))
+            }
+            Err(error) => Err(Status::invalid_argument(error.to_string())),
+        }
+    }
+
+    async fn validate_session(&self, request: Request<pb2::ValidateSessionRequest>) -> Result<Response<pb2::ValidateSessionResponse>, Status> {
+        if !self.client_authorized(&request) {
+            return Err(Status::permission_denied("client certificate required"));
+        }
+        let request = request.get_ref();
+        let now = SystemTime::now();
+        let (claims, session) = self.live_session(&request.session_id, now).await.map_err(internal_error)?
+            .ok_or_else(|| Status::unauthenticated("invalid session"))?;
+        self.sessions.touch_session(&claims.jti, now, session.expires).await.map_err(internal_error)?;
+        Ok(Response::new(pb2::ValidateSessionResponse {
+            user: session.user,
+            expires_at: unix(session.expires),
+        }))
+    }
+
+    async fn refresh_session(&self, request: Request<pb2::RefreshSessionRequest>) -> Result<Response<pb2::RefreshSessionResponse>, Status> {
+        let request = request.get_ref();
+        let now = SystemTime::now();
+        let (claims, session) = self.live_session(&request.session_id, now).await.map_err(internal_error)?
+            .ok_or_else(|| Status::unauthenticated("invalid session"))?;
+        let expires = (now + self.session_ttl).min(session.started + self.session_max_age);
+        self.sessions.touch_session(&claims.jti, now, expires).await.map_err(internal_error)?;
+        log::info!("refreshed session of user {}", session.user);
+        let session_id = self.keys.issue(&Claims { iat: unix(now), exp: unix(expires), ..claims }).map_err(internal_error)?;
+        Ok(Response::new(pb2::RefreshSessionResponse {
+            expires_at: unix(expires),
+            session_id,
+        }))
+    }
+
+    async fn logout(&self, request: Request<pb2::LogoutRequest>) -> Result<Response<pb2::LogoutResponse>, Status> {
+        let request = request.get_ref();
+        let claims = self.keys.verify(&request.session_id).ok_or_else(|| Status::unauthenticated("invalid session"))?;
+        if self.sessions.remove_session(&claims.jti).await.map_err(internal_error)? {
+            self.audit(&claims.sub, Event::Logout, Some(claims.jti)).await.map_err(internal_error)?;
+            Ok(Response::new(pb2::LogoutResponse {}))
+        } else {
+            Err(Status::unauthenticated("invalid session"))
+        }
+    }
+
+    async fn get_signing_keys(&self, _request: Request<pb2::SigningKeysRequest>) -> Result<Response<pb2::SigningKeysResponse>, Status> {
+        Ok(Response::new(pb2::SigningKeysResponse {
+            keys: self.keys.public_keys()
+                .map(|(kid, public_key)| pb2::SigningKey {
+                    kid: kid.to_string(),
+                    algorithm: "EdDSA".to_string(),
+                    public_key: public_key.to_vec(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn unlock_user(&self, request: Request<pb2::UnlockUserRequest>) -> Result<Response<pb2::UnlockUserResponse>, Status> {
+        if !self.client_certificates {
+            return Err(Status::failed_precondition("unlocking users requires a client_ca in the tls config"));
+        }
+        if !self.admin_authorized(&request) {
+            return Err(Status::permission_denied("client certificate required"));
+        }
+        let request = request.get_ref();
+        if self.users.get_user(&request.user).await.map_err(internal_error)?.is_none() {
+            return Err(Status::not_found("user not found"));
+        }
+        let locked = self.lockout_wait(&request.user).await.map_err(internal_error)?.is_some();
+        self.users.reset_failures(&request.user).await.map_err(internal_error)?;
+        self.audit(&request.user, Event::Unlock, None).await.map_err(internal_error)?;
+        log::info!("unlocked user {}", request.user);
+        Ok(Response::new(pb2::UnlockUserResponse { locked }))
+    }
+
+    async fn create_session_nonce(&self, request: Request<pb2::SessionNonceRequest>) -> Result<Response<pb2::SessionNonceResponse>, Status> {
+        let request = request.get_ref();
+        let (claims, _) = self.live_session(&request.session_id, SystemTime::now()).await.map_err(internal_error)?
+            .ok_or_else(|| Status::unauthenticated("invalid session"))?;
+        let nonce = rand::random::<[u8; 32]>().to_vec();
+        if !self.sessions.set_nonce(&claims.jti, nonce.clone()).await.map_err(internal_error)? {
+            return Err(Status::unauthenticated("invalid session"));
+        }
+        Ok(Response::new(pb2::SessionNonceResponse {
+            nonce,
+        }))
+    }
+
+    async fn change_password(&self, request: Request<pb2::ChangePasswordRequest>) -> Result<Response<pb2::ChangePasswordResponse>, Status> {
+        let request = request.get_ref();
+        let user = self.users.get_user(&request.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+        if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+            return Err(throttled("too many failed attempts, retry later", wait));
+        }
+        let suite = self.registry.get(&user.ciphersuite).map_err(|error| Status::internal(error.to_string()))?;
+
+        // The current password is proven either by answering a challenge, which then starts a
+        // session like a login, or within a live session, which is the one kept. The proofs are
+        // bound to the new credentials and to a nonce used for this change only: the challenge's
+        // auth id, or the latest nonce issued to the session.
+        let reauthenticated = self.reauthenticate(&user, &request.auth_id, &request.s, &request.session_id).await?;
+        let (nonce, kept) = match &reauthenticated {
+            Reauthenticated::Challenge(_) => (request.auth_id.as_bytes().to_vec(), None),
+            Reauthenticated::Session(claims) => {
+                let nonce = self.sessions.take_nonce(&claims.jti).await.map_err(internal_error)?
+                    .ok_or_else(|| Status::failed_precondition("the session has no nonce"))?;
+                (nonce, Some(claims.jti))
+            }
+        };
+        let context = change_context(&user.realm, &user.name, &nonce, &request.y);
+        if kept.is_some() {
+            let proof = request.session_proof.clone().unwrap_or_default();
+            let verified = suite.verify_proof(&self.encoded(&user.y), &proof.r, &proof.s, &context).unwrap_or(false);
+            self.record_verification(&[&user.name], verified).await.map_err(internal_error)?;
+            if !verified {
+                return Err(Status::unauthenticated("invalid password"));
+            }
+        }
+        let proof = request.proof.clone().unwrap_or_default();
+        if !suite.verify_proof(&request.y, &proof.r, &proof.s, &context).unwrap_or(false) {
+            return Err(Status::invalid_argument("invalid proof of possession"));
+        }
+        let y = self.decoded(&user.y, &request.y).ok_or_else(|| Status::invalid_argument("invalid credentials"))?;
+
+        if !self.users.replace_credentials(&user.name, &user.y, y).await.map_err(internal_error)? {
+            return Err(Status::aborted("credentials changed concurrently"));
+        }
+        self.audit(&user.name, Event::PasswordChange, None).await.map_err(internal_error)?;
+        let revoked_sessions = self.sessions.remove_user_sessions(&user.name, kept.as_ref()).await.map_err(internal_error)?;
+        log::info!("changed the password of user {} and revoked {} sessions", user.name, revoked_sessions);
+        let session_id = match reauthenticated {
+            Reauthenticated::Challenge(session) => self.start_session(*session, &user.ciphersuite).await.map_err(internal_error)?,
+            Reauthenticated::Session(_) => request.session_id.clone(),
+        };
+        Ok(Response::new(pb2::ChangePasswordResponse {
+            session_id,
+            revoked_sessions,
+        }))
+    }
+
+    async fn delete_account(&self, request: Request<pb2::DeleteAccountRequest>) -> Result<Response<pb2::DeleteAccountResponse>, Status> {
+        let request = request.get_ref();
+        let user = self.users.get_user(&request.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+        if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+            return Err(throttled("too many failed attempts, retry later", wait));
+        }
+        self.reauthenticate(&user, &request.auth_id, &request.s, &request.session_id).await?;
+        if !self.users.remove_user(&user.name, SystemTime::now()).await.map_err(internal_error)? {
+            return Err(Status::not_found("user not found"));
+        }
+        log::info!("deleted user {}", user.name);
+        Ok(Response::new(pb2::DeleteAccountResponse {}))
+    }
+
+    async fn export_account(&self, request: Request<pb2::ExportAccountRequest>) -> Result<Response<pb2::ExportAccountResponse>, Status> {
+        let request = request.get_ref();
+        let user = self.users.get_user(&request.user).await.map_err(internal_error)?.ok_or_else(|| Status::not_found("user not found"))?;
+        if let Some(wait) = self.lockout_wait(&user.name).await.map_err(internal_error)? {
+            return Err(throttled("too many failed attempts, retry later", wait));
+        }
+        self.reauthenticate(&user, &request.auth_id, &request.s, &request.session_id).await?;
+        let export = self.export(&user).await.map_err(internal_error)?;
+        log::info!("exported user {}", user.name);
+        Ok(Response::new(pb2::ExportAccountResponse {
+            json: export.to_string(),
+        }))
+    }
+}
+
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// TOML config file, see `config.example.toml`. Environment variables override it and flags
+    /// override both.
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Validate the configuration, including the signing keys, and exit.
+    #[arg(long)]
+    check_config: bool,
+    /// Address to listen on.
+    #[arg(long)]
+    listen: Option<SocketAddr>,
+    /// `env_logger` filter, such as `info` or `chaum_pedersen_auth=debug`.
+    #[arg(long)]
+    log_level: Option<String>,
+    /// SQLite database to keep users and sessions in, instead of memory.
+    #[arg(long)]
+    database: Option<PathBuf>,
+    /// Ed25519 key to sign session tokens with, as `<kid>=<pkcs8 pem file>`. The first key signs,
+    /// further keys only verify tokens issued before a rotation. Without keys, a key is generated
+    /// on every start.
+    #[arg(long = "signing-key", value_parser = parse_signing_key)]
+    signing_keys: Vec<SigningKey>,
+}
+
+
+fn parse_signing_key(value: &str) -> Result<SigningKey, String> {
+    let (kid, path) = value.split_once('=').ok_or("expected <kid>=<path>")?;
+    Ok(SigningKey { kid: kid.to_string(), path: path.into() })
+}
+
+
+/// The config file and environment, overridden by the flags in `args`.
+fn configure(args: Args) -> Result<Config> {
+    let mut config = Config::load(args.config.as_deref(), |name| std::env::var(name).ok())?;
+    if let Some(listen) = args.listen {
+        config.listen = listen;
+    }
+    if let Some(log_level) = args.log_level {
+        config.log_level = log_level;
+    }
+    if let Some(path) = args.database {
+        config.storage = Storage::Sqlite { path };
+    }
+    if !args.signing_keys.is_empty() {
+        config.signing_keys = args.signing_keys;
+    }
+    Ok(config)
 }
 
 
-const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
-const DEFAULT_EXPOSED_HEADERS: [&str; 3] =
-    ["grpc-status", "grpc-message", "grpc-status-details-bin"];
-const DEFAULT_ALLOW_HEADERS: [&str; 4] =
-    ["x-grpc-web", "content-type", "x-user-agent", "grpc-timeout"];
+fn load_keys(config: &Config) -> Result<Keys> {
+    if config.signing_keys.is_empty() {
+        log::warn!("no signing key given, session tokens will not be valid after a restart");
+        Keys::generate()
+    } else {
+        Keys::load(&config.signing_keys.iter().map(|key| (key.kid.clone(), &key.path)).collect::<Vec<_>>())
+    }
+}
 
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
     dotenv::dotenv().ok();
-    env_logger::init();
+    let check = args.check_config;
+    let config = configure(args)?;
+    config.check()?;
+    let tls = config.tls.as_ref().map(Tls::load).transpose()?.map(Arc::new);
+    if check {
+        load_keys(&config)?;
+        println!("configuration is valid");
+        return Ok(());
+    }
+    env_logger::Builder::new().parse_filters(&config.log_level).init();
 
-    let addr = "[::0]:50051".parse()?;
-    let api = API::new();
+    if let Some(address) = config.metrics_listen {
+        metrics_exporter_prometheus::PrometheusBuilder::new().with_http_listener(address).install()?;
+        log::info!("serving metrics on {}", address);
+    }
+
+    let (mut users, sessions): (Arc<dyn UserStore>, Arc<dyn SessionStore>) = match &config.storage {
+        Storage::Sqlite { path } => {
+            log::info!("storing users and sessions in {}", path.display());
+            let store = Arc::new(SqliteStore::open(path).await?);
+            (store.clone(), store)
+        }
+        Storage::Memory => {
+            let store = Arc::new(MemoryStore::default());
+            (store.clone(), store)
+        }
+    };
+    if config.enumeration.protect {
+        log::info!("answering unknown users with decoys");
+        users = Arc::new(DecoyStore::new(&config, users)?);
+    }
+    let api = API::new(&config, users, sessions.clone(), load_keys(&config)?)?;
+    api.pin_parameters().await?;
+    if !api.client_certificates {
+        log::warn!("no TLS client CA is configured, so UnlockUser is refused and locked users wait out the lock");
+    }
+    tokio::spawn(reap(sessions, api.challenge_ttl, api.session_idle_timeout));
 
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
@@ -253,34 +1167,560 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build()
         .unwrap();
 
-    Server::builder()
+    log::info!("listening on {}{}", config.listen, if tls.is_some() { " with TLS" } else { "" });
+    let router = Server::builder()
         .accept_http1(true)
-        .layer(
-            CorsLayer::new()
-                .allow_origin(AllowOrigin::mirror_request())
-                .allow_credentials(true)
-                .max_age(DEFAULT_MAX_AGE)
-                .expose_headers(
-                    DEFAULT_EXPOSED_HEADERS
-                        .iter()
-                        .cloned()
-                        .map(HeaderName::from_static)
-                        .collect::<Vec<HeaderName>>(),
-                )
-                .allow_headers(
-                    DEFAULT_ALLOW_HEADERS
-                        .iter()
-                        .cloned()
-                        .map(HeaderName::from_static)
-                        .collect::<Vec<HeaderName>>(),
-                ),
-        )
+        .layer(config.cors()?)
         .layer(GrpcWebLayer::new())
+        .layer(RateLimitLayer::new(Arc::new(RateLimiter::new(config.rate_limit.clone()))))
+        .layer(PaddingLayer::new(config.min_response_time()))
         .add_service(pb2::auth_server::AuthServer::new(api))
         .add_service(health_service)
-        .add_service(reflection_service)
-        .serve(addr)
-        .await?;
+        .add_service(reflection_service);
+    match tls {
+        Some(tls) => {
+            tokio::spawn(tls::reload_on_hangup(tls.clone()));
+            router.serve_with_incoming(tls.incoming(TcpListener::bind(config.listen).await?)).await?;
+        }
+        None => router.serve(config.listen).await?,
+    }
 
     Ok(())
 }
+
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::{point::AffineCoordinates, PrimeField};
+    use protocol::Or;
+    use tonic::Code;
+
+    use super::*;
+    use pb2::auth_server::Auth;
+
+    const REALM: &str = "chaum-pedersen-auth";
+
+    fn setup_api() -> API {
+        let config = Config::parse(include_str!("../config.example.toml")).unwrap();
+        let store = Arc::new(MemoryStore::default());
+        API::new(&config, store.clone(), store, Keys::generate().unwrap()).unwrap()
+    }
+
+    fn point(point: AffinePoint) -> Option<pb2::Point> {
+        Some(pb2::Point { x: point.x().to_vec(), is_y_odd: point.y_is_odd().into() })
+    }
+
+    fn register_request(api: &API, user: &str, proof_user: &str) -> pb2::RegisterRequest {
+        let (y1, y2) = api.protocol.register(REALM, user, "password");
+        let (r1, r2, s) = api.protocol.prove_registration(REALM, proof_user, "password");
+        pb2::RegisterRequest {
+            user: user.to_string(),
+            y1: y1.to_bytes_be(),
+            y2: y2.to_bytes_be(),
+            factors: Vec::new(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+        }
+    }
+
+    fn k256_register_request(api: &API, user: &str, proof_user: &str) -> pb2::K256RegisterRequest {
+        let (y1, y2) = api.protocol_k256.register(REALM, user, "password");
+        let (r1, r2, s) = api.protocol_k256.prove_registration(REALM, proof_user, "password");
+        pb2::K256RegisterRequest {
+            user: user.to_string(),
+            y1: point(y1),
+            y2: point(y2),
+            factors: Vec::new(),
+            r1: point(r1),
+            r2: point(r2),
+            s: s.to_repr().to_vec(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_answers() {
+        let api = setup_api();
+        let request = pb2::AuthenticationAnswerRequest { auth_id: "not an id".to_string(), s: vec![1] };
+        assert_eq!(api.verify_authentication(Request::new(request)).await.unwrap_err().code(), Code::InvalidArgument);
+        let request = pb2::K256AuthenticationAnswerRequest { auth_id: "not an id".to_string(), s: vec![1; 32] };
+        assert_eq!(api.k256_verify_authentication(Request::new(request)).await.unwrap_err().code(), Code::InvalidArgument);
+        for s in [vec![1; 31], vec![1; 33], Vec::new()] {
+            let request = pb2::K256AuthenticationAnswerRequest { auth_id: Uuid::new_v4().to_string(), s };
+            assert_eq!(api.k256_verify_authentication(Request::new(request)).await.unwrap_err().code(), Code::Unauthenticated);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_proof() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        let copied = register_request(&api, "mallory", "alice");
+        assert_eq!(api.register(Request::new(copied)).await.unwrap_err().code(), Code::InvalidArgument);
+        let missing = pb2::RegisterRequest { r1: Vec::new(), r2: Vec::new(), s: Vec::new(), ..register_request(&api, "bob", "bob") };
+        assert_eq!(api.register(Request::new(missing)).await.unwrap_err().code(), Code::InvalidArgument);
+        let identity = pb2::RegisterRequest { y1: vec![1], y2: vec![1], ..register_request(&api, "bob", "bob") };
+        assert_eq!(api.register(Request::new(identity)).await.unwrap_err().code(), Code::InvalidArgument);
+
+        let config = Config::parse(include_str!("../config.example.toml")).unwrap();
+        let p: BigUint = config.parameters.p.unwrap().parse().unwrap();
+        let multi = api.protocol.as_multi();
+        let x = multi.secret(REALM, "bob", "password");
+        let ys: Vec<_> = multi.register(REALM, "bob", "password").iter().map(|y| &p - y).collect();
+        let context = protocol::registration_context(REALM, "bob");
+        let (rs, s) = loop {
+            let (k, rs) = multi.commit();
+            let c = multi.challenge_for(&ys, &rs, &context);
+            if !c.bit(0) {
+                break (rs, multi.solve_secret(&x, &k, &c));
+            }
+        };
+        assert!(multi.verify_proof(&ys, &rs, &s, &context));
+        let negated = pb2::RegisterRequest {
+            user: "bob".to_string(),
+            y1: ys[0].to_bytes_be(),
+            y2: ys[1].to_bytes_be(),
+            factors: Vec::new(),
+            r1: rs[0].to_bytes_be(),
+            r2: rs[1].to_bytes_be(),
+            s: s.to_bytes_be(),
+        };
+        assert_eq!(api.register(Request::new(negated)).await.unwrap_err().code(), Code::InvalidArgument);
+
+        api.k256_register(Request::new(k256_register_request(&api, "carol", "carol"))).await.unwrap();
+        let copied = k256_register_request(&api, "mallory", "carol");
+        assert_eq!(api.k256_register(Request::new(copied)).await.unwrap_err().code(), Code::InvalidArgument);
+        let missing = pb2::K256RegisterRequest { r1: None, ..k256_register_request(&api, "dave", "dave") };
+        assert_eq!(api.k256_register(Request::new(missing)).await.unwrap_err().code(), Code::InvalidArgument);
+
+        let suite = api.registry.get(ChaumPedersenK256::CIPHERSUITE).unwrap();
+        let suite_request = |user: &str, proof_user: &str| {
+            let (r, s) = suite.prove_registration(REALM, proof_user, "password", &mut rand::thread_rng());
+            pb2::SuiteRegisterRequest {
+                user: user.to_string(),
+                ciphersuite: suite.id().to_string(),
+                y: suite.register(REALM, user, "password"),
+                factors: Vec::new(),
+                r,
+                s,
+            }
+        };
+        api.suite_register(Request::new(suite_request("erin", "erin"))).await.unwrap();
+        assert_eq!(api.suite_register(Request::new(suite_request("mallory", "erin"))).await.unwrap_err().code(), Code::InvalidArgument);
+        let missing = pb2::SuiteRegisterRequest { r: Vec::new(), ..suite_request("frank", "frank") };
+        assert_eq!(api.suite_register(Request::new(missing)).await.unwrap_err().code(), Code::InvalidArgument);
+    }
+
+    /// OR challenge over `users`, stored as if an earlier version of the service had issued it,
+    /// answered with the secret of the last user only.
+    async fn or_answer(api: &API, users: &[&str]) -> pb2::ComposedAuthenticationAnswerRequest {
+        let protocol = api.protocol.as_multi();
+        let mut statements = Vec::new();
+        for user in users {
+            let Credentials::Exp((y1, y2)) = api.users.get_user(user).await.unwrap().unwrap().y else { unreachable!() };
+            statements.push(Statement::new(protocol.clone(), vec![y1, y2]));
+        }
+        let or = Or::new(protocol.group().clone(), statements);
+        let (commitment, rs) = or.commit(users.len() - 1).unwrap();
+        let c = or.challenge();
+        let mut branches: Vec<_> = users.iter().zip(&rs).map(|(user, r)| (user.to_string(), Credentials::Exp((r[0].clone(), r[1].clone())))).collect();
+        let (user, r) = branches.remove(0);
+        let auth_id = Uuid::new_v4();
+        api.sessions.insert_session(auth_id, Session {
+            id: None,
+            user,
+            r,
+            c: c.to_bytes_be(),
+            composition: Some(Composition { kind: pb2::Composition::Or, branches }),
+            expires: SystemTime::now() + Duration::from_secs(60),
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }).await.unwrap();
+        let (cs, ss) = or.solve(commitment, &protocol.secret(REALM, users[users.len() - 1], "password"), &c);
+        pb2::ComposedAuthenticationAnswerRequest {
+            auth_id: auth_id.to_string(),
+            c: cs.iter().map(BigUint::to_bytes_be).collect(),
+            s: ss.iter().map(BigUint::to_bytes_be).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_or_refused() {
+        let api = setup_api();
+        for user in ["alice", "mallory"] {
+            api.register(Request::new(register_request(&api, user, user))).await.unwrap();
+        }
+        api.k256_register(Request::new(k256_register_request(&api, "carol", "carol"))).await.unwrap();
+        let commitment = || {
+            let (_, r1, r2) = api.protocol.commit();
+            pb2::Commitment { r1: r1.to_bytes_be(), r2: r2.to_bytes_be() }
+        };
+        for users in [["alice", "mallory"], ["alice", "alice"]] {
+            let status = api.create_composed_authentication_challenge(Request::new(pb2::ComposedAuthenticationChallengeRequest {
+                composition: pb2::Composition::Or as i32,
+                users: users.map(String::from).to_vec(),
+                commitments: vec![commitment(), commitment()],
+            })).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        }
+        let k256_commitment = || {
+            let (_, r1, r2) = api.protocol_k256.commit();
+            pb2::K256Commitment { r1: point(r1), r2: point(r2) }
+        };
+        let status = api.k256_create_composed_authentication_challenge(Request::new(pb2::K256ComposedAuthenticationChallengeRequest {
+            composition: pb2::Composition::Or as i32,
+            users: vec!["carol".to_string(), "carol".to_string()],
+            commitments: vec![k256_commitment(), k256_commitment()],
+        })).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        for users in [["alice", "mallory"], ["mallory", "mallory"]] {
+            let answer = or_answer(&api, &users).await;
+            let status = api.verify_composed_authentication(Request::new(answer)).await.unwrap_err();
+            assert_eq!((status.code(), status.message()), (Code::Unauthenticated, "invalid protocol"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_and_across_registrations() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        api.k256_register(Request::new(k256_register_request(&api, "carol", "carol"))).await.unwrap();
+        for (user, id) in [("erin", ChaumPedersen::CIPHERSUITE), ("frank", ChaumPedersenK256::CIPHERSUITE)] {
+            let suite = api.registry.get(id).unwrap();
+            let (r, s) = suite.prove_registration(REALM, user, "password", &mut rand::thread_rng());
+            api.suite_register(Request::new(pb2::SuiteRegisterRequest {
+                user: user.to_string(),
+                ciphersuite: id.to_string(),
+                y: suite.register(REALM, user, "password"),
+                factors: Vec::new(),
+                r,
+                s,
+            })).await.unwrap();
+        }
+
+        let protocol = api.protocol.as_multi();
+        let users = ["alice", "erin"];
+        let and = And::new(protocol.group().clone(), users.iter().map(|user| Statement::new(protocol.clone(), protocol.register(REALM, user, "password"))).collect());
+        let (ks, rs) = and.commit();
+        let challenge = api.create_composed_authentication_challenge(Request::new(pb2::ComposedAuthenticationChallengeRequest {
+            composition: pb2::Composition::And as i32,
+            users: users.map(String::from).to_vec(),
+            commitments: rs.iter().map(|r| pb2::Commitment { r1: r[0].to_bytes_be(), r2: r[1].to_bytes_be() }).collect(),
+        })).await.unwrap().into_inner();
+        let xs: Vec<_> = users.iter().map(|user| protocol.secret(REALM, user, "password")).collect();
+        let ss = and.solve(&xs, &ks, &BigUint::from_bytes_be(&challenge.c));
+        let session_id = api.verify_composed_authentication(Request::new(pb2::ComposedAuthenticationAnswerRequest {
+            auth_id: challenge.auth_id,
+            c: Vec::new(),
+            s: ss.iter().map(BigUint::to_bytes_be).collect(),
+        })).await.unwrap().into_inner().session_id;
+        assert_eq!(api.keys.verify(&session_id).unwrap().sub, "alice");
+
+        let protocol = api.protocol_k256.as_multi();
+        let users = ["carol", "frank"];
+        let and = And::new(K256Group, users.iter().map(|user| Statement::new(protocol.clone(), protocol.register(REALM, user, "password"))).collect());
+        let (ks, rs) = and.commit();
+        let challenge = api.k256_create_composed_authentication_challenge(Request::new(pb2::K256ComposedAuthenticationChallengeRequest {
+            composition: pb2::Composition::And as i32,
+            users: users.map(String::from).to_vec(),
+            commitments: rs.iter().map(|r| pb2::K256Commitment { r1: point(r[0]), r2: point(r[1]) }).collect(),
+        })).await.unwrap().into_inner();
+        let xs: Vec<_> = users.iter().map(|user| protocol.secret(REALM, user, "password")).collect();
+        let ss = and.solve(&xs, &ks, &k256_scalar(&challenge.c).unwrap());
+        let session_id = api.k256_verify_composed_authentication(Request::new(pb2::K256ComposedAuthenticationAnswerRequest {
+            auth_id: challenge.auth_id,
+            c: Vec::new(),
+            s: ss.iter().map(|s| s.to_repr().to_vec()).collect(),
+        })).await.unwrap().into_inner().session_id;
+        assert_eq!(api.keys.verify(&session_id).unwrap().sub, "carol");
+    }
+
+    #[tokio::test]
+    async fn test_composed_failure_counts_once() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        let commitment = || {
+            let (_, r1, r2) = api.protocol.commit();
+            pb2::Commitment { r1: r1.to_bytes_be(), r2: r2.to_bytes_be() }
+        };
+        let users = vec!["alice".to_string(); api.lockout.lock_after as usize];
+        let challenge = api.create_composed_authentication_challenge(Request::new(pb2::ComposedAuthenticationChallengeRequest {
+            composition: pb2::Composition::And as i32,
+            commitments: users.iter().map(|_| commitment()).collect(),
+            users,
+        })).await.unwrap().into_inner();
+        let status = api.verify_composed_authentication(Request::new(pb2::ComposedAuthenticationAnswerRequest {
+            auth_id: challenge.auth_id,
+            c: Vec::new(),
+            s: vec![vec![1]; api.lockout.lock_after as usize],
+        })).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(api.users.get_failures("alice").await.unwrap().unwrap().count, 1);
+    }
+
+    async fn answer(api: &API, user: &str, password: &str) -> pb2::AuthenticationAnswerRequest {
+        let (k, r1, r2) = api.protocol.commit();
+        let challenge = api.create_authentication_challenge(Request::new(pb2::AuthenticationChallengeRequest {
+            user: user.to_string(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+        })).await.unwrap().into_inner();
+        let s = api.protocol.solve(REALM, user, password, &k, &BigUint::from_bytes_be(&challenge.c));
+        pb2::AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be() }
+    }
+
+    async fn login(api: &API, user: &str, password: &str) -> Result<String, Status> {
+        let answer = answer(api, user, password).await;
+        Ok(api.verify_authentication(Request::new(answer)).await?.into_inner().session_id)
+    }
+
+    fn change_password_request(api: &API, user: &str, password: &str, nonce: &[u8]) -> pb2::ChangePasswordRequest {
+        let suite = api.registry.get(ChaumPedersen::CIPHERSUITE).unwrap();
+        let y = suite.register(REALM, user, password);
+        let (r, s) = suite.prove(REALM, user, password, &change_context(REALM, user, nonce, &y), &mut rand::thread_rng());
+        pb2::ChangePasswordRequest {
+            user: user.to_string(),
+            y,
+            proof: Some(pb2::Proof { r, s }),
+            ..Default::default()
+        }
+    }
+
+    async fn session_nonce(api: &API, session_id: &str) -> Result<Vec<u8>, Status> {
+        let request = pb2::SessionNonceRequest { session_id: session_id.to_string() };
+        Ok(api.create_session_nonce(Request::new(request)).await?.into_inner().nonce)
+    }
+
+    async fn valid(api: &API, session_id: &str) -> bool {
+        api.validate_session(Request::new(pb2::ValidateSessionRequest { session_id: session_id.to_string() })).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_lockout() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        for _ in 0..2 {
+            assert_eq!(login(&api, "alice", "wrong").await.unwrap_err().code(), Code::Unauthenticated);
+        }
+        let (wrong, right) = (answer(&api, "alice", "wrong").await, answer(&api, "alice", "password").await);
+        assert_eq!(api.verify_authentication(Request::new(wrong)).await.unwrap_err().code(), Code::Unauthenticated);
+        let status = api.verify_authentication(Request::new(right)).await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "1");
+        let request = pb2::AuthenticationChallengeRequest { user: "alice".to_string(), ..Default::default() };
+        let status = api.create_authentication_challenge(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        let status = api.unlock_user(Request::new(pb2::UnlockUserRequest { user: "alice".to_string() })).await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+
+    /// Rewrites the stored session of `token` with `f`, to move it in time.
+    async fn age(api: &API, token: &str, f: impl FnOnce(Session) -> Session) {
+        let jti = api.keys.verify(token).unwrap().jti;
+        let session = api.sessions.get_session(&jti).await.unwrap().unwrap();
+        api.sessions.insert_session(jti, f(session)).await.unwrap();
+    }
+
+    async fn refresh(api: &API, session_id: &str) -> Result<pb2::RefreshSessionResponse, Status> {
+        let request = pb2::RefreshSessionRequest { session_id: session_id.to_string() };
+        Ok(api.refresh_session(Request::new(request)).await?.into_inner())
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        let session_id = login(&api, "alice", "password").await.unwrap();
+        let validated = api.validate_session(Request::new(pb2::ValidateSessionRequest { session_id: session_id.clone() })).await.unwrap().into_inner();
+        assert_eq!(validated.user, "alice");
+        assert!(validated.expires_at > unix(SystemTime::now()));
+
+        age(&api, &session_id, |session| Session { expires: SystemTime::now() + Duration::from_secs(10), ..session }).await;
+        let refreshed = refresh(&api, &session_id).await.unwrap();
+        assert!(refreshed.expires_at >= unix(SystemTime::now() + api.session_ttl) - 1);
+        assert_eq!(api.keys.verify(&refreshed.session_id).unwrap().jti, api.keys.verify(&session_id).unwrap().jti);
+        assert!(valid(&api, &refreshed.session_id).await);
+
+        api.logout(Request::new(pb2::LogoutRequest { session_id: session_id.clone() })).await.unwrap();
+        assert!(!valid(&api, &session_id).await && !valid(&api, &refreshed.session_id).await);
+        assert_eq!(refresh(&api, &refreshed.session_id).await.unwrap_err().code(), Code::Unauthenticated);
+        let status = api.logout(Request::new(pb2::LogoutRequest { session_id: refreshed.session_id })).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(refresh(&api, "invalid").await.unwrap_err().code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_session_expiry() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        let now = SystemTime::now();
+        let sessions = [
+            |session: Session| Session { expires: SystemTime::now() - Duration::from_secs(1), ..session },
+            |session: Session| Session { last_seen: session.last_seen - Duration::from_secs(30 * 60), ..session },
+            |session: Session| Session { started: session.started - Duration::from_secs(7 * 24 * 60 * 60), ..session },
+        ];
+        for f in sessions {
+            let session_id = login(&api, "alice", "password").await.unwrap();
+            age(&api, &session_id, f).await;
+            assert_eq!(refresh(&api, &session_id).await.unwrap_err().code(), Code::Unauthenticated);
+            assert!(!valid(&api, &session_id).await);
+            let jti = api.keys.verify(&session_id).unwrap().jti;
+            assert!(api.sessions.get_session(&jti).await.unwrap().is_none());
+        }
+
+        // Refreshing extends a session up to its maximum age only.
+        let session_id = login(&api, "alice", "password").await.unwrap();
+        let started = now - api.session_max_age + Duration::from_secs(60);
+        age(&api, &session_id, |session| Session { started, ..session }).await;
+        let refreshed = refresh(&api, &session_id).await.unwrap();
+        assert_eq!(refreshed.expires_at, unix(started + api.session_max_age));
+        assert_eq!(api.keys.verify(&refreshed.session_id).unwrap().exp, refreshed.expires_at);
+        assert!(valid(&api, &refreshed.session_id).await);
+    }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        let sessions = [login(&api, "alice", "password").await.unwrap(), login(&api, "alice", "password").await.unwrap()];
+
+        let answered = |password: &'static str| {
+            let api = &api;
+            async move {
+                let answer = answer(api, "alice", password).await;
+                let request = change_password_request(api, "alice", "new password", answer.auth_id.as_bytes());
+                pb2::ChangePasswordRequest { auth_id: answer.auth_id, s: answer.s, ..request }
+            }
+        };
+        let status = api.change_password(Request::new(answered("wrong").await)).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        let copied = pb2::ChangePasswordRequest { proof: change_password_request(&api, "mallory", "new password", b"").proof, ..answered("password").await };
+        assert_eq!(api.change_password(Request::new(copied)).await.unwrap_err().code(), Code::InvalidArgument);
+        let stale = pb2::ChangePasswordRequest { proof: change_password_request(&api, "alice", "new password", b"other").proof, ..answered("password").await };
+        assert_eq!(api.change_password(Request::new(stale)).await.unwrap_err().code(), Code::InvalidArgument);
+        let unproven = change_password_request(&api, "alice", "new password", b"");
+        assert_eq!(api.change_password(Request::new(unproven)).await.unwrap_err().code(), Code::InvalidArgument);
+        assert!(valid(&api, &sessions[0]).await);
+
+        let changed = api.change_password(Request::new(answered("password").await)).await.unwrap().into_inner();
+        assert_eq!(changed.revoked_sessions, 2);
+        assert!(!valid(&api, &sessions[0]).await && !valid(&api, &sessions[1]).await);
+        assert!(valid(&api, &changed.session_id).await);
+        assert_eq!(login(&api, "alice", "password").await.unwrap_err().code(), Code::Unauthenticated);
+        let session_id = login(&api, "alice", "new password").await.unwrap();
+
+        let suite = api.registry.get(ChaumPedersen::CIPHERSUITE).unwrap();
+        let in_session = |session_id: &str, nonce: &[u8], password: &str, new_password: &str| {
+            let request = change_password_request(&api, "alice", new_password, nonce);
+            let (r, s) = suite.prove(REALM, "alice", password, &change_context(REALM, "alice", nonce, &request.y), &mut rand::thread_rng());
+            pb2::ChangePasswordRequest {
+                session_id: session_id.to_string(),
+                session_proof: Some(pb2::Proof { r, s }),
+                ..request
+            }
+        };
+        assert_eq!(session_nonce(&api, "invalid").await.unwrap_err().code(), Code::Unauthenticated);
+        let status = api.change_password(Request::new(in_session(&session_id, b"", "new password", "newer password"))).await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        let nonce = session_nonce(&api, &session_id).await.unwrap();
+        let request = in_session(&session_id, &nonce, "new password", "newer password");
+        let other = change_password_request(&api, "alice", "other password", &nonce);
+        let swapped = pb2::ChangePasswordRequest { y: other.y, proof: other.proof, ..request.clone() };
+        assert_eq!(api.change_password(Request::new(swapped)).await.unwrap_err().code(), Code::Unauthenticated);
+        assert_eq!(api.change_password(Request::new(request.clone())).await.unwrap_err().code(), Code::FailedPrecondition);
+        session_nonce(&api, &session_id).await.unwrap();
+        assert_eq!(api.change_password(Request::new(request.clone())).await.unwrap_err().code(), Code::Unauthenticated);
+        // A login resets the failures before they lock alice out.
+        login(&api, "alice", "new password").await.unwrap();
+        session_nonce(&api, &changed.session_id).await.unwrap();
+        let replayed = pb2::ChangePasswordRequest { session_id: changed.session_id.clone(), ..request };
+        assert_eq!(api.change_password(Request::new(replayed)).await.unwrap_err().code(), Code::Unauthenticated);
+
+        let nonce = session_nonce(&api, &session_id).await.unwrap();
+        let changed = api.change_password(Request::new(in_session(&session_id, &nonce, "new password", "newer password"))).await.unwrap().into_inner();
+        assert_eq!((changed.session_id.as_str(), changed.revoked_sessions), (session_id.as_str(), 2));
+        assert!(valid(&api, &session_id).await);
+        login(&api, "alice", "newer password").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pin_parameters() {
+        let mut config = Config::parse(include_str!("../config.example.toml")).unwrap();
+        let store = Arc::new(MemoryStore::default());
+        let api = |config: &Config| API::new(config, store.clone(), store.clone(), Keys::generate().unwrap()).unwrap();
+        api(&config).pin_parameters().await.unwrap();
+        api(&config).pin_parameters().await.unwrap();
+        config.parameters.k256_h_offset = config.parameters.k256_h_offset.map(|offset| offset + 1);
+        let error = api(&config).pin_parameters().await.unwrap_err();
+        assert!(error.to_string().contains(ChaumPedersenK256::CIPHERSUITE));
+    }
+
+    #[tokio::test]
+    async fn test_delete_account() {
+        let api = setup_api();
+        for user in ["alice", "bob"] {
+            api.register(Request::new(register_request(&api, user, user))).await.unwrap();
+        }
+        let session_id = login(&api, "alice", "password").await.unwrap();
+        let bob = login(&api, "bob", "password").await.unwrap();
+        let delete = |user: &str, session_id: &str| pb2::DeleteAccountRequest {
+            user: user.to_string(),
+            session_id: session_id.to_string(),
+            ..Default::default()
+        };
+        let status = api.delete_account(Request::new(delete("alice", &bob))).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        let status = api.delete_account(Request::new(delete("alice", ""))).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        let wrong = answer(&api, "alice", "wrong").await;
+        let wrong = pb2::DeleteAccountRequest { auth_id: wrong.auth_id, s: wrong.s, ..delete("alice", "") };
+        assert_eq!(api.delete_account(Request::new(wrong)).await.unwrap_err().code(), Code::Unauthenticated);
+
+        api.delete_account(Request::new(delete("alice", &session_id))).await.unwrap();
+        assert!(!valid(&api, &session_id).await);
+        assert!(api.users.get_user("alice").await.unwrap().is_none());
+        assert!(api.users.get_failures("alice").await.unwrap().is_none());
+        assert!(valid(&api, &bob).await);
+
+        let answered = answer(&api, "bob", "password").await;
+        api.delete_account(Request::new(pb2::DeleteAccountRequest { auth_id: answered.auth_id, s: answered.s, ..delete("bob", "") })).await.unwrap();
+        assert!(!valid(&api, &bob).await);
+        api.register(Request::new(register_request(&api, "bob", "bob"))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_account() {
+        let api = setup_api();
+        api.register(Request::new(register_request(&api, "alice", "alice"))).await.unwrap();
+        assert_eq!(login(&api, "alice", "wrong").await.unwrap_err().code(), Code::Unauthenticated);
+        let session_id = login(&api, "alice", "password").await.unwrap();
+        let other = login(&api, "alice", "password").await.unwrap();
+        api.logout(Request::new(pb2::LogoutRequest { session_id: other })).await.unwrap();
+
+        let answered = answer(&api, "alice", "password").await;
+        let export = |request| async { api.export_account(Request::new(request)).await.map(|response| response.into_inner().json) };
+        let json = export(pb2::ExportAccountRequest { user: "alice".to_string(), auth_id: answered.auth_id, s: answered.s, ..Default::default() }).await.unwrap();
+        assert_eq!(json, export(pb2::ExportAccountRequest { user: "alice".to_string(), session_id: session_id.clone(), ..Default::default() }).await.unwrap());
+        let export: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let (y1, y2) = api.protocol.register(REALM, "alice", "password");
+        let y = api.encoded(&Credentials::Exp((y1, y2)));
+        let hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+        assert_eq!(export["user"], "alice");
+        assert_eq!(export["ciphersuite"], ChaumPedersen::CIPHERSUITE);
+        assert_eq!(export["factors"], serde_json::json!(["FACTOR_PASSWORD"]));
+        assert_eq!(export["credentials"], serde_json::json!([hex(&y[0]), hex(&y[1])]));
+        assert!(export["registered_at"].as_u64().unwrap() > 0);
+        assert!(export["failures"].is_null());
+        assert_eq!(export["sessions"].as_array().unwrap().len(), 1);
+        let claims = api.keys.verify(&session_id).unwrap();
+        assert_eq!(export["sessions"][0]["session_id"], claims.jti.to_string());
+        let events: Vec<_> = export["audit"].as_array().unwrap().iter().map(|entry| entry["event"].as_str().unwrap()).collect();
+        assert_eq!(events, ["failed_verification", "login", "login", "logout"]);
+
+        let status = api.export_account(Request::new(pb2::ExportAccountRequest { user: "alice".to_string(), ..Default::default() })).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+}
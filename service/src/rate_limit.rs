@@ -0,0 +1,342 @@
+//! Rate limiting registration, challenge creation and verification per client address, as a tower
+//! layer in front of the gRPC services. Every client has a token bucket per budget.
+
+use std::{collections::HashMap, future::Future, net::IpAddr, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll}, time::{Duration, Instant}};
+
+use tonic::{body::BoxBody, server::NamedService, transport::server::{TcpConnectInfo, TlsConnectInfo}};
+use tower::{Layer, Service};
+
+use crate::{config::{Limit, RateLimit}, throttled};
+
+
+/// Buckets kept before full ones are dropped.
+const MAX_BUCKETS: usize = 10_000;
+
+/// Buckets dropped, least recently used first, when none of them are full.
+const EVICTED_BUCKETS: usize = MAX_BUCKETS / 10;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub(crate) enum Budget {
+    Register,
+    Challenge,
+    Verify,
+}
+
+impl Budget {
+    const ALL: [Budget; 3] = [Budget::Register, Budget::Challenge, Budget::Verify];
+
+    /// Budget of the RPC at `path`, none for RPCs that are not limited.
+    pub(crate) fn of(path: &str) -> Option<Self> {
+        let method = path.strip_prefix("/zkp_auth.Auth/")?;
+        if method.ends_with("Register") {
+            Some(Budget::Register)
+        } else if method.ends_with("AuthenticationChallenge") || method == "CreateSessionNonce" {
+            Some(Budget::Challenge)
+        } else if (method.contains("Verify") && method.ends_with("Authentication")) || matches!(method, "ChangePassword" | "DeleteAccount" | "ExportAccount") {
+            Some(Budget::Verify)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Budget::Register => "register",
+            Budget::Challenge => "challenge",
+            Budget::Verify => "verify",
+        }
+    }
+}
+
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: Limit, now: Instant) -> Self {
+        Self { tokens: limit.burst as f64, updated: now }
+    }
+
+    fn refilled(&self, limit: Limit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * limit.per_minute as f64 / 60.0).min(limit.burst as f64)
+    }
+
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        self.tokens = self.refilled(limit, now);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long until the next one.
+    fn take(&mut self, limit: Limit, now: Instant) -> Option<Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) * 60.0 / limit.per_minute as f64))
+        }
+    }
+}
+
+
+pub struct RateLimiter {
+    config: RateLimit,
+    buckets: Mutex<HashMap<(Budget, IpAddr), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimit) -> Self {
+        for budget in Budget::ALL {
+            let limit = config.limit(budget);
+            metrics::gauge!("rate_limit_burst", "budget" => budget.name()).set(limit.burst as f64);
+            metrics::gauge!("rate_limit_per_minute", "budget" => budget.name()).set(limit.per_minute as f64);
+        }
+        Self { config, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    /// The address limits apply to: the peer, or behind trusted proxies the last address in
+    /// `x-forwarded-for` that is not one of them.
+    fn client<B>(&self, request: &http::Request<B>) -> Option<IpAddr> {
+        let extensions = request.extensions();
+        let peer = extensions.get::<TcpConnectInfo>()
+            .or_else(|| extensions.get::<TlsConnectInfo<TcpConnectInfo>>().map(TlsConnectInfo::get_ref))
+            .and_then(TcpConnectInfo::remote_addr)?
+            .ip()
+            .to_canonical();
+        let trusted = |ip: &IpAddr| self.config.trusted_proxies.contains(ip);
+        if !trusted(&peer) {
+            return Some(peer);
+        }
+        let forwarded: Vec<_> = request.headers().get_all("x-forwarded-for").iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .collect();
+        for address in forwarded.into_iter().rev() {
+            match address.trim().parse::<IpAddr>().map(|ip| ip.to_canonical()) {
+                Ok(ip) if trusted(&ip) => continue,
+                Ok(ip) => return Some(ip),
+                Err(_) => break,
+            }
+        }
+        Some(peer)
+    }
+
+    /// The bucket `client` draws from: IPv6 clients are usually handed a whole /64, so they
+    /// share one bucket per prefix rather than getting one per address.
+    fn bucket(client: IpAddr) -> IpAddr {
+        match client {
+            IpAddr::V4(_) => client,
+            IpAddr::V6(ip) => IpAddr::V6((u128::from(ip) & !u128::from(u64::MAX)).into()),
+        }
+    }
+
+    /// Takes a call from the budget of the RPC `request` is for, or returns how long to wait.
+    fn check<B>(&self, request: &http::Request<B>, now: Instant) -> Option<Duration> {
+        if !self.config.enabled {
+            return None;
+        }
+        let budget = Budget::of(request.uri().path())?;
+        let client = Self::bucket(self.client(request)?);
+        let limit = self.config.limit(budget);
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&(budget, client)) {
+            buckets.retain(|(budget, _), bucket| {
+                let limit = self.config.limit(*budget);
+                bucket.refilled(limit, now) < limit.burst as f64
+            });
+            if buckets.len() >= MAX_BUCKETS {
+                let mut updated: Vec<_> = buckets.values().map(|bucket| bucket.updated).collect();
+                let (_, cutoff, _) = updated.select_nth_unstable(EVICTED_BUCKETS);
+                let cutoff = *cutoff;
+                buckets.retain(|_, bucket| bucket.updated >= cutoff);
+                log::warn!("dropped {} rate limit buckets still in use", MAX_BUCKETS - buckets.len());
+            }
+        }
+        let wait = buckets.entry((budget, client)).or_insert_with(|| Bucket::full(limit, now)).take(limit, now);
+        let outcome = if wait.is_some() { "limited" } else { "allowed" };
+        metrics::counter!("rate_limit_requests_total", "budget" => budget.name(), "outcome" => outcome).increment(1);
+        if wait.is_some() {
+            log::debug!("rate limited {} calls of {}", budget.name(), client);
+        }
+        wait
+    }
+}
+
+impl RateLimit {
+    fn limit(&self, budget: Budget) -> Limit {
+        match budget {
+            Budget::Register => self.register,
+            Budget::Challenge => self.challenge,
+            Budget::Verify => self.verify,
+        }
+    }
+}
+
+
+/// Layer answering `resource_exhausted` to clients that used up a budget.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, limiter: self.limiter.clone() }
+    }
+}
+
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<http::Request<B>> for RateLimitService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<BoxBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        match self.limiter.check(&request, Instant::now()) {
+            Some(wait) => Box::pin(std::future::ready(Ok(throttled("rate limit exceeded, retry later", wait).to_http()))),
+            None => Box::pin(self.inner.call(request)),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for RateLimitService<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use tower::{service_fn, ServiceExt};
+
+    use super::*;
+
+    fn request(path: &str, peer: &str, forwarded: Option<&str>) -> http::Request<()> {
+        let mut request = http::Request::builder().uri(path);
+        if let Some(forwarded) = forwarded {
+            request = request.header("x-forwarded-for", forwarded);
+        }
+        let mut request = request.body(()).unwrap();
+        let peer: SocketAddr = peer.parse().unwrap();
+        request.extensions_mut().insert(TcpConnectInfo { local_addr: None, remote_addr: Some(peer) });
+        request
+    }
+
+    #[test]
+    fn test_budget() {
+        assert_eq!(Budget::of("/zkp_auth.Auth/K256Register"), Some(Budget::Register));
+        assert_eq!(Budget::of("/zkp_auth.Auth/SuiteCreateAuthenticationChallenge"), Some(Budget::Challenge));
+        assert_eq!(Budget::of("/zkp_auth.Auth/CreateComposedAuthenticationChallenge"), Some(Budget::Challenge));
+        assert_eq!(Budget::of("/zkp_auth.Auth/CreateSessionNonce"), Some(Budget::Challenge));
+        assert_eq!(Budget::of("/zkp_auth.Auth/K256VerifyComposedAuthentication"), Some(Budget::Verify));
+        assert_eq!(Budget::of("/zkp_auth.Auth/ChangePassword"), Some(Budget::Verify));
+        assert_eq!(Budget::of("/zkp_auth.Auth/ExportAccount"), Some(Budget::Verify));
+        assert_eq!(Budget::of("/zkp_auth.Auth/ValidateSession"), None);
+        assert_eq!(Budget::of("/grpc.health.v1.Health/Check"), None);
+    }
+
+    #[test]
+    fn test_bucket() {
+        let limit = Limit { burst: 2, per_minute: 60 };
+        let now = Instant::now();
+        let mut bucket = Bucket::full(limit, now);
+        assert_eq!(bucket.take(limit, now), None);
+        assert_eq!(bucket.take(limit, now), None);
+        assert_eq!(bucket.take(limit, now), Some(Duration::from_secs(1)));
+        assert_eq!(bucket.take(limit, now + Duration::from_millis(500)).map(|wait| wait.as_millis()), Some(500));
+        assert_eq!(bucket.take(limit, now + Duration::from_secs(1)), None);
+        assert_eq!(bucket.take(limit, now + Duration::from_secs(60)), None);
+        assert_eq!(bucket.take(limit, now + Duration::from_secs(60)), None);
+        assert!(bucket.take(limit, now + Duration::from_secs(60)).is_some());
+    }
+
+    #[test]
+    fn test_client() {
+        let limiter = RateLimiter::new(RateLimit { trusted_proxies: vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()], ..RateLimit::default() });
+        let client = |peer, forwarded| limiter.client(&request("/", peer, forwarded)).unwrap().to_string();
+        assert_eq!(client("192.0.2.1:1234", Some("198.51.100.1")), "192.0.2.1");
+        assert_eq!(client("[::ffff:10.0.0.1]:1234", None), "10.0.0.1");
+        assert_eq!(client("10.0.0.1:1234", Some("198.51.100.1, 198.51.100.2, 10.0.0.2")), "198.51.100.2");
+        assert_eq!(client("10.0.0.1:1234", Some("garbage, 10.0.0.2")), "10.0.0.1");
+        assert!(limiter.client(&http::Request::new(())).is_none());
+    }
+
+    async fn ok(_request: http::Request<()>) -> Result<http::Response<BoxBody>, Infallible> {
+        Ok(http::Response::new(tonic::body::empty_body()))
+    }
+
+    #[tokio::test]
+    async fn test_layer() {
+        let limiter = Arc::new(RateLimiter::new(RateLimit { register: Limit { burst: 1, per_minute: 1 }, ..RateLimit::default() }));
+        let service = RateLimitLayer::new(limiter).layer(service_fn(ok));
+        let register = |peer| request("/zkp_auth.Auth/Register", peer, None);
+        let response = service.clone().oneshot(register("192.0.2.1:1")).await.unwrap();
+        assert!(response.headers().get("grpc-status").is_none());
+        let response = service.clone().oneshot(register("192.0.2.1:2")).await.unwrap();
+        assert_eq!(response.headers()["grpc-status"], (tonic::Code::ResourceExhausted as i32).to_string().as_str());
+        assert_eq!(response.headers()["retry-after"], "60");
+        assert!(service.clone().oneshot(register("192.0.2.2:1")).await.unwrap().headers().get("grpc-status").is_none());
+        let challenge = request("/zkp_auth.Auth/CreateAuthenticationChallenge", "192.0.2.1:3", None);
+        assert!(service.oneshot(challenge).await.unwrap().headers().get("grpc-status").is_none());
+
+        let limiter = RateLimiter::new(RateLimit { enabled: false, register: Limit { burst: 1, per_minute: 1 }, ..RateLimit::default() });
+        assert!((0..3).all(|_| limiter.check(&register("192.0.2.1:1"), Instant::now()).is_none()));
+    }
+
+    #[test]
+    fn test_ipv6_prefix() {
+        let limiter = RateLimiter::new(RateLimit { register: Limit { burst: 1, per_minute: 1 }, ..RateLimit::default() });
+        let now = Instant::now();
+        let register = |peer| request("/zkp_auth.Auth/Register", peer, None);
+        assert!(limiter.check(&register("[2001:db8:0:1::1]:1"), now).is_none());
+        assert!(limiter.check(&register("[2001:db8:0:1:ffff::2]:1"), now).is_some());
+        assert!(limiter.check(&register("[2001:db8:0:2::1]:1"), now).is_none());
+        assert!(limiter.check(&register("[::ffff:192.0.2.1]:1"), now).is_none());
+        assert!(limiter.check(&register("[::ffff:192.0.2.2]:1"), now).is_none());
+        assert_eq!(RateLimiter::bucket("2001:db8::1:2:3:4".parse().unwrap()).to_string(), "2001:db8::");
+    }
+
+    #[test]
+    fn test_max_buckets() {
+        let limiter = RateLimiter::new(RateLimit { register: Limit { burst: 1, per_minute: 1 }, ..RateLimit::default() });
+        let now = Instant::now();
+        let register = |client: usize| request("/zkp_auth.Auth/Register", &format!("10.{}.{}.{}:1", client >> 16, (client >> 8) & 0xff, client & 0xff), None);
+        for client in 0..MAX_BUCKETS {
+            assert!(limiter.check(&register(client), now + Duration::from_millis(client as u64)).is_none());
+        }
+        let later = now + Duration::from_secs(30);
+        assert!(limiter.check(&register(MAX_BUCKETS - 1), later).is_some());
+        assert!(limiter.check(&register(MAX_BUCKETS), later).is_none());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_BUCKETS - EVICTED_BUCKETS + 1);
+        assert!(limiter.check(&register(0), later).is_none());
+        assert!(limiter.check(&register(EVICTED_BUCKETS), later).is_some());
+    }
+}
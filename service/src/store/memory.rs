@@ -0,0 +1,388 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use eyre::Result;
+use tokio::sync::Mutex;
+use tonic::async_trait;
+use uuid::Uuid;
+
+use super::{AuditEntry, Credentials, Failures, Session, SessionStore, User, UserStore};
+
+
+/// Users and sessions kept in process memory, lost when the service stops.
+#[derive(Default)]
+pub struct MemoryStore {
+    users: Mutex<HashMap<String, User>>,
+    failures: Mutex<HashMap<String, Failures>>,
+    events: Mutex<HashMap<String, Vec<AuditEntry>>>,
+    deletions: Mutex<HashMap<String, Vec<SystemTime>>>,
+    parameters: Mutex<HashMap<String, Vec<u8>>>,
+    sessions: Mutex<HashMap<Uuid, Session>>,
+    nonces: Mutex<HashMap<Uuid, Vec<u8>>>,
+}
+
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn insert_user(&self, user: User) -> Result<bool> {
+        let mut users = self.users.lock().await;
+        if users.contains_key(&user.name) {
+            Ok(false)
+        } else {
+            users.insert(user.name.clone(), user);
+            Ok(true)
+        }
+    }
+
+    async fn get_user(&self, name: &str) -> Result<Option<User>> {
+        Ok(self.users.lock().await.get(name).cloned())
+    }
+
+    async fn get_failures(&self, name: &str) -> Result<Option<Failures>> {
+        Ok(self.failures.lock().await.get(name).cloned())
+    }
+
+    async fn record_failure(&self, name: &str, now: SystemTime) -> Result<Failures> {
+        let mut failures = self.failures.lock().await;
+        let entry = failures.entry(name.to_string()).or_insert(Failures { count: 0, last: now });
+        entry.count = entry.count.saturating_add(1);
+        entry.last = now;
+        Ok(entry.clone())
+    }
+
+    async fn reset_failures(&self, name: &str) -> Result<bool> {
+        Ok(self.failures.lock().await.remove(name).is_some())
+    }
+
+    async fn replace_credentials(&self, name: &str, old: &Credentials, new: Credentials) -> Result<bool> {
+        match self.users.lock().await.get_mut(name).filter(|user| user.y == *old) {
+            Some(user) => {
+                user.y = new;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remove_user(&self, name: &str, now: SystemTime) -> Result<bool> {
+        let mut users = self.users.lock().await;
+        self.failures.lock().await.remove(name);
+        self.events.lock().await.remove(name);
+        self.sessions.lock().await.retain(|_, session| {
+            session.user != name && session.composition.iter().flat_map(|composition| &composition.branches).all(|(user, _)| user != name)
+        });
+        let removed = users.remove(name).is_some();
+        if removed {
+            self.deletions.lock().await.entry(name.to_string()).or_default().push(now);
+        }
+        Ok(removed)
+    }
+
+    async fn record_event(&self, name: &str, entry: AuditEntry) -> Result<()> {
+        if self.users.lock().await.contains_key(name) {
+            self.events.lock().await.entry(name.to_string()).or_default().push(entry);
+        }
+        Ok(())
+    }
+
+    async fn get_events(&self, name: &str) -> Result<Vec<AuditEntry>> {
+        Ok(self.events.lock().await.get(name).cloned().unwrap_or_default())
+    }
+
+    async fn pin_parameters(&self, ciphersuite: &str, fingerprint: &[u8]) -> Result<bool> {
+        let mut parameters = self.parameters.lock().await;
+        Ok(parameters.entry(ciphersuite.to_string()).or_insert_with(|| fingerprint.to_vec()) == fingerprint)
+    }
+}
+
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn insert_session(&self, key: Uuid, session: Session) -> Result<()> {
+        self.sessions.lock().await.insert(key, session);
+        Ok(())
+    }
+
+    async fn take_challenge(&self, auth_id: &Uuid) -> Result<Option<Session>> {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.get(auth_id).is_some_and(|session| session.id.is_none()) {
+            Ok(sessions.remove(auth_id).filter(|session| session.expires > SystemTime::now()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn get_session(&self, session_id: &Uuid) -> Result<Option<Session>> {
+        Ok(self.sessions.lock().await.get(session_id).filter(|session| session.id == Some(*session_id)).cloned())
+    }
+
+    async fn touch_session(&self, session_id: &Uuid, last_seen: SystemTime, expires: SystemTime) -> Result<bool> {
+        let mut sessions = self.sessions.lock().await;
+        match sessions.get_mut(session_id).filter(|session| session.id == Some(*session_id)) {
+            Some(session) => {
+                session.last_seen = last_seen;
+                session.expires = expires;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn remove_session(&self, session_id: &Uuid) -> Result<bool> {
+        let mut sessions = self.sessions.lock().await;
+        if sessions.get(session_id).is_some_and(|session| session.id == Some(*session_id)) {
+            Ok(sessions.remove(session_id).is_some())
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn set_nonce(&self, session_id: &Uuid, nonce: Vec<u8>) -> Result<bool> {
+        let sessions = self.sessions.lock().await;
+        if sessions.get(session_id).is_some_and(|session| session.id == Some(*session_id)) {
+            self.nonces.lock().await.insert(*session_id, nonce);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn take_nonce(&self, session_id: &Uuid) -> Result<Option<Vec<u8>>> {
+        let sessions = self.sessions.lock().await;
+        let nonce = self.nonces.lock().await.remove(session_id);
+        Ok(nonce.filter(|_| sessions.get(session_id).is_some_and(|session| session.id == Some(*session_id))))
+    }
+
+    async fn user_sessions(&self, user: &str) -> Result<Vec<Session>> {
+        let mut sessions: Vec<_> = self.sessions.lock().await.values()
+            .filter(|session| session.user == user && session.id.is_some())
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| session.last_seen);
+        Ok(sessions)
+    }
+
+    async fn remove_user_sessions(&self, user: &str, except: Option<&Uuid>) -> Result<u64> {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.user != user || session.id.is_none() || session.id.as_ref() == except);
+        Ok((before - sessions.len()) as u64)
+    }
+
+    async fn reap(&self, now: SystemTime, idle_since: SystemTime) -> Result<u64> {
+        let mut sessions = self.sessions.lock().await;
+        let before = sessions.len();
+        sessions.retain(|_, session| session.expires > now && (session.id.is_none() || session.last_seen > idle_since));
+        self.nonces.lock().await.retain(|session_id, _| sessions.contains_key(session_id));
+        Ok((before - sessions.len()) as u64)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use num_bigint::BigUint;
+
+    use super::*;
+    use crate::{pb2, store::{Composition, Credentials, Event}};
+
+    fn user(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            realm: "chaum-pedersen-auth".to_string(),
+            ciphersuite: "CP-EXP-SHA256-SHA256".to_string(),
+            factors: vec![pb2::Factor::Password],
+            y: Credentials::Exp((BigUint::from(2u8), BigUint::from(3u8))),
+            registered: Some(SystemTime::now()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_user() {
+        let store = MemoryStore::default();
+        assert!(store.insert_user(user("alice")).await.unwrap());
+        assert!(!store.insert_user(user("alice")).await.unwrap());
+        assert_eq!(store.get_user("alice").await.unwrap().unwrap().name, "alice");
+        assert!(store.get_user("bob").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let store = MemoryStore::default();
+        let now = SystemTime::now();
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert_eq!(store.record_failure("alice", now).await.unwrap().count, 1);
+        let later = now + Duration::from_secs(1);
+        assert_eq!(store.record_failure("alice", later).await.unwrap(), Failures { count: 2, last: later });
+        assert_eq!(store.get_failures("alice").await.unwrap(), Some(Failures { count: 2, last: later }));
+        assert!(store.reset_failures("alice").await.unwrap());
+        assert!(!store.reset_failures("alice").await.unwrap());
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replace_credentials() {
+        let store = MemoryStore::default();
+        let old = user("alice").y;
+        let new = Credentials::Exp((BigUint::from(4u8), BigUint::from(5u8)));
+        assert!(!store.replace_credentials("alice", &old, new.clone()).await.unwrap());
+        store.insert_user(user("alice")).await.unwrap();
+        assert!(store.replace_credentials("alice", &old, new.clone()).await.unwrap());
+        assert!(!store.replace_credentials("alice", &old, old.clone()).await.unwrap());
+        assert_eq!(store.get_user("alice").await.unwrap().unwrap().y, new);
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let store = MemoryStore::default();
+        let entry = |event| AuditEntry { time: SystemTime::now(), event, session_id: None };
+        store.record_event("alice", entry(Event::Unlock)).await.unwrap();
+        assert!(store.get_events("alice").await.unwrap().is_empty());
+        store.insert_user(user("alice")).await.unwrap();
+        let login = AuditEntry { session_id: Some(Uuid::new_v4()), ..entry(Event::Login) };
+        store.record_event("alice", login.clone()).await.unwrap();
+        store.record_event("alice", entry(Event::Logout)).await.unwrap();
+        let events = store.get_events("alice").await.unwrap();
+        assert_eq!(events[0], login);
+        assert_eq!(events.iter().map(|entry| entry.event).collect::<Vec<_>>(), [Event::Login, Event::Logout]);
+    }
+
+    fn challenge(expires: SystemTime) -> Session {
+        Session {
+            id: None,
+            user: "alice".to_string(),
+            r: Credentials::Encoded(vec![vec![1], vec![2]]),
+            c: vec![3],
+            composition: None,
+            expires,
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_take_challenge() {
+        let store = MemoryStore::default();
+        let auth_id = Uuid::new_v4();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+        store.insert_session(auth_id, challenge(SystemTime::now() + Duration::from_secs(60))).await.unwrap();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_some());
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+
+        store.insert_session(auth_id, challenge(SystemTime::now() - Duration::from_secs(1))).await.unwrap();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+
+        store.insert_session(auth_id, Session { id: Some(Uuid::new_v4()), ..challenge(SystemTime::now() + Duration::from_secs(60)) }).await.unwrap();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let store = MemoryStore::default();
+        let session_id = Uuid::new_v4();
+        let auth_id = Uuid::new_v4();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        assert!(store.get_session(&auth_id).await.unwrap().is_none());
+        assert!(!store.remove_session(&auth_id).await.unwrap());
+
+        store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        let later = expires + Duration::from_secs(60);
+        assert!(store.touch_session(&session_id, expires, later).await.unwrap());
+        let session = store.get_session(&session_id).await.unwrap().unwrap();
+        assert_eq!((session.last_seen, session.expires), (expires, later));
+        assert!(store.remove_session(&session_id).await.unwrap());
+        assert!(store.get_session(&session_id).await.unwrap().is_none());
+        assert!(!store.touch_session(&session_id, expires, later).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_remove_user_sessions() {
+        let store = MemoryStore::default();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let auth_id = Uuid::new_v4();
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        let (kept, removed, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for session_id in [kept, removed] {
+            store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        }
+        store.insert_session(other, Session { id: Some(other), user: "bob".to_string(), ..challenge(expires) }).await.unwrap();
+        assert_eq!(store.remove_user_sessions("alice", Some(&kept)).await.unwrap(), 1);
+        assert!(store.get_session(&kept).await.unwrap().is_some());
+        assert!(store.get_session(&removed).await.unwrap().is_none());
+        assert!(store.get_session(&other).await.unwrap().is_some());
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_some());
+        assert_eq!(store.remove_user_sessions("alice", None).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_remove_user() {
+        let store = MemoryStore::default();
+        assert!(!store.remove_user("alice", SystemTime::now()).await.unwrap());
+        assert!(store.deletions.lock().await.is_empty());
+        store.insert_user(user("alice")).await.unwrap();
+        store.record_failure("alice", SystemTime::now()).await.unwrap();
+        store.record_event("alice", AuditEntry { time: SystemTime::now(), event: Event::Login, session_id: None }).await.unwrap();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let (session_id, composed, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        store.insert_session(composed, Session {
+            user: "bob".to_string(),
+            composition: Some(Composition { kind: pb2::Composition::And, branches: vec![("alice".to_string(), challenge(expires).r)] }),
+            ..challenge(expires)
+        }).await.unwrap();
+        store.insert_session(other, Session { id: Some(other), user: "bob".to_string(), ..challenge(expires) }).await.unwrap();
+        assert_eq!(store.user_sessions("alice").await.unwrap().len(), 1);
+
+        let now = SystemTime::now();
+        assert!(store.remove_user("alice", now).await.unwrap());
+        assert_eq!(store.deletions.lock().await["alice"], [now]);
+        assert!(store.get_user("alice").await.unwrap().is_none());
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert!(store.get_events("alice").await.unwrap().is_empty());
+        assert!(store.user_sessions("alice").await.unwrap().is_empty());
+        assert!(store.take_challenge(&composed).await.unwrap().is_none());
+        assert!(store.get_session(&other).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_nonce() {
+        let store = MemoryStore::default();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let (auth_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        assert!(!store.set_nonce(&auth_id, vec![1]).await.unwrap());
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(store.set_nonce(&session_id, vec![1]).await.unwrap());
+        assert!(store.set_nonce(&session_id, vec![2]).await.unwrap());
+        assert_eq!(store.take_nonce(&session_id).await.unwrap(), Some(vec![2]));
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(store.set_nonce(&session_id, vec![3]).await.unwrap());
+        assert!(store.remove_session(&session_id).await.unwrap());
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(!store.set_nonce(&session_id, vec![4]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reap() {
+        let store = MemoryStore::default();
+        let now = SystemTime::now();
+        let idle_since = now - Duration::from_secs(30);
+        store.insert_session(Uuid::new_v4(), challenge(now - Duration::from_secs(1))).await.unwrap();
+        store.insert_session(Uuid::new_v4(), Session { id: Some(Uuid::new_v4()), ..challenge(now - Duration::from_secs(1)) }).await.unwrap();
+        store.insert_session(Uuid::new_v4(), Session {
+            id: Some(Uuid::new_v4()),
+            last_seen: idle_since - Duration::from_secs(1),
+            ..challenge(now + Duration::from_secs(60))
+        }).await.unwrap();
+        let auth_id = Uuid::new_v4();
+        store.insert_session(auth_id, Session { last_seen: idle_since - Duration::from_secs(1), ..challenge(now + Duration::from_secs(60)) }).await.unwrap();
+        let session_id = Uuid::new_v4();
+        store.insert_session(session_id, Session { id: Some(session_id), ..challenge(now + Duration::from_secs(60)) }).await.unwrap();
+        assert_eq!(store.reap(now, idle_since).await.unwrap(), 3);
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_some());
+        assert!(store.get_session(&session_id).await.unwrap().is_some());
+    }
+}
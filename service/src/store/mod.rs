@@ -0,0 +1,168 @@
+//! Storage of registered users and of the sessions created by authentication challenges, behind
+//! async traits so that the backend can be chosen when the service starts.
+
+use std::time::SystemTime;
+
+use eyre::Result;
+use k256::AffinePoint;
+use num_bigint::BigUint;
+use tonic::async_trait;
+use uuid::Uuid;
+
+use crate::pb2;
+
+mod memory;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Credentials {
+    Exp((BigUint, BigUint)),
+    K256((AffinePoint, AffinePoint)),
+    /// Canonically encoded elements of the user's ciphersuite.
+    Encoded(Vec<Vec<u8>>),
+}
+
+
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: Option<Uuid>,
+    pub user: String,
+    pub r: Credentials,
+    /// Challenge in the encoding it was sent to the client in.
+    pub c: Vec<u8>,
+    pub composition: Option<Composition>,
+    /// When the challenge, or once answered the session, stops being valid.
+    pub expires: SystemTime,
+    /// Last time the session was used, for the idle timeout.
+    pub last_seen: SystemTime,
+    /// When the challenge was created, or once answered the session started, for the maximum
+    /// session age.
+    pub started: SystemTime,
+}
+
+
+/// Further branches of a composed proof, the session's own `user` and `r` being the first one.
+#[derive(Clone, Debug)]
+pub struct Composition {
+    pub kind: pb2::Composition,
+    pub branches: Vec<(String, Credentials)>,
+}
+
+
+#[derive(Clone, Debug)]
+pub struct User {
+    pub name: String,
+    pub realm: String,
+    pub ciphersuite: String,
+    pub factors: Vec<pb2::Factor>,
+    pub y: Credentials,
+    /// When the user registered, none if it registered before that was recorded.
+    pub registered: Option<SystemTime>,
+}
+
+
+/// Failed verifications of a user since its last successful one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failures {
+    pub count: u32,
+    pub last: SystemTime,
+}
+
+
+/// Events kept in the audit log of a user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Login,
+    FailedVerification,
+    Logout,
+    PasswordChange,
+    Unlock,
+    /// Someone registered the name again while enumeration is protected.
+    RegistrationAttempt,
+}
+
+impl Event {
+    const ALL: [Event; 6] = [Event::Login, Event::FailedVerification, Event::Logout, Event::PasswordChange, Event::Unlock, Event::RegistrationAttempt];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Login => "login",
+            Event::FailedVerification => "failed_verification",
+            Event::Logout => "logout",
+            Event::PasswordChange => "password_change",
+            Event::Unlock => "unlock",
+            Event::RegistrationAttempt => "registration_attempt",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.name() == name)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditEntry {
+    pub time: SystemTime,
+    pub event: Event,
+    /// Session the event is about, if any.
+    pub session_id: Option<Uuid>,
+}
+
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Stores `user` unless a user with the same name exists, returning whether it was stored.
+    async fn insert_user(&self, user: User) -> Result<bool>;
+    async fn get_user(&self, name: &str) -> Result<Option<User>>;
+    async fn get_failures(&self, name: &str) -> Result<Option<Failures>>;
+    /// Counts a failed verification of `name` at `now`, returning the updated failures.
+    async fn record_failure(&self, name: &str, now: SystemTime) -> Result<Failures>;
+    /// Forgets the failures of `name`, returning whether there were any.
+    async fn reset_failures(&self, name: &str) -> Result<bool>;
+    /// Replaces the credentials of `name` by `new` if they still are `old`, returning whether they
+    /// were replaced.
+    async fn replace_credentials(&self, name: &str, old: &Credentials, new: Credentials) -> Result<bool>;
+    /// Removes `name` with its failures, its audit log and every challenge and session it takes
+    /// part in, returning whether it existed. A deletion is recorded at `now` apart from the user.
+    async fn remove_user(&self, name: &str, now: SystemTime) -> Result<bool>;
+    /// Appends `entry` to the audit log of `name`, unless there is no such user.
+    async fn record_event(&self, name: &str, entry: AuditEntry) -> Result<()>;
+    /// Audit log of `name`, oldest first.
+    async fn get_events(&self, name: &str) -> Result<Vec<AuditEntry>>;
+    /// Records `fingerprint` as the parameters users of `ciphersuite` are registered under, unless
+    /// others are recorded already, returning whether the recorded ones are `fingerprint`.
+    async fn pin_parameters(&self, ciphersuite: &str, fingerprint: &[u8]) -> Result<bool>;
+}
+
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    /// Stores `session` under `key`, the auth id of a challenge or the id of an answered session.
+    async fn insert_session(&self, key: Uuid, session: Session) -> Result<()>;
+    /// Removes the unanswered challenge `auth_id` and returns it unless it has expired, so that
+    /// every challenge can be answered at most once.
+    async fn take_challenge(&self, auth_id: &Uuid) -> Result<Option<Session>>;
+    async fn get_session(&self, session_id: &Uuid) -> Result<Option<Session>>;
+    /// Updates when the session was last used and when it expires, returning false if it does
+    /// not exist.
+    async fn touch_session(&self, session_id: &Uuid, last_seen: SystemTime, expires: SystemTime) -> Result<bool>;
+    async fn remove_session(&self, session_id: &Uuid) -> Result<bool>;
+    /// Issues `nonce` to the answered session `session_id`, replacing any earlier one, returning
+    /// false if the session does not exist.
+    async fn set_nonce(&self, session_id: &Uuid, nonce: Vec<u8>) -> Result<bool>;
+    /// Removes the nonce of the answered session `session_id` and returns it, so that every nonce
+    /// can be used at most once.
+    async fn take_nonce(&self, session_id: &Uuid) -> Result<Option<Vec<u8>>>;
+    /// Answered sessions of `user`, including expired ones not reaped yet.
+    async fn user_sessions(&self, user: &str) -> Result<Vec<Session>>;
+    /// Removes the sessions of `user` but `except`, returning how many.
+    async fn remove_user_sessions(&self, user: &str, except: Option<&Uuid>) -> Result<u64>;
+    /// Removes challenges and sessions that expired before `now` and sessions last used before
+    /// `idle_since`, returning how many.
+    async fn reap(&self, now: SystemTime, idle_since: SystemTime) -> Result<u64>;
+}
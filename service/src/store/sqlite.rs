@@ -0,0 +1,698 @@
+use std::{path::Path, str::FromStr, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use eyre::{eyre, Result};
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use num_bigint::BigUint;
+use protocol::{Group, K256Group};
+use sqlx::{sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow}, Row, SqlitePool};
+use tonic::async_trait;
+use uuid::Uuid;
+
+use super::{AuditEntry, Composition, Credentials, Event, Failures, Session, SessionStore, User, UserStore};
+use crate::pb2;
+
+
+/// Users and sessions in a SQLite database, migrated to the latest schema when opened.
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    pub async fn open(path: &Path) -> Result<Self> {
+        let options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+        Self::connect(options, 4).await
+    }
+
+    async fn connect(options: SqliteConnectOptions, connections: u32) -> Result<Self> {
+        let pool = SqlitePoolOptions::new().max_connections(connections).connect_with(options).await?;
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Self { pool })
+    }
+}
+
+
+/// Length prefixed concatenation of the byte encodings of the elements.
+fn pack(elements: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for element in elements {
+        out.extend((element.len() as u32).to_be_bytes());
+        out.extend(element);
+    }
+    out
+}
+
+
+fn unpack(mut bytes: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut elements = Vec::new();
+    while !bytes.is_empty() {
+        let (len, rest) = bytes.split_first_chunk::<4>()?;
+        let len = u32::from_be_bytes(*len) as usize;
+        if rest.len() < len {
+            return None;
+        }
+        elements.push(rest[..len].to_vec());
+        bytes = &rest[len..];
+    }
+    Some(elements)
+}
+
+
+fn encode(credentials: &Credentials) -> (&'static str, Vec<u8>) {
+    match credentials {
+        Credentials::Exp((a, b)) => ("exp", pack(&[a.to_bytes_be(), b.to_bytes_be()])),
+        Credentials::K256((a, b)) => ("k256", pack(&[
+            a.to_encoded_point(true).as_bytes().to_vec(),
+            b.to_encoded_point(true).as_bytes().to_vec(),
+        ])),
+        Credentials::Encoded(elements) => ("encoded", pack(elements)),
+    }
+}
+
+
+fn to_unix(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64)
+}
+
+
+fn from_unix(seconds: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+
+fn decode(kind: &str, bytes: &[u8]) -> Result<Credentials> {
+    let elements = unpack(bytes).ok_or_else(|| eyre!("credentials are truncated"))?;
+    let credentials = match (kind, elements.as_slice()) {
+        ("exp", [a, b]) => Credentials::Exp((BigUint::from_bytes_be(a), BigUint::from_bytes_be(b))),
+        ("k256", [a, b]) => {
+            let (Some(a), Some(b)) = (K256Group.decode_element(a), K256Group.decode_element(b)) else {
+                return Err(eyre!("credentials are not valid k256 points"));
+            };
+            Credentials::K256((a, b))
+        }
+        ("encoded", _) => Credentials::Encoded(elements),
+        _ => return Err(eyre!("unknown credentials kind {}", kind)),
+    };
+    Ok(credentials)
+}
+
+
+/// Session of a `sessions` row together with its `session_branches` rows in order.
+fn session(row: &SqliteRow, branches: &[SqliteRow]) -> Result<Session> {
+    let composition = match row.try_get::<Option<i32>, _>("composition")? {
+        Some(kind) => Some(Composition {
+            kind: pb2::Composition::try_from(kind).map_err(|_| eyre!("unknown composition {}", kind))?,
+            branches: branches.iter()
+                .map(|row| Ok((row.try_get("user")?, decode(row.try_get("kind")?, row.try_get("r")?)?)))
+                .collect::<Result<_>>()?,
+        }),
+        None => None,
+    };
+    Ok(Session {
+        id: row.try_get::<Option<&str>, _>("session_id")?.map(Uuid::parse_str).transpose()?,
+        user: row.try_get("user")?,
+        r: decode(row.try_get("kind")?, row.try_get("r")?)?,
+        c: row.try_get("c")?,
+        composition,
+        expires: from_unix(row.try_get("expires")?),
+        last_seen: from_unix(row.try_get("last_seen")?),
+        started: from_unix(row.try_get("started")?),
+    })
+}
+
+
+#[async_trait]
+impl UserStore for SqliteStore {
+    async fn insert_user(&self, user: User) -> Result<bool> {
+        let (kind, y) = encode(&user.y);
+        let factors = user.factors.iter().map(|factor| (*factor as i32).to_string()).collect::<Vec<_>>().join(",");
+        let result = sqlx::query("INSERT INTO users (name, realm, ciphersuite, factors, kind, y, registered) VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT (name) DO NOTHING")
+            .bind(&user.name)
+            .bind(&user.realm)
+            .bind(&user.ciphersuite)
+            .bind(factors)
+            .bind(kind)
+            .bind(y)
+            .bind(user.registered.map(to_unix))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_user(&self, name: &str) -> Result<Option<User>> {
+        let Some(row) = sqlx::query("SELECT name, realm, ciphersuite, factors, kind, y, registered FROM users WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await? else {
+            return Ok(None);
+        };
+        let factors = row.try_get::<&str, _>("factors")?.split(',')
+            .map(|factor| i32::from_str(factor).ok().and_then(|factor| pb2::Factor::try_from(factor).ok()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| eyre!("unknown factor of user {}", name))?;
+        Ok(Some(User {
+            name: row.try_get("name")?,
+            realm: row.try_get("realm")?,
+            ciphersuite: row.try_get("ciphersuite")?,
+            factors,
+            y: decode(row.try_get("kind")?, row.try_get("y")?)?,
+            registered: row.try_get::<Option<i64>, _>("registered")?.map(from_unix),
+        }))
+    }
+
+    async fn get_failures(&self, name: &str) -> Result<Option<Failures>> {
+        sqlx::query("SELECT count, last FROM failures WHERE user = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?
+            .map(|row| Ok(Failures { count: row.try_get("count")?, last: from_unix(row.try_get("last")?) }))
+            .transpose()
+    }
+
+    async fn record_failure(&self, name: &str, now: SystemTime) -> Result<Failures> {
+        let row = sqlx::query("INSERT INTO failures (user, count, last) VALUES (?, 1, ?) ON CONFLICT (user) DO UPDATE SET count = count + 1, last = excluded.last RETURNING count, last")
+            .bind(name)
+            .bind(to_unix(now))
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Failures { count: row.try_get("count")?, last: from_unix(row.try_get("last")?) })
+    }
+
+    async fn reset_failures(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM failures WHERE user = ?")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn replace_credentials(&self, name: &str, old: &Credentials, new: Credentials) -> Result<bool> {
+        let (old_kind, old_y) = encode(old);
+        let (kind, y) = encode(&new);
+        let result = sqlx::query("UPDATE users SET kind = ?, y = ? WHERE name = ? AND kind = ? AND y = ?")
+            .bind(kind)
+            .bind(y)
+            .bind(name)
+            .bind(old_kind)
+            .bind(old_y)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remove_user(&self, name: &str, now: SystemTime) -> Result<bool> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM sessions WHERE user = ? OR auth_id IN (SELECT auth_id FROM session_branches WHERE user = ?)")
+            .bind(name)
+            .bind(name)
+            .execute(&mut *transaction)
+            .await?;
+        let result = sqlx::query("DELETE FROM users WHERE name = ?")
+            .bind(name)
+            .execute(&mut *transaction)
+            .await?;
+        let removed = result.rows_affected() == 1;
+        if removed {
+            sqlx::query("INSERT INTO deletions (user, time) VALUES (?, ?)")
+                .bind(name)
+                .bind(to_unix(now))
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(removed)
+    }
+
+    async fn record_event(&self, name: &str, entry: AuditEntry) -> Result<()> {
+        sqlx::query("INSERT INTO audit (user, time, event, session_id) SELECT name, ?, ?, ? FROM users WHERE name = ?")
+            .bind(to_unix(entry.time))
+            .bind(entry.event.name())
+            .bind(entry.session_id.map(|id| id.to_string()))
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_events(&self, name: &str) -> Result<Vec<AuditEntry>> {
+        sqlx::query("SELECT time, event, session_id FROM audit WHERE user = ? ORDER BY id")
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                let event = row.try_get("event")?;
+                Ok(AuditEntry {
+                    time: from_unix(row.try_get("time")?),
+                    event: Event::from_name(event).ok_or_else(|| eyre!("unknown event {}", event))?,
+                    session_id: row.try_get::<Option<&str>, _>("session_id")?.map(Uuid::parse_str).transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    async fn pin_parameters(&self, ciphersuite: &str, fingerprint: &[u8]) -> Result<bool> {
+        sqlx::query("INSERT INTO parameters (ciphersuite, fingerprint) VALUES (?, ?) ON CONFLICT (ciphersuite) DO NOTHING")
+            .bind(ciphersuite)
+            .bind(fingerprint)
+            .execute(&self.pool)
+            .await?;
+        let pinned: Vec<u8> = sqlx::query_scalar("SELECT fingerprint FROM parameters WHERE ciphersuite = ?")
+            .bind(ciphersuite)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(pinned == fingerprint)
+    }
+}
+
+
+#[async_trait]
+impl SessionStore for SqliteStore {
+    async fn insert_session(&self, key: Uuid, session: Session) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM sessions WHERE auth_id = ?")
+            .bind(key.to_string())
+            .execute(&mut *transaction)
+            .await?;
+        let (kind, r) = encode(&session.r);
+        sqlx::query("INSERT INTO sessions (auth_id, session_id, user, kind, r, c, composition, expires, last_seen, started) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(key.to_string())
+            .bind(session.id.map(|id| id.to_string()))
+            .bind(&session.user)
+            .bind(kind)
+            .bind(r)
+            .bind(&session.c)
+            .bind(session.composition.as_ref().map(|composition| composition.kind as i32))
+            .bind(to_unix(session.expires))
+            .bind(to_unix(session.last_seen))
+            .bind(to_unix(session.started))
+            .execute(&mut *transaction)
+            .await?;
+        for (position, (user, r)) in session.composition.iter().flat_map(|composition| &composition.branches).enumerate() {
+            let (kind, r) = encode(r);
+            sqlx::query("INSERT INTO session_branches (auth_id, position, user, kind, r) VALUES (?, ?, ?, ?, ?)")
+                .bind(key.to_string())
+                .bind(position as i64)
+                .bind(user)
+                .bind(kind)
+                .bind(r)
+                .execute(&mut *transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn take_challenge(&self, auth_id: &Uuid) -> Result<Option<Session>> {
+        // Writing first takes the database lock for the whole transaction, the branches being
+        // restored by the rollback when there is no challenge to take.
+        let mut transaction = self.pool.begin().await?;
+        let mut branches = sqlx::query("DELETE FROM session_branches WHERE auth_id = ? RETURNING *")
+            .bind(auth_id.to_string())
+            .fetch_all(&mut *transaction)
+            .await?;
+        let Some(row) = sqlx::query("DELETE FROM sessions WHERE auth_id = ? AND session_id IS NULL RETURNING *")
+            .bind(auth_id.to_string())
+            .fetch_optional(&mut *transaction)
+            .await? else {
+            return Ok(None);
+        };
+        transaction.commit().await?;
+        branches.sort_by_key(|row| row.try_get::<i64, _>("position").unwrap_or_default());
+        Ok(Some(session(&row, &branches)?).filter(|session| session.expires > SystemTime::now()))
+    }
+
+    async fn get_session(&self, session_id: &Uuid) -> Result<Option<Session>> {
+        let mut transaction = self.pool.begin().await?;
+        let Some(row) = sqlx::query("SELECT * FROM sessions WHERE session_id = ?")
+            .bind(session_id.to_string())
+            .fetch_optional(&mut *transaction)
+            .await? else {
+            return Ok(None);
+        };
+        let branches = sqlx::query("SELECT * FROM session_branches WHERE auth_id = ? ORDER BY position")
+            .bind(row.try_get::<&str, _>("auth_id")?)
+            .fetch_all(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(Some(session(&row, &branches)?))
+    }
+
+    async fn touch_session(&self, session_id: &Uuid, last_seen: SystemTime, expires: SystemTime) -> Result<bool> {
+        let result = sqlx::query("UPDATE sessions SET last_seen = ?, expires = ? WHERE session_id = ?")
+            .bind(to_unix(last_seen))
+            .bind(to_unix(expires))
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn remove_session(&self, session_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM sessions WHERE session_id = ?")
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn set_nonce(&self, session_id: &Uuid, nonce: Vec<u8>) -> Result<bool> {
+        let result = sqlx::query("INSERT INTO session_nonces (session_id, nonce) SELECT session_id, ? FROM sessions WHERE session_id = ? ON CONFLICT (session_id) DO UPDATE SET nonce = excluded.nonce")
+            .bind(nonce)
+            .bind(session_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn take_nonce(&self, session_id: &Uuid) -> Result<Option<Vec<u8>>> {
+        Ok(sqlx::query_scalar("DELETE FROM session_nonces WHERE session_id = ? RETURNING nonce")
+            .bind(session_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn user_sessions(&self, user: &str) -> Result<Vec<Session>> {
+        let mut transaction = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT * FROM sessions WHERE user = ? AND session_id IS NOT NULL ORDER BY last_seen")
+            .bind(user)
+            .fetch_all(&mut *transaction)
+            .await?;
+        let mut sessions = Vec::with_capacity(rows.len());
+        for row in &rows {
+            let branches = sqlx::query("SELECT * FROM session_branches WHERE auth_id = ? ORDER BY position")
+                .bind(row.try_get::<&str, _>("auth_id")?)
+                .fetch_all(&mut *transaction)
+                .await?;
+            sessions.push(session(row, &branches)?);
+        }
+        transaction.commit().await?;
+        Ok(sessions)
+    }
+
+    async fn remove_user_sessions(&self, user: &str, except: Option<&Uuid>) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user = ? AND session_id IS NOT NULL AND session_id IS NOT ?")
+            .bind(user)
+            .bind(except.map(|id| id.to_string()))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn reap(&self, now: SystemTime, idle_since: SystemTime) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM sessions WHERE expires <= ? OR (session_id IS NOT NULL AND last_seen <= ?)")
+            .bind(to_unix(now))
+            .bind(to_unix(idle_since))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use k256::{AffinePoint, ProjectivePoint, Scalar};
+
+    use super::*;
+
+    async fn setup_store() -> SqliteStore {
+        SqliteStore::connect(SqliteConnectOptions::from_str("sqlite::memory:").unwrap(), 1).await.unwrap()
+    }
+
+    fn user(name: &str, y: Credentials) -> User {
+        User {
+            name: name.to_string(),
+            realm: "chaum-pedersen-auth".to_string(),
+            ciphersuite: "CP-K256-SHA256-SHA256".to_string(),
+            factors: vec![pb2::Factor::Password, pb2::Factor::Device],
+            y,
+            registered: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        }
+    }
+
+    fn point(x: u64) -> AffinePoint {
+        (ProjectivePoint::GENERATOR * Scalar::from(x)).to_affine()
+    }
+
+    #[test]
+    fn test_pack() {
+        let elements = vec![vec![1, 2], vec![], vec![3]];
+        assert_eq!(unpack(&pack(&elements)), Some(elements));
+        assert_eq!(unpack(&[0, 0, 0, 2, 1]), None);
+    }
+
+    #[tokio::test]
+    async fn test_users() {
+        let store = setup_store().await;
+        assert!(store.insert_user(user("alice", Credentials::K256((point(2), point(3))))).await.unwrap());
+        assert!(!store.insert_user(user("alice", Credentials::Encoded(vec![vec![1]]))).await.unwrap());
+        assert!(store.insert_user(user("bob", Credentials::Exp((BigUint::from(2u8), BigUint::from(300u16))))).await.unwrap());
+        let alice = store.get_user("alice").await.unwrap().unwrap();
+        assert_eq!(alice.factors, [pb2::Factor::Password, pb2::Factor::Device]);
+        assert_eq!(alice.registered, Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)));
+        assert!(matches!(alice.y, Credentials::K256((a, b)) if a == point(2) && b == point(3)));
+        let bob = store.get_user("bob").await.unwrap().unwrap();
+        assert!(matches!(bob.y, Credentials::Exp((a, b)) if a == BigUint::from(2u8) && b == BigUint::from(300u16)));
+        assert!(store.get_user("carol").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replace_credentials() {
+        let store = setup_store().await;
+        let old = Credentials::K256((point(2), point(3)));
+        let new = Credentials::Encoded(vec![vec![4], vec![5]]);
+        assert!(!store.replace_credentials("alice", &old, new.clone()).await.unwrap());
+        store.insert_user(user("alice", old.clone())).await.unwrap();
+        assert!(store.replace_credentials("alice", &old, new.clone()).await.unwrap());
+        assert!(!store.replace_credentials("alice", &old, old.clone()).await.unwrap());
+        let alice = store.get_user("alice").await.unwrap().unwrap();
+        assert_eq!((alice.y, alice.factors), (new, vec![pb2::Factor::Password, pb2::Factor::Device]));
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let now = from_unix(to_unix(SystemTime::now()));
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert_eq!(store.record_failure("alice", now).await.unwrap().count, 1);
+        let later = now + Duration::from_secs(1);
+        assert_eq!(store.record_failure("alice", later).await.unwrap(), Failures { count: 2, last: later });
+        assert_eq!(store.record_failure("bob", now).await.unwrap().count, 1);
+        assert_eq!(store.get_failures("alice").await.unwrap(), Some(Failures { count: 2, last: later }));
+        assert!(store.reset_failures("alice").await.unwrap());
+        assert!(!store.reset_failures("alice").await.unwrap());
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert_eq!(store.get_failures("bob").await.unwrap().unwrap().count, 1);
+    }
+
+    fn challenge(expires: SystemTime) -> Session {
+        Session {
+            id: None,
+            user: "alice".to_string(),
+            r: Credentials::Encoded(vec![vec![3], vec![4]]),
+            c: vec![5],
+            composition: Some(Composition {
+                kind: pb2::Composition::Or,
+                branches: vec![
+                    ("bob".to_string(), Credentials::K256((point(6), point(7)))),
+                    ("alice".to_string(), Credentials::Exp((BigUint::from(8u8), BigUint::from(9u8)))),
+                ],
+            }),
+            expires,
+            last_seen: SystemTime::now(),
+            started: SystemTime::now(),
+        }
+    }
+
+    async fn setup_users(store: &SqliteStore) {
+        for name in ["alice", "bob"] {
+            store.insert_user(user(name, Credentials::Encoded(vec![vec![1], vec![2]]))).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let store = setup_store().await;
+        let time = from_unix(1_700_000_000);
+        store.record_event("alice", AuditEntry { time, event: Event::Unlock, session_id: None }).await.unwrap();
+        setup_users(&store).await;
+        assert!(store.get_events("alice").await.unwrap().is_empty());
+        let entries = [
+            AuditEntry { time, event: Event::Login, session_id: Some(Uuid::new_v4()) },
+            AuditEntry { time, event: Event::FailedVerification, session_id: None },
+        ];
+        for entry in &entries {
+            store.record_event("alice", entry.clone()).await.unwrap();
+        }
+        assert_eq!(store.get_events("alice").await.unwrap(), entries);
+        assert!(store.get_events("bob").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remove_user() {
+        let store = setup_store().await;
+        assert!(!store.remove_user("alice", SystemTime::now()).await.unwrap());
+        setup_users(&store).await;
+        store.record_failure("alice", SystemTime::now()).await.unwrap();
+        store.record_event("alice", AuditEntry { time: SystemTime::now(), event: Event::Login, session_id: None }).await.unwrap();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let (session_id, composed, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        store.insert_session(session_id, Session { id: Some(session_id), composition: None, ..challenge(expires) }).await.unwrap();
+        store.insert_session(composed, Session { user: "bob".to_string(), ..challenge(expires) }).await.unwrap();
+        store.insert_session(other, Session { id: Some(other), user: "bob".to_string(), composition: None, ..challenge(expires) }).await.unwrap();
+        assert_eq!(store.user_sessions("alice").await.unwrap().len(), 1);
+
+        let now = SystemTime::now();
+        assert!(store.remove_user("alice", now).await.unwrap());
+        let deletions: Vec<(String, i64)> = sqlx::query_as("SELECT user, time FROM deletions").fetch_all(&store.pool).await.unwrap();
+        assert_eq!(deletions, [("alice".to_string(), to_unix(now))]);
+        assert!(store.get_user("alice").await.unwrap().is_none());
+        assert!(store.get_failures("alice").await.unwrap().is_none());
+        assert!(store.get_events("alice").await.unwrap().is_empty());
+        assert!(store.user_sessions("alice").await.unwrap().is_empty());
+        assert!(store.take_challenge(&composed).await.unwrap().is_none());
+        assert!(store.get_session(&other).await.unwrap().is_some());
+        let branches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_branches").fetch_one(&store.pool).await.unwrap();
+        assert_eq!(branches, 0);
+    }
+
+    #[tokio::test]
+    async fn test_take_challenge() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let auth_id = Uuid::new_v4();
+        store.insert_session(auth_id, challenge(SystemTime::now() + Duration::from_secs(60))).await.unwrap();
+        let session = store.take_challenge(&auth_id).await.unwrap().unwrap();
+        assert_eq!((session.id, session.user.as_str(), session.c.as_slice()), (None, "alice", [5].as_slice()));
+        let composition = session.composition.unwrap();
+        assert_eq!(composition.kind, pb2::Composition::Or);
+        assert!(matches!(composition.branches.as_slice(), [(bob, Credentials::K256(_)), (alice, Credentials::Exp(_))] if bob == "bob" && alice == "alice"));
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+
+        store.insert_session(auth_id, challenge(SystemTime::now() - Duration::from_secs(1))).await.unwrap();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_answered_challenge_is_kept() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let auth_id = Uuid::new_v4();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        store.insert_session(auth_id, Session { id: Some(Uuid::new_v4()), ..challenge(expires) }).await.unwrap();
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_none());
+        let branches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_branches").fetch_one(&store.pool).await.unwrap();
+        assert_eq!(branches, 2);
+    }
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let auth_id = Uuid::new_v4();
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        let session = store.take_challenge(&auth_id).await.unwrap().unwrap();
+        let session_id = Uuid::new_v4();
+        store.insert_session(session_id, Session { id: Some(session_id), ..session }).await.unwrap();
+
+        let later = expires + Duration::from_secs(60);
+        assert!(store.touch_session(&session_id, expires, later).await.unwrap());
+        let session = store.get_session(&session_id).await.unwrap().unwrap();
+        assert_eq!((session.id, to_unix(session.last_seen), to_unix(session.expires)), (Some(session_id), to_unix(expires), to_unix(later)));
+        assert_eq!(session.composition.unwrap().branches.len(), 2);
+        assert!(store.get_session(&auth_id).await.unwrap().is_none());
+
+        assert!(store.remove_session(&session_id).await.unwrap());
+        assert!(!store.remove_session(&session_id).await.unwrap());
+        assert!(!store.touch_session(&session_id, expires, later).await.unwrap());
+        let branches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_branches").fetch_one(&store.pool).await.unwrap();
+        assert_eq!(branches, 0);
+    }
+
+    #[tokio::test]
+    async fn test_remove_user_sessions() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let auth_id = Uuid::new_v4();
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        let (kept, removed, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for session_id in [kept, removed] {
+            store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        }
+        store.insert_session(other, Session { id: Some(other), user: "bob".to_string(), ..challenge(expires) }).await.unwrap();
+        assert_eq!(store.remove_user_sessions("alice", Some(&kept)).await.unwrap(), 1);
+        assert!(store.get_session(&kept).await.unwrap().is_some());
+        assert!(store.get_session(&removed).await.unwrap().is_none());
+        assert!(store.get_session(&other).await.unwrap().is_some());
+        assert_eq!(store.remove_user_sessions("alice", None).await.unwrap(), 1);
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_some());
+        let branches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_branches").fetch_one(&store.pool).await.unwrap();
+        assert_eq!(branches, 2);
+    }
+
+    #[tokio::test]
+    async fn test_reap() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let now = SystemTime::now();
+        let idle_since = now - Duration::from_secs(30);
+        store.insert_session(Uuid::new_v4(), challenge(now - Duration::from_secs(1))).await.unwrap();
+        store.insert_session(Uuid::new_v4(), Session { id: Some(Uuid::new_v4()), ..challenge(now - Duration::from_secs(1)) }).await.unwrap();
+        store.insert_session(Uuid::new_v4(), Session {
+            id: Some(Uuid::new_v4()),
+            last_seen: idle_since - Duration::from_secs(1),
+            ..challenge(now + Duration::from_secs(60))
+        }).await.unwrap();
+        let auth_id = Uuid::new_v4();
+        store.insert_session(auth_id, Session { last_seen: idle_since - Duration::from_secs(1), ..challenge(now + Duration::from_secs(60)) }).await.unwrap();
+        assert_eq!(store.reap(now, idle_since).await.unwrap(), 3);
+        let branches: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_branches").fetch_one(&store.pool).await.unwrap();
+        assert_eq!(branches, 2);
+        assert!(store.take_challenge(&auth_id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_nonce() {
+        let store = setup_store().await;
+        setup_users(&store).await;
+        let expires = SystemTime::now() + Duration::from_secs(60);
+        let (auth_id, session_id) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert_session(auth_id, challenge(expires)).await.unwrap();
+        store.insert_session(session_id, Session { id: Some(session_id), ..challenge(expires) }).await.unwrap();
+        assert!(!store.set_nonce(&auth_id, vec![1]).await.unwrap());
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(store.set_nonce(&session_id, vec![1]).await.unwrap());
+        assert!(store.set_nonce(&session_id, vec![2]).await.unwrap());
+        assert_eq!(store.take_nonce(&session_id).await.unwrap(), Some(vec![2]));
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(store.set_nonce(&session_id, vec![3]).await.unwrap());
+        assert!(store.remove_session(&session_id).await.unwrap());
+        assert!(store.take_nonce(&session_id).await.unwrap().is_none());
+        assert!(!store.set_nonce(&session_id, vec![4]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_pin_parameters() {
+        let store = setup_store().await;
+        assert!(store.pin_parameters("CP-K256-SHA256-SHA256", &[1]).await.unwrap());
+        assert!(store.pin_parameters("CP-K256-SHA256-SHA256", &[1]).await.unwrap());
+        assert!(!store.pin_parameters("CP-K256-SHA256-SHA256", &[2]).await.unwrap());
+        assert!(store.pin_parameters("CP-EXP-SHA256-SHA256", &[2]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_reopen() {
+        let path = std::env::temp_dir().join(format!("chaum-pedersen-auth-{}.db", Uuid::new_v4()));
+        let store = SqliteStore::open(&path).await.unwrap();
+        store.insert_user(user("alice", Credentials::Encoded(vec![vec![1], vec![2]]))).await.unwrap();
+        store.pool.close().await;
+        let store = SqliteStore::open(&path).await.unwrap();
+        assert!(matches!(store.get_user("alice").await.unwrap().unwrap().y, Credentials::Encoded(y) if y == [vec![1], vec![2]]));
+        store.pool.close().await;
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+//! TLS for the gRPC listener with rustls. Certificates are read from PEM files and re-read by
+//! `reload`, which only affects handshakes from then on, so open connections are kept.
+
+use std::{fs::File, io::BufReader, path::Path, sync::{Arc, RwLock}, time::Duration};
+
+use eyre::{eyre, Result, WrapErr};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{rustls::{server::AllowAnyAnonymousOrAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig}, server::TlsStream, TlsAcceptor};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::config;
+
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+pub struct Tls {
+    config: config::Tls,
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+
+fn read_pem(path: &Path) -> Result<Vec<rustls_pemfile::Item>> {
+    let file = File::open(path).wrap_err_with(|| format!("cannot read {}", path.display()))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file)).wrap_err_with(|| format!("invalid PEM file {}", path.display()))
+}
+
+
+fn certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let certificates: Vec<_> = read_pem(path)?.into_iter()
+        .filter_map(|item| match item {
+            rustls_pemfile::Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if certificates.is_empty() {
+        return Err(eyre!("no certificate in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+
+fn private_key(path: &Path) -> Result<PrivateKey> {
+    read_pem(path)?.into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(der) | rustls_pemfile::Item::RSAKey(der) | rustls_pemfile::Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| eyre!("no private key in {}", path.display()))
+}
+
+
+fn server_config(config: &config::Tls) -> Result<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca {
+        Some(path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in certificates(path)? {
+                roots.add(&certificate).wrap_err_with(|| format!("invalid client CA in {}", path.display()))?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder
+        .with_single_cert(certificates(&config.cert)?, private_key(&config.key)?)
+        .wrap_err("certificate does not match its key")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server_config))
+}
+
+
+impl Tls {
+    pub fn load(config: &config::Tls) -> Result<Self> {
+        Ok(Self { config: config.clone(), current: RwLock::new(server_config(config)?) })
+    }
+
+    /// Re-reads the certificate, key and client CA, keeping the previous ones if they are invalid.
+    pub fn reload(&self) -> Result<()> {
+        let server_config = server_config(&self.config)?;
+        *self.current.write().unwrap() = server_config;
+        Ok(())
+    }
+
+    fn acceptor(&self) -> TlsAcceptor {
+        TlsAcceptor::from(self.current.read().unwrap().clone())
+    }
+
+    /// Connections accepted on `listener` that completed a handshake, for `serve_with_incoming`.
+    pub fn incoming(self: Arc<Self>, listener: TcpListener) -> ReceiverStream<std::io::Result<TlsStream<TcpStream>>> {
+        let (sender, receiver) = tokio::sync::mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(error) => {
+                        log::warn!("cannot accept connection: {}", error);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                if sender.is_closed() {
+                    return;
+                }
+                let acceptor = self.acceptor();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            sender.send(Ok(stream)).await.ok();
+                        }
+                        Ok(Err(error)) => log::debug!("TLS handshake with {} failed: {}", peer, error),
+                        Err(_) => log::debug!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+        ReceiverStream::new(receiver)
+    }
+}
+
+
+/// Reloads the certificates whenever the process receives SIGHUP.
+pub async fn reload_on_hangup(tls: Arc<Tls>) -> Result<()> {
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        match tls.reload() {
+            Ok(()) => log::info!("reloaded TLS certificates"),
+            Err(error) => log::error!("cannot reload TLS certificates, keeping the previous ones: {:?}", error),
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use num_bigint::BigUint;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa};
+    use tonic::{transport::{Channel, ClientTlsConfig, Identity, Server}, Code};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{pb2, store::MemoryStore, token::Keys, API};
+
+    const REALM: &str = "chaum-pedersen-auth";
+
+    fn ca() -> rcgen::Certificate {
+        let mut params = CertificateParams::new(Vec::new());
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        rcgen::Certificate::from_params(params).unwrap()
+    }
+
+    /// Writes a certificate for `name` signed by `ca` and its key, returning their paths.
+    fn issue(dir: &Path, name: &str, ca: &rcgen::Certificate) -> (PathBuf, PathBuf) {
+        let certificate = rcgen::Certificate::from_params(CertificateParams::new(vec![name.to_string()])).unwrap();
+        let (cert, key) = (dir.join(format!("{}.pem", name)), dir.join(format!("{}.key", name)));
+        std::fs::write(&cert, certificate.serialize_pem_with_signer(ca).unwrap()).unwrap();
+        std::fs::write(&key, certificate.serialize_private_key_pem()).unwrap();
+        (cert, key)
+    }
+
+    async fn connect(port: u16, ca: &rcgen::Certificate, identity: Option<(&Path, &Path)>) -> Result<pb2::auth_client::AuthClient<Channel>> {
+        let mut tls = ClientTlsConfig::new()
+            .ca_certificate(tonic::transport::Certificate::from_pem(ca.serialize_pem()?))
+            .domain_name("localhost");
+        if let Some((cert, key)) = identity {
+            tls = tls.identity(Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?));
+        }
+        let channel = Channel::from_shared(format!("https://127.0.0.1:{}", port))?.tls_config(tls)?.connect().await?;
+        Ok(pb2::auth_client::AuthClient::new(channel))
+    }
+
+    /// Service with a client CA in `dir`, returning its port, the TLS config, the server CA and
+    /// the paths of its key and of a client certificate and key.
+    async fn serve(dir: &Path) -> (u16, Arc<Tls>, rcgen::Certificate, PathBuf, (PathBuf, PathBuf)) {
+        std::fs::create_dir(dir).unwrap();
+        let (server_ca, client_ca) = (ca(), ca());
+        let (cert, key) = issue(dir, "localhost", &server_ca);
+        let client = issue(dir, "client", &client_ca);
+        let client_ca_path = dir.join("client-ca.pem");
+        std::fs::write(&client_ca_path, client_ca.serialize_pem().unwrap()).unwrap();
+
+        let mut config = config::Config::parse(include_str!("../config.example.toml")).unwrap();
+        config.tls = Some(config::Tls { cert, key: key.clone(), client_ca: Some(client_ca_path) });
+        let store = Arc::new(MemoryStore::default());
+        let api = API::new(&config, store.clone(), store, Keys::generate().unwrap()).unwrap();
+        let tls = Arc::new(Tls::load(config.tls.as_ref().unwrap()).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(Server::builder()
+            .add_service(pb2::auth_server::AuthServer::new(api))
+            .serve_with_incoming(tls.clone().incoming(listener)));
+        (port, tls, server_ca, key, client)
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let dir = std::env::temp_dir().join(format!("chaum-pedersen-auth-{}", Uuid::new_v4()));
+        let (port, tls, server_ca, key, (client_cert, client_key)) = serve(&dir).await;
+
+        let mut anonymous = connect(port, &server_ca, None).await.unwrap();
+        assert_eq!(anonymous.get_realm(pb2::RealmRequest {}).await.unwrap().into_inner().realm, "chaum-pedersen-auth");
+        let validate = || pb2::ValidateSessionRequest { session_id: "invalid".to_string() };
+        assert_eq!(anonymous.validate_session(validate()).await.unwrap_err().code(), Code::PermissionDenied);
+        let mut authenticated = connect(port, &server_ca, Some((&client_cert, &client_key))).await.unwrap();
+        assert_eq!(authenticated.validate_session(validate()).await.unwrap_err().code(), Code::Unauthenticated);
+        let unlock = || pb2::UnlockUserRequest { user: "alice".to_string() };
+        assert_eq!(anonymous.unlock_user(unlock()).await.unwrap_err().code(), Code::PermissionDenied);
+        assert_eq!(authenticated.unlock_user(unlock()).await.unwrap_err().code(), Code::NotFound);
+        let (other_cert, other_key) = issue(&dir, "other", &ca());
+        let rejected = match connect(port, &server_ca, Some((&other_cert, &other_key))).await {
+            Ok(mut client) => client.get_realm(pb2::RealmRequest {}).await.is_err(),
+            Err(_) => true,
+        };
+        assert!(rejected);
+
+        let rotated_ca = ca();
+        issue(&dir, "localhost", &rotated_ca);
+        tls.reload().unwrap();
+        assert!(anonymous.get_realm(pb2::RealmRequest {}).await.is_ok());
+        assert!(connect(port, &server_ca, None).await.is_err());
+        assert!(connect(port, &rotated_ca, None).await.unwrap().get_realm(pb2::RealmRequest {}).await.is_ok());
+
+        std::fs::write(key, "").unwrap();
+        assert!(tls.reload().is_err());
+        assert!(connect(port, &rotated_ca, None).await.is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unlock() {
+        let dir = std::env::temp_dir().join(format!("chaum-pedersen-auth-{}", Uuid::new_v4()));
+        let (port, _, server_ca, _, (client_cert, client_key)) = serve(&dir).await;
+        let mut client = connect(port, &server_ca, Some((&client_cert, &client_key))).await.unwrap();
+        let (protocol, _) = config::Config::parse(include_str!("../config.example.toml")).unwrap().protocols().unwrap();
+        let (y1, y2) = protocol.register(REALM, "alice", "password");
+        let (r1, r2, s) = protocol.prove_registration(REALM, "alice", "password");
+        client.register(pb2::RegisterRequest {
+            user: "alice".to_string(),
+            y1: y1.to_bytes_be(),
+            y2: y2.to_bytes_be(),
+            factors: Vec::new(),
+            r1: r1.to_bytes_be(),
+            r2: r2.to_bytes_be(),
+            s: s.to_bytes_be(),
+        }).await.unwrap();
+        let login = |password: &'static str| {
+            let (protocol, mut client) = (protocol.clone(), client.clone());
+            async move {
+                let (k, r1, r2) = protocol.commit();
+                let challenge = client.create_authentication_challenge(pb2::AuthenticationChallengeRequest {
+                    user: "alice".to_string(),
+                    r1: r1.to_bytes_be(),
+                    r2: r2.to_bytes_be(),
+                }).await?.into_inner();
+                let s = protocol.solve(REALM, "alice", password, &k, &BigUint::from_bytes_be(&challenge.c));
+                client.verify_authentication(pb2::AuthenticationAnswerRequest { auth_id: challenge.auth_id, s: s.to_bytes_be() }).await
+            }
+        };
+        for _ in 0..3 {
+            assert_eq!(login("wrong").await.unwrap_err().code(), Code::Unauthenticated);
+        }
+        let status = login("password").await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(status.metadata().get("retry-after").is_some());
+
+        let unlocked = client.clone().unlock_user(pb2::UnlockUserRequest { user: "alice".to_string() }).await.unwrap().into_inner();
+        assert!(unlocked.locked);
+        login("password").await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
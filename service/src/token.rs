@@ -0,0 +1,135 @@
+//! Issuing session tokens: JWTs signed with Ed25519 (`alg` `EdDSA`), naming the signing key in the
+//! `kid` header so that keys can be rotated while tokens signed with older keys are still accepted.
+
+use std::{collections::BTreeMap, path::Path};
+
+use ed25519_dalek::{pkcs8::{DecodePrivateKey, EncodePrivateKey}, SigningKey};
+use eyre::{eyre, Result, WrapErr};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use middleware::{Claims, VerifyingKeys};
+use uuid::Uuid;
+
+
+/// Keys by id, the first one given signing new tokens and all of them verifying.
+pub struct Keys {
+    signing: String,
+    encoding: BTreeMap<String, EncodingKey>,
+    verifying: VerifyingKeys,
+}
+
+impl Keys {
+    fn new(keys: &[(String, SigningKey)]) -> Result<Self> {
+        let (signing, _) = keys.first().ok_or_else(|| eyre!("no signing key"))?;
+        let mut encoding = BTreeMap::new();
+        let mut verifying = VerifyingKeys::new();
+        for (kid, key) in keys {
+            if !verifying.insert(kid.clone(), key.verifying_key().to_bytes()) {
+                return Err(eyre!("signing key id {} is given twice", kid));
+            }
+            encoding.insert(kid.clone(), EncodingKey::from_ed_der(key.to_pkcs8_der()?.as_bytes()));
+        }
+        Ok(Self { signing: signing.clone(), encoding, verifying })
+    }
+
+    /// Loads PKCS#8 PEM Ed25519 keys, as written by `openssl genpkey -algorithm ed25519`.
+    pub fn load<P: AsRef<Path>>(paths: &[(String, P)]) -> Result<Self> {
+        let keys = paths.iter().map(|(kid, path)| {
+            let pem = std::fs::read_to_string(path.as_ref()).wrap_err_with(|| format!("cannot read signing key {}", kid))?;
+            let key = SigningKey::from_pkcs8_pem(&pem).map_err(|error| eyre!("invalid signing key {}: {}", kid, error))?;
+            Ok((kid.clone(), key))
+        }).collect::<Result<Vec<_>>>()?;
+        Self::new(&keys)
+    }
+
+    /// A fresh key with a random id, so tokens do not outlive the process.
+    pub fn generate() -> Result<Self> {
+        Self::new(&[(Uuid::new_v4().to_string(), SigningKey::generate(&mut rand::rngs::OsRng))])
+    }
+
+    pub fn issue(&self, claims: &Claims) -> Result<String> {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(self.signing.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &self.encoding[&self.signing])?)
+    }
+
+    /// Claims of `token` if it is signed by one of the keys and has not expired.
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        self.verifying.verify(token)
+    }
+
+    /// Ids and raw public keys of all keys, for verifying tokens elsewhere.
+    pub fn public_keys(&self) -> impl Iterator<Item = (&str, &[u8; 32])> {
+        self.verifying.public_keys()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use ed25519_dalek::pkcs8::{spki::der::pem::LineEnding, EncodePrivateKey};
+
+    use super::*;
+
+    fn claims(exp: u64) -> Claims {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        Claims {
+            iss: "chaum-pedersen-auth".to_string(),
+            sub: "alice".to_string(),
+            ciphersuite: "CP-K256-SHA256-SHA256".to_string(),
+            iat: now,
+            exp: now + exp,
+            jti: Uuid::new_v4(),
+        }
+    }
+
+    fn write_key(dir: &Path, kid: &str) -> std::path::PathBuf {
+        let path = dir.join(format!("{}.pem", kid));
+        let key = SigningKey::generate(&mut rand::rngs::OsRng);
+        std::fs::write(&path, key.to_pkcs8_pem(LineEnding::LF).unwrap().as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_issue() {
+        let keys = Keys::generate().unwrap();
+        let claims = claims(60);
+        let token = keys.issue(&claims).unwrap();
+        assert_eq!(keys.verify(&token), Some(claims.clone()));
+        assert_eq!(Keys::generate().unwrap().verify(&token), None);
+
+        let mut tampered = token.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_eq!(keys.verify(std::str::from_utf8(&tampered).unwrap()), None);
+    }
+
+    #[test]
+    fn test_expired() {
+        let keys = Keys::generate().unwrap();
+        let claims = Claims { exp: claims(0).iat - 1, ..claims(0) };
+        assert_eq!(keys.verify(&keys.issue(&claims).unwrap()), None);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("chaum-pedersen-auth-{}", Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let old = ("2023-09".to_string(), write_key(&dir, "old"));
+        let new = ("2023-10".to_string(), write_key(&dir, "new"));
+
+        let before = Keys::load(std::slice::from_ref(&old)).unwrap();
+        let token = before.issue(&claims(60)).unwrap();
+        let after = Keys::load(&[new, old.clone()]).unwrap();
+        assert!(after.verify(&token).is_some());
+        let token = after.issue(&claims(60)).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid.as_deref(), Some("2023-10"));
+        assert!(before.verify(&token).is_none());
+        assert_eq!(after.public_keys().map(|(kid, _)| kid).collect::<Vec<_>>(), ["2023-09", "2023-10"]);
+
+        assert!(Keys::load(&[old.clone(), old]).is_err());
+        assert!(Keys::load::<&Path>(&[]).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
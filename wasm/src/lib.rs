@@ -0,0 +1,212 @@
+//! JavaScript api for the prover, built with `wasm-bindgen`.
+//!
+//! Values use the encodings of `zkp_auth.proto`: exponentiation values are unsigned big endian
+//! bytes, k256 scalars are 32 byte big endian and k256 points are `Point` messages with an `x`
+//! coordinate and an `isYOdd` flag, so they can be put into gRPC-web requests as they are.
+
+use k256::{
+    AffinePoint,
+    Scalar,
+    elliptic_curve::{point::AffineCoordinates, PrimeField},
+};
+use num_bigint::BigUint;
+use wasm_bindgen::prelude::*;
+
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ExpRegistration {
+    pub y1: Vec<u8>,
+    pub y2: Vec<u8>,
+}
+
+/// Proof of possession to send along the registration.
+#[wasm_bindgen(getter_with_clone)]
+pub struct ExpRegistrationProof {
+    pub r1: Vec<u8>,
+    pub r2: Vec<u8>,
+    pub s: Vec<u8>,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct ExpCommitment {
+    pub k: Vec<u8>,
+    pub r1: Vec<u8>,
+    pub r2: Vec<u8>,
+}
+
+
+#[wasm_bindgen]
+pub struct ChaumPedersen(protocol::ChaumPedersen);
+
+#[wasm_bindgen]
+impl ChaumPedersen {
+    /// Takes the group parameters as decimal strings, the same way the service reads them from
+    /// its environment.
+    #[wasm_bindgen(constructor)]
+    pub fn new(p: &str, q: &str, g: &str, h: &str) -> Result<ChaumPedersen, JsError> {
+        let parse = |name: &str, value: &str| value.parse::<BigUint>()
+            .map_err(|_| JsError::new(&format!("{} is not an integer", name)));
+        Ok(Self(protocol::ChaumPedersen::new(parse("p", p)?, parse("q", q)?, parse("g", g)?, parse("h", h)?)))
+    }
+
+    pub fn register(&self, realm: &str, user: &str, password: &str) -> ExpRegistration {
+        let (y1, y2) = self.0.register(realm, user, password);
+        ExpRegistration { y1: y1.to_bytes_be(), y2: y2.to_bytes_be() }
+    }
+
+    #[wasm_bindgen(js_name = proveRegistration)]
+    pub fn prove_registration(&self, realm: &str, user: &str, password: &str) -> ExpRegistrationProof {
+        let (r1, r2, s) = self.0.prove_registration(realm, user, password);
+        ExpRegistrationProof { r1: r1.to_bytes_be(), r2: r2.to_bytes_be(), s: s.to_bytes_be() }
+    }
+
+    pub fn commit(&self) -> ExpCommitment {
+        let (k, r1, r2) = self.0.commit();
+        ExpCommitment { k: k.to_bytes_be(), r1: r1.to_bytes_be(), r2: r2.to_bytes_be() }
+    }
+
+    pub fn solve(&self, realm: &str, user: &str, password: &str, k: &[u8], c: &[u8]) -> Vec<u8> {
+        self.0.solve(realm, user, password, &BigUint::from_bytes_be(k), &BigUint::from_bytes_be(c)).to_bytes_be()
+    }
+}
+
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone)]
+pub struct Point {
+    pub x: Vec<u8>,
+    #[wasm_bindgen(js_name = isYOdd)]
+    pub is_y_odd: bool,
+}
+
+impl From<AffinePoint> for Point {
+    fn from(point: AffinePoint) -> Self {
+        Self { x: point.x().to_vec(), is_y_odd: point.y_is_odd().into() }
+    }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct K256Registration {
+    pub y1: Point,
+    pub y2: Point,
+}
+
+/// Proof of possession to send along the registration.
+#[wasm_bindgen(getter_with_clone)]
+pub struct K256RegistrationProof {
+    pub r1: Point,
+    pub r2: Point,
+    pub s: Vec<u8>,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct K256Commitment {
+    pub k: Vec<u8>,
+    pub r1: Point,
+    pub r2: Point,
+}
+
+
+fn scalar(name: &str, bytes: &[u8]) -> Result<Scalar, JsError> {
+    let bytes: [u8; 32] = bytes.try_into().map_err(|_| JsError::new(&format!("{} must be 32 bytes", name)))?;
+    Option::from(Scalar::from_repr(bytes.into())).ok_or_else(|| JsError::new(&format!("{} is not a valid scalar", name)))
+}
+
+
+#[wasm_bindgen]
+pub struct ChaumPedersenK256(protocol::ChaumPedersenK256);
+
+#[wasm_bindgen]
+impl ChaumPedersenK256 {
+    /// Takes `K256_H_OFFSET` as a decimal string, as it does not fit into a JavaScript number.
+    #[wasm_bindgen(constructor)]
+    pub fn new(h_offset: &str) -> Result<ChaumPedersenK256, JsError> {
+        let h_offset = h_offset.parse().map_err(|_| JsError::new("h_offset is not an integer"))?;
+        Ok(Self(protocol::ChaumPedersenK256::new(h_offset)))
+    }
+
+    pub fn register(&self, realm: &str, user: &str, password: &str) -> K256Registration {
+        let (y1, y2) = self.0.register(realm, user, password);
+        K256Registration { y1: y1.into(), y2: y2.into() }
+    }
+
+    #[wasm_bindgen(js_name = proveRegistration)]
+    pub fn prove_registration(&self, realm: &str, user: &str, password: &str) -> K256RegistrationProof {
+        let (r1, r2, s) = self.0.prove_registration(realm, user, password);
+        K256RegistrationProof { r1: r1.into(), r2: r2.into(), s: s.to_repr().to_vec() }
+    }
+
+    pub fn commit(&self) -> K256Commitment {
+        let (k, r1, r2) = self.0.commit();
+        K256Commitment { k: k.to_repr().to_vec(), r1: r1.into(), r2: r2.into() }
+    }
+
+    pub fn solve(&self, realm: &str, user: &str, password: &str, k: &[u8], c: &[u8]) -> Result<Vec<u8>, JsError> {
+        Ok(self.0.solve(realm, user, password, &scalar("k", k)?, &scalar("c", c)?).to_repr().to_vec())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::{point::DecompressPoint, subtle::Choice};
+
+    use super::*;
+
+    #[test]
+    fn test_exp_round_trip() {
+        let protocol = ChaumPedersen::new("363967321904221003", "7696033", "165950041202038920", "96429580695728554").ok().unwrap();
+        let registration = protocol.register("chaum-pedersen-auth", "testuser", "password");
+        assert_eq!(BigUint::from_bytes_be(&registration.y1), BigUint::from(348596868091682774u64));
+        assert_eq!(BigUint::from_bytes_be(&registration.y2), BigUint::from(289175850390861676u64));
+
+        let commitment = protocol.commit();
+        let c = protocol.0.challenge();
+        let s = protocol.solve("chaum-pedersen-auth", "testuser", "password", &commitment.k, &c.to_bytes_be());
+        assert!(protocol.0.verify(
+            &BigUint::from_bytes_be(&registration.y1),
+            &BigUint::from_bytes_be(&registration.y2),
+            &BigUint::from_bytes_be(&commitment.r1),
+            &BigUint::from_bytes_be(&commitment.r2),
+            &c,
+            &BigUint::from_bytes_be(&s),
+        ));
+
+        let proof = protocol.prove_registration("chaum-pedersen-auth", "testuser", "password");
+        assert!(protocol.0.verify_registration(
+            "chaum-pedersen-auth",
+            "testuser",
+            &BigUint::from_bytes_be(&registration.y1),
+            &BigUint::from_bytes_be(&registration.y2),
+            &(BigUint::from_bytes_be(&proof.r1), BigUint::from_bytes_be(&proof.r2), BigUint::from_bytes_be(&proof.s)),
+        ));
+    }
+
+    #[test]
+    fn test_k256_round_trip() {
+        let point = |point: &Point| AffinePoint::decompress(point.x.as_slice().into(), Choice::from(point.is_y_odd as u8)).unwrap();
+
+        let protocol = ChaumPedersenK256::new("107211496160805127").ok().unwrap();
+        let registration = protocol.register("chaum-pedersen-auth", "testuser", "password");
+        let commitment = protocol.commit();
+        let c = protocol.0.challenge();
+        let s = protocol.solve("chaum-pedersen-auth", "testuser", "password", &commitment.k, &c.to_repr()).ok().unwrap();
+        assert!(protocol.0.verify(
+            &point(&registration.y1),
+            &point(&registration.y2),
+            &point(&commitment.r1),
+            &point(&commitment.r2),
+            &c,
+            &Scalar::from_repr(<[u8; 32]>::try_from(s).unwrap().into()).unwrap(),
+        ));
+
+        let proof = protocol.prove_registration("chaum-pedersen-auth", "testuser", "password");
+        assert!(protocol.0.verify_registration(
+            "chaum-pedersen-auth",
+            "testuser",
+            &point(&registration.y1),
+            &point(&registration.y2),
+            &(point(&proof.r1), point(&proof.r2), scalar("s", &proof.s).ok().unwrap()),
+        ));
+    }
+}